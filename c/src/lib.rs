@@ -776,7 +776,7 @@ pub unsafe extern "C" fn PFBuildOptionsSetDilation(options: PFBuildOptionsRef,
 #[no_mangle]
 pub unsafe extern "C" fn PFBuildOptionsSetSubpixelAAEnabled(options: PFBuildOptionsRef,
                                                             subpixel_aa_enabled: bool) {
-    (*options).subpixel_aa_enabled = subpixel_aa_enabled
+    (*options).subpixel_aa = subpixel_aa_enabled.into()
 }
 
 #[no_mangle]
@@ -954,6 +954,7 @@ impl PFRendererOptions {
                 },
                 dest: *Box::from_raw(self.dest as *mut DestFramebuffer<D>),
                 show_debug_ui,
+                ..RendererOptions::default()
             }
         }
     }
@@ -15,6 +15,7 @@ use std::ops::{Add, Mul, Deref};
 ///
 /// The entries are stored in 5 columns of F32x4, each containing a row.
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ColorMatrix(pub [F32x4; 5]);
 
 impl ColorMatrix {
@@ -0,0 +1,466 @@
+// pathfinder/content/src/boolean.rs
+//
+// Copyright © 2020 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Boolean operations on the areas that outlines fill.
+
+use crate::fill::FillRule;
+use crate::outline::{Contour, ContourIterFlags, Outline};
+use crate::segment::Segment;
+use pathfinder_geometry::line_segment::LineSegment2F;
+use pathfinder_geometry::vector::{Vector2F, vec2f};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+// Tolerances smaller than this are raised to it, since merging points any closer than this would
+// be at the mercy of floating point error.
+const MIN_TOLERANCE: f32 = 0.0001;
+
+/// A way of combining the areas filled by two outlines.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BooleanOp {
+    /// The area filled by either outline.
+    Union,
+    /// The area filled by both outlines.
+    Intersection,
+    /// The area filled by the first outline but not the second.
+    Difference,
+    /// The area filled by exactly one of the outlines.
+    Xor,
+}
+
+impl BooleanOp {
+    #[inline]
+    fn includes(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            BooleanOp::Union => in_a || in_b,
+            BooleanOp::Intersection => in_a && in_b,
+            BooleanOp::Difference => in_a && !in_b,
+            BooleanOp::Xor => in_a != in_b,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operand {
+    A,
+    B,
+}
+
+// An edge of one of the operands, between two merged vertices.
+#[derive(Clone, Copy)]
+struct Edge {
+    from: u32,
+    to: u32,
+    operand: Operand,
+}
+
+pub(crate) fn combine(a: &Outline,
+                      b: &Outline,
+                      op: BooleanOp,
+                      fill_rule: FillRule,
+                      tolerance: f32)
+                      -> Outline {
+    let tolerance = f32::max(tolerance, MIN_TOLERANCE);
+
+    let mut line_segments = vec![];
+    flatten_outline(a, Operand::A, tolerance, &mut line_segments);
+    flatten_outline(b, Operand::B, tolerance, &mut line_segments);
+
+    let mut vertices = VertexPool::new(tolerance);
+    let edges = split_line_segments(&line_segments, tolerance, &mut vertices);
+
+    // Edges that the operands share, or that one operand traces back over, only need to be
+    // classified once.
+    let mut classified_edges = HashSet::new();
+    let mut boundary_edges: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut boundary_edge_count = 0;
+    for edge in &edges {
+        let key = (edge.from.min(edge.to), edge.from.max(edge.to));
+        if !classified_edges.insert(key) {
+            continue;
+        }
+
+        // Test a point just to either side of the middle of the edge. Vertices and edges closer
+        // than the tolerance have been merged, so nothing else lies between these points.
+        let (from, to) = (vertices.points[edge.from as usize], vertices.points[edge.to as usize]);
+        let vector = to - from;
+        let offset = vec2f(-vector.y(), vector.x()).normalize() * (tolerance * 0.25);
+        let midpoint = from.lerp(to, 0.5);
+        let is_inside = |point: Vector2F| {
+            let (winding_a, winding_b) = winding_numbers(&edges, &vertices.points, point);
+            op.includes(fill_rule.is_inside(winding_a), fill_rule.is_inside(winding_b))
+        };
+        let (inside_right, inside_left) = (is_inside(midpoint + offset),
+                                           is_inside(midpoint - offset));
+
+        // Keep the result on the right of each edge, so that outer contours are clockwise with Y
+        // down.
+        let (from, to) = match (inside_left, inside_right) {
+            (false, true) => (edge.from, edge.to),
+            (true, false) => (edge.to, edge.from),
+            _ => continue,
+        };
+        boundary_edges.entry(from).or_default().push(to);
+        boundary_edge_count += 1;
+    }
+
+    link_boundary_edges(boundary_edges, boundary_edge_count, &vertices.points)
+}
+
+fn flatten_outline(outline: &Outline,
+                   operand: Operand,
+                   tolerance: f32,
+                   line_segments: &mut Vec<(LineSegment2F, Operand)>) {
+    for contour in outline.contours() {
+        for segment in contour.iter(ContourIterFlags::empty()) {
+            flatten_segment(&segment, tolerance, &mut |line_segment| {
+                line_segments.push((line_segment, operand))
+            });
+        }
+
+        // Open contours are filled as though they were closed.
+        if let (false, Some(first), Some(last)) = (contour.is_closed(),
+                                                   contour.first_position(),
+                                                   contour.last_position()) {
+            line_segments.push((LineSegment2F::new(last, first), operand));
+        }
+    }
+}
+
+fn flatten_segment<F>(segment: &Segment, tolerance: f32, process_line_segment: &mut F)
+                      where F: FnMut(LineSegment2F) {
+    if segment.is_quadratic() {
+        return flatten_segment(&segment.to_cubic(), tolerance, process_line_segment);
+    }
+    if segment.is_line() || segment.as_cubic_segment().is_flat(tolerance) {
+        return process_line_segment(segment.baseline);
+    }
+
+    let (prev, next) = segment.split(0.5);
+    flatten_segment(&prev, tolerance, process_line_segment);
+    flatten_segment(&next, tolerance, process_line_segment);
+}
+
+// Splits each line segment wherever another crosses it or has an endpoint within the tolerance of
+// it, and merges the resulting vertices. Nearly coincident edges thereby become the same edge.
+fn split_line_segments(line_segments: &[(LineSegment2F, Operand)],
+                       tolerance: f32,
+                       vertices: &mut VertexPool)
+                       -> Vec<Edge> {
+    let mut splits: Vec<Vec<(f32, Vector2F)>> = vec![vec![]; line_segments.len()];
+
+    // Only line segments whose horizontal extents overlap can meet, so sweep across them from left
+    // to right.
+    let mut sorted_indices: Vec<usize> = (0..line_segments.len()).collect();
+    sorted_indices.sort_by(|&index_a, &index_b| {
+        let (min_x_a, min_x_b) = (line_segments[index_a].0.min_x(),
+                                  line_segments[index_b].0.min_x());
+        min_x_a.partial_cmp(&min_x_b).unwrap_or(Ordering::Equal)
+    });
+    for (sorted_index, &index_a) in sorted_indices.iter().enumerate() {
+        let line_segment_a = line_segments[index_a].0;
+        for &index_b in &sorted_indices[(sorted_index + 1)..] {
+            let line_segment_b = line_segments[index_b].0;
+            if line_segment_b.min_x() > line_segment_a.max_x() + tolerance {
+                break;
+            }
+            if line_segment_b.min_y() > line_segment_a.max_y() + tolerance ||
+                    line_segment_a.min_y() > line_segment_b.max_y() + tolerance {
+                continue;
+            }
+
+            if let Some((t_a, t_b, point)) = crossing(line_segment_a, line_segment_b) {
+                splits[index_a].push((t_a, point));
+                splits[index_b].push((t_b, point));
+            }
+            for &endpoint in &[line_segment_b.from(), line_segment_b.to()] {
+                if let Some(t) = nearby_point(line_segment_a, endpoint, tolerance) {
+                    splits[index_a].push((t, endpoint));
+                }
+            }
+            for &endpoint in &[line_segment_a.from(), line_segment_a.to()] {
+                if let Some(t) = nearby_point(line_segment_b, endpoint, tolerance) {
+                    splits[index_b].push((t, endpoint));
+                }
+            }
+        }
+    }
+
+    let mut edges = vec![];
+    for (&(line_segment, operand), mut splits) in line_segments.iter().zip(splits) {
+        splits.sort_by(|&(t_a, _), &(t_b, _)| t_a.partial_cmp(&t_b).unwrap_or(Ordering::Equal));
+        let mut from = vertices.add(line_segment.from());
+        let split_points = splits.into_iter().map(|(_, point)| point);
+        for point in split_points.chain(Some(line_segment.to())) {
+            let to = vertices.add(point);
+            if to != from {
+                edges.push(Edge { from, to, operand });
+            }
+            from = to;
+        }
+    }
+    edges
+}
+
+// Returns the parameters along each line segment, and the position, of the point where the two
+// cross, if they cross away from their endpoints. Parallel line segments never cross; where they
+// overlap, their endpoints are near each other, so they're split there instead.
+fn crossing(line_segment_a: LineSegment2F, line_segment_b: LineSegment2F)
+            -> Option<(f32, f32, Vector2F)> {
+    let (vector_a, vector_b) = (line_segment_a.vector(), line_segment_b.vector());
+    let denominator = vector_a.det(vector_b);
+    if denominator.abs() <= f32::EPSILON * vector_a.length() * vector_b.length() {
+        return None;
+    }
+    let offset = line_segment_b.from() - line_segment_a.from();
+    let (t_a, t_b) = (offset.det(vector_b) / denominator, offset.det(vector_a) / denominator);
+    if t_a <= 0.0 || t_a >= 1.0 || t_b <= 0.0 || t_b >= 1.0 {
+        return None;
+    }
+    Some((t_a, t_b, line_segment_a.sample(t_a)))
+}
+
+// Returns the parameter along the line segment of the given point, if the point is within the
+// tolerance of the line segment and away from its endpoints.
+fn nearby_point(line_segment: LineSegment2F, point: Vector2F, tolerance: f32) -> Option<f32> {
+    let vector = line_segment.vector();
+    let square_length = vector.square_length();
+    if square_length == 0.0 {
+        return None;
+    }
+    let t = (point - line_segment.from()).dot(vector) / square_length;
+    if t <= 0.0 || t >= 1.0 ||
+            (line_segment.sample(t) - point).square_length() > tolerance * tolerance {
+        return None;
+    }
+    Some(t)
+}
+
+// Returns the winding numbers of the given point with respect to each operand.
+fn winding_numbers(edges: &[Edge], points: &[Vector2F], point: Vector2F) -> (i32, i32) {
+    let (mut winding_a, mut winding_b) = (0, 0);
+    for edge in edges {
+        let (from, to) = (points[edge.from as usize], points[edge.to as usize]);
+        let side = (to - from).det(point - from);
+        let winding = if from.y() <= point.y() && to.y() > point.y() && side > 0.0 {
+            1
+        } else if to.y() <= point.y() && from.y() > point.y() && side < 0.0 {
+            -1
+        } else {
+            continue
+        };
+        match edge.operand {
+            Operand::A => winding_a += winding,
+            Operand::B => winding_b += winding,
+        }
+    }
+    (winding_a, winding_b)
+}
+
+// Joins the edges of the result into closed contours. Each vertex has as many boundary edges
+// leaving it as arriving at it, so following them always leads back to the start.
+fn link_boundary_edges(mut boundary_edges: HashMap<u32, Vec<u32>>,
+                       boundary_edge_count: usize,
+                       points: &[Vector2F])
+                       -> Outline {
+    let mut outline = Outline::new();
+    let mut remaining_edge_count = boundary_edge_count;
+    let mut start_vertices: Vec<u32> = boundary_edges.keys().cloned().collect();
+    start_vertices.sort_unstable();
+    for start_vertex in start_vertices {
+        while remaining_edge_count > 0 {
+            let mut contour = Contour::new();
+            let mut vertex = start_vertex;
+            while let Some(next_vertex) = boundary_edges.get_mut(&vertex)
+                                                        .and_then(|to_vertices| to_vertices.pop()) {
+                contour.push_endpoint(points[vertex as usize]);
+                remaining_edge_count -= 1;
+                vertex = next_vertex;
+                if vertex == start_vertex {
+                    break;
+                }
+            }
+
+            // If classification went wrong somewhere and the contour didn't return to its start,
+            // closing it anyway keeps the error local.
+            if contour.is_empty() {
+                break;
+            }
+            contour.close();
+            outline.push_contour(contour);
+        }
+    }
+    outline
+}
+
+// Deduplicates points, merging each point with any earlier point within the tolerance.
+struct VertexPool {
+    points: Vec<Vector2F>,
+    grid: HashMap<(i32, i32), Vec<u32>>,
+    tolerance: f32,
+}
+
+impl VertexPool {
+    fn new(tolerance: f32) -> VertexPool {
+        VertexPool { points: vec![], grid: HashMap::new(), tolerance }
+    }
+
+    fn add(&mut self, point: Vector2F) -> u32 {
+        // The grid cells are as large as the tolerance, so any nearby point is in one of the
+        // neighboring cells.
+        let cell = (point * (1.0 / self.tolerance)).floor();
+        let (cell_x, cell_y) = (cell.x() as i32, cell.y() as i32);
+        for neighbor_y in (cell_y.saturating_sub(1))..=(cell_y.saturating_add(1)) {
+            for neighbor_x in (cell_x.saturating_sub(1))..=(cell_x.saturating_add(1)) {
+                let vertices = match self.grid.get(&(neighbor_x, neighbor_y)) {
+                    None => continue,
+                    Some(vertices) => vertices,
+                };
+                for &vertex in vertices {
+                    let distance = self.points[vertex as usize] - point;
+                    if distance.square_length() <= self.tolerance * self.tolerance {
+                        return vertex;
+                    }
+                }
+            }
+        }
+
+        let vertex = self.points.len() as u32;
+        self.points.push(point);
+        self.grid.entry((cell_x, cell_y)).or_default().push(vertex);
+        vertex
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fill::FillRule;
+    use crate::outline::{Contour, Outline};
+    use pathfinder_geometry::rect::RectF;
+    use pathfinder_geometry::transform2d::Transform2F;
+    use pathfinder_geometry::vector::vec2f;
+    use std::f32::consts::PI;
+
+    use super::BooleanOp;
+
+    const TOLERANCE: f32 = 0.01;
+
+    fn rect(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Outline {
+        Outline::from_rect(RectF::from_points(vec2f(min_x, min_y), vec2f(max_x, max_y)))
+    }
+
+    // Returns the area that the outline fills, counting holes as negative. The result of a boolean
+    // operation consists of line segments only, so the shoelace formula is exact for it.
+    fn area(outline: &Outline) -> f32 {
+        let mut area = 0.0;
+        for contour in outline.contours() {
+            let points = contour.points();
+            for (point_index, &point) in points.iter().enumerate() {
+                let next_point = points[(point_index + 1) % points.len()];
+                area += point.x() * next_point.y() - next_point.x() * point.y();
+            }
+        }
+        area * 0.5
+    }
+
+    // Checks the area and contour count of each operation's result, under both fill rules.
+    fn check_ops(a: &Outline, b: &Outline, expected: &[(BooleanOp, f32, usize)]) {
+        for &fill_rule in &[FillRule::Winding, FillRule::EvenOdd] {
+            for &(op, expected_area, expected_contour_count) in expected {
+                let result = a.boolean_op(b, op, fill_rule, TOLERANCE);
+                assert!((area(&result) - expected_area).abs() < 0.01,
+                        "{:?} ({:?}): area {} != {}",
+                        op,
+                        fill_rule,
+                        area(&result),
+                        expected_area);
+                assert_eq!(result.contours().len(),
+                           expected_contour_count,
+                           "{:?} ({:?})",
+                           op,
+                           fill_rule);
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_rects() {
+        let (a, b) = (rect(0.0, 0.0, 20.0, 20.0), rect(10.0, 10.0, 30.0, 30.0));
+        check_ops(&a, &b, &[
+            (BooleanOp::Union, 700.0, 1),
+            (BooleanOp::Intersection, 100.0, 1),
+            (BooleanOp::Difference, 300.0, 1),
+            (BooleanOp::Xor, 600.0, 2),
+        ]);
+        let union = a.boolean_op(&b, BooleanOp::Union, FillRule::Winding, TOLERANCE);
+        assert_eq!(union.bounds(), RectF::from_points(vec2f(0.0, 0.0), vec2f(30.0, 30.0)));
+    }
+
+    #[test]
+    fn rects_sharing_an_edge() {
+        // The shared edge cancels out, leaving a single contour around both.
+        let (a, b) = (rect(0.0, 0.0, 10.0, 10.0), rect(10.0, 0.0, 20.0, 10.0));
+        check_ops(&a, &b, &[
+            (BooleanOp::Union, 200.0, 1),
+            (BooleanOp::Intersection, 0.0, 0),
+            (BooleanOp::Difference, 100.0, 1),
+            (BooleanOp::Xor, 200.0, 1),
+        ]);
+    }
+
+    #[test]
+    fn identical_rects() {
+        let a = rect(0.0, 0.0, 20.0, 10.0);
+        check_ops(&a, &a.clone(), &[
+            (BooleanOp::Union, 200.0, 1),
+            (BooleanOp::Intersection, 200.0, 1),
+            (BooleanOp::Difference, 0.0, 0),
+            (BooleanOp::Xor, 0.0, 0),
+        ]);
+    }
+
+    #[test]
+    fn disjoint_rects() {
+        let (a, b) = (rect(0.0, 0.0, 10.0, 10.0), rect(20.0, 0.0, 30.0, 10.0));
+        check_ops(&a, &b, &[
+            (BooleanOp::Union, 200.0, 2),
+            (BooleanOp::Intersection, 0.0, 0),
+            (BooleanOp::Difference, 100.0, 1),
+            (BooleanOp::Xor, 200.0, 2),
+        ]);
+    }
+
+    #[test]
+    fn circle_and_rect() {
+        // A circle of radius 10 around the origin, and a rect covering its right half.
+        let mut circle = Contour::new();
+        circle.push_ellipse(&Transform2F::from_scale(10.0));
+        circle.close();
+        let mut a = Outline::new();
+        a.push_contour(circle);
+        let b = rect(0.0, -20.0, 20.0, 20.0);
+
+        // Flattening the circle loses a little area, so compare loosely.
+        let half_circle_area = PI * 100.0 * 0.5;
+        for &(op, expected_area) in &[
+            (BooleanOp::Union, half_circle_area + 800.0),
+            (BooleanOp::Intersection, half_circle_area),
+            (BooleanOp::Difference, half_circle_area),
+            (BooleanOp::Xor, 800.0),
+        ] {
+            let result = a.boolean_op(&b, op, FillRule::Winding, TOLERANCE);
+            assert!((area(&result) - expected_area).abs() < expected_area * 0.005,
+                    "{:?}: area {} != {}",
+                    op,
+                    area(&result),
+                    expected_area);
+        }
+    }
+}
@@ -72,6 +72,13 @@ impl<'a, 'b, 'c> ContourDash<'a, 'b, 'c> {
     }
 
     fn dash(&mut self) {
+        // Don't let a dash bridge the gap from the previous contour.
+        if !self.state.output.is_empty() {
+            self.output.push_contour(mem::replace(&mut self.state.output, Contour::new()));
+        }
+        let first_output_index = self.output.contours.len();
+        let started_on = self.state.is_on();
+
         let mut iterator = self.input.iter(ContourIterFlags::empty());
         let mut queued_segment = None;
         loop {
@@ -112,6 +119,19 @@ impl<'a, 'b, 'c> ContourDash<'a, 'b, 'c> {
                 self.state.distance_left = self.state.dashes[self.state.current_dash_index];
             }
         }
+
+        // If a dash runs across the point where a closed contour starts and ends, join its two
+        // halves, so that the seam isn't capped.
+        if self.input.is_closed() && started_on && self.state.is_on() &&
+                self.output.contours.len() > first_output_index {
+            let first_dash = mem::replace(&mut self.output.contours[first_output_index],
+                                          Contour::new());
+            let mut last_dash = mem::replace(&mut self.state.output, Contour::new());
+            for segment in first_dash.iter(ContourIterFlags::empty()) {
+                last_dash.push_segment(&segment, PushSegmentFlags::empty());
+            }
+            self.output.contours[first_output_index] = last_dash;
+        }
     }
 }
 
@@ -126,6 +146,9 @@ impl<'a> DashState<'a> {
     fn new(dashes: &'a [f32], mut offset: f32) -> DashState<'a> {
         let total: f32 = dashes.iter().cloned().sum();
         offset %= total;
+        if offset < 0.0 {
+            offset += total;
+        }
 
         let mut current_dash_index = 0;
         while current_dash_index < dashes.len() {
@@ -137,11 +160,17 @@ impl<'a> DashState<'a> {
             current_dash_index += 1;
         }
 
+        // Rounding can leave us just past the end of the pattern.
+        if current_dash_index == dashes.len() {
+            current_dash_index = 0;
+            offset = 0.0;
+        }
+
         DashState {
             output: Contour::new(),
             dashes,
             current_dash_index,
-            distance_left: offset,
+            distance_left: dashes[current_dash_index] - offset,
         }
     }
 
@@ -150,3 +179,85 @@ impl<'a> DashState<'a> {
         self.current_dash_index % 2 == 0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::outline::{Contour, ContourIterFlags, Outline};
+    use pathfinder_geometry::rect::RectF;
+    use pathfinder_geometry::vector::vec2f;
+
+    use super::OutlineDash;
+
+    const TOLERANCE: f32 = 0.01;
+
+    fn dash(outline: &Outline, dashes: &[f32], offset: f32) -> Outline {
+        let mut dash = OutlineDash::new(outline, dashes, offset);
+        dash.dash();
+        dash.into_outline()
+    }
+
+    // Returns the horizontal extent of each dash of a horizontal line from x = 0 to x = 100.
+    fn dash_line(dashes: &[f32], offset: f32) -> Vec<(f32, f32)> {
+        let mut contour = Contour::new();
+        contour.push_endpoint(vec2f(0.0, 0.0));
+        contour.push_endpoint(vec2f(100.0, 0.0));
+        let mut line = Outline::new();
+        line.push_contour(contour);
+        // The dasher doesn't update the bounds of the contours it outputs, so use their points.
+        dash(&line, dashes, offset).contours().iter().map(|contour| {
+            let xs = contour.points().iter().map(|point| point.x());
+            (xs.clone().fold(f32::INFINITY, f32::min), xs.fold(f32::NEG_INFINITY, f32::max))
+        }).collect()
+    }
+
+    fn assert_dashes_eq(actual: &[(f32, f32)], expected: &[(f32, f32)]) {
+        assert_eq!(actual.len(), expected.len(), "{:?} != {:?}", actual, expected);
+        for (&(actual_start, actual_end), &(start, end)) in actual.iter().zip(expected) {
+            assert!((actual_start - start).abs() < TOLERANCE &&
+                    (actual_end - end).abs() < TOLERANCE,
+                    "{:?} != {:?}",
+                    actual,
+                    expected);
+        }
+    }
+
+    fn contour_length(contour: &Contour) -> f32 {
+        contour.iter(ContourIterFlags::empty()).map(|segment| segment.arc_length()).sum()
+    }
+
+    #[test]
+    fn dashes_start_at_offset() {
+        assert_dashes_eq(&dash_line(&[30.0, 10.0], 0.0),
+                         &[(0.0, 30.0), (40.0, 70.0), (80.0, 100.0)]);
+        assert_dashes_eq(&dash_line(&[30.0, 10.0], 20.0),
+                         &[(0.0, 10.0), (20.0, 50.0), (60.0, 90.0)]);
+    }
+
+    #[test]
+    fn negative_offset_shifts_pattern_forward() {
+        // An offset of -15 is the same as an offset of 25 into a 40-unit pattern: the first 5
+        // units of the line are in a dash, then a gap follows.
+        let expected = [(0.0, 5.0), (15.0, 45.0), (55.0, 85.0), (95.0, 100.0)];
+        assert_dashes_eq(&dash_line(&[30.0, 10.0], -15.0), &expected);
+        assert_dashes_eq(&dash_line(&[30.0, 10.0], 25.0), &expected);
+    }
+
+    #[test]
+    fn offset_past_pattern_length_wraps() {
+        assert_dashes_eq(&dash_line(&[30.0, 10.0], 40.0), &dash_line(&[30.0, 10.0], 0.0));
+        assert_dashes_eq(&dash_line(&[30.0, 10.0], 100.0), &dash_line(&[30.0, 10.0], 20.0));
+    }
+
+    #[test]
+    fn dash_across_start_of_closed_contour_is_joined() {
+        // The perimeter is 160 units long. With this offset, the last dash runs for 10 units up
+        // to the start of the contour and continues for 20 more after it.
+        let square = Outline::from_rect(RectF::new(vec2f(0.0, 0.0), vec2f(40.0, 40.0)));
+        let dashed = dash(&square, &[30.0, 10.0], 10.0);
+        assert_eq!(dashed.contours().len(), 4);
+        for contour in dashed.contours() {
+            assert!(!contour.is_closed());
+            assert!((contour_length(contour) - 30.0).abs() < TOLERANCE);
+        }
+    }
+}
@@ -41,6 +41,7 @@ pub const MAX_STEM_DARKENING_PIXELS_PER_EM: f32 = 72.0;
 
 /// The shader that should be used when compositing this layer onto its destination.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Filter {
     /// No special filter.
     None,
@@ -61,6 +62,7 @@ pub enum Filter {
 
 /// Shaders applicable to patterns.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PatternFilter {
     /// Performs postprocessing operations useful for monochrome text.
     Text {
@@ -96,6 +98,7 @@ pub enum PatternFilter {
 
 /// Blend modes that can be applied to individual paths.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BlendMode {
     // Porter-Duff, supported by GPU blender
     /// No regions are enabled.
@@ -170,10 +173,12 @@ pub enum BlendMode {
 /// fourth element of this kernel is applied to the center of the pixel, the third element is
 /// applied one pixel to the left, and so on.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DefringingKernel(pub [f32; 4]);
 
 /// The axis a Gaussian blur is applied to.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BlurDirection {
     /// The horizontal axis.
     X,
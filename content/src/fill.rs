@@ -21,3 +21,15 @@ pub enum FillRule {
     /// The even-odd rule: https://en.wikipedia.org/wiki/Even%E2%80%93odd_rule
     EvenOdd,
 }
+
+impl FillRule {
+    /// Returns true if a point with the given winding number is inside a path filled with this
+    /// rule.
+    #[inline]
+    pub fn is_inside(self, winding_number: i32) -> bool {
+        match self {
+            FillRule::Winding => winding_number != 0,
+            FillRule::EvenOdd => winding_number & 1 != 0,
+        }
+    }
+}
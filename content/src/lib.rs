@@ -17,6 +17,7 @@ extern crate bitflags;
 #[macro_use]
 extern crate log;
 
+pub mod boolean;
 pub mod clip;
 pub mod dash;
 pub mod effects;
@@ -10,8 +10,10 @@
 
 //! A compressed in-memory representation of a vector path.
 
+use crate::boolean::{self, BooleanOp};
 use crate::clip::{self, ContourPolygonClipper};
 use crate::dilation::ContourDilator;
+use crate::fill::FillRule;
 use crate::orientation::Orientation;
 use crate::segment::{Segment, SegmentFlags, SegmentKind};
 use crate::util::safe_sqrt;
@@ -25,6 +27,10 @@ use std::f32::consts::PI;
 use std::fmt::{self, Debug, Formatter};
 use std::mem;
 
+// Conics are split in half at most this many times when approximating them with quadratic Bézier
+// curves, so a conic becomes at most 32 quadratics.
+const MAX_CONIC_SUBDIVISION_DEPTH: u32 = 5;
+
 /// A vector path to be filled. Outlines (a.k.a. paths) consist of *contours* (a.k.a. subpaths),
 /// which can be filled according to a fill rule.
 ///
@@ -239,13 +245,30 @@ impl Outline {
 
     /// Thickens the outline by the given amount.
     ///
-    /// This is implemented by pushing vectors out along their normals.
+    /// This is implemented by pushing vectors out along their normals. Negative amounts thin the
+    /// outline instead. A contour that's too thin to survive the thinning would turn inside out
+    /// and fill with inverted coverage, so any contour whose winding flips is removed instead.
     pub fn dilate(&mut self, amount: Vector2F) {
         let orientation = Orientation::from_outline(self);
-        self.contours
-            .iter_mut()
-            .for_each(|contour| contour.dilate(amount, orientation));
-        self.bounds = self.bounds.dilate(amount);
+        if amount.x() >= 0.0 && amount.y() >= 0.0 {
+            self.contours
+                .iter_mut()
+                .for_each(|contour| contour.dilate(amount, orientation));
+            self.bounds = self.bounds.dilate(amount);
+            return;
+        }
+
+        let mut new_bounds = None;
+        for mut contour in mem::replace(&mut self.contours, vec![]) {
+            let area_before = contour.signed_area();
+            contour.dilate(amount, orientation);
+            if area_before * contour.signed_area() <= 0.0 {
+                continue;
+            }
+            contour.update_bounds(&mut new_bounds);
+            self.contours.push(contour);
+        }
+        self.bounds = new_bounds.unwrap_or_else(|| RectF::default());
     }
 
     /// Returns true if this outline is obviously completely outside the closed polygon with the
@@ -274,6 +297,23 @@ impl Outline {
         }
     }
 
+    /// Combines the area that this outline fills with the area that another fills, using the
+    /// given boolean operation, and returns an outline that fills the result.
+    ///
+    /// Both outlines are filled with `fill_rule`. Curves are flattened into lines that stray from
+    /// them by at most `tolerance`, and points within `tolerance` of each other or of an edge are
+    /// merged, so that edges the outlines share, even approximately, cancel out cleanly instead of
+    /// leaving slivers. The result consists of line segments, with outer contours clockwise and
+    /// holes counterclockwise (with Y down), and it fills the same area under either fill rule,
+    /// which also makes this a way to remove self-intersections from an outline.
+    ///
+    /// This takes time quadratic in the number of lines in the worst case, so it's meant for
+    /// precomputing geometry, such as composing icons, rather than for use on every frame.
+    pub fn boolean_op(&self, other: &Outline, op: BooleanOp, fill_rule: FillRule, tolerance: f32)
+                      -> Outline {
+        boolean::combine(self, other, op, fill_rule, tolerance)
+    }
+
     /// Marks all contours as closed.
     #[inline]
     pub fn close_all_contours(&mut self) {
@@ -549,6 +589,50 @@ impl Contour {
         self.push_point(to, PointFlags::empty(), true);
     }
 
+    /// Adds a conic section (a rational quadratic Bézier curve) to the given on-curve position,
+    /// with the given control point and weight, to this contour.
+    ///
+    /// Contours can't store conics directly, so the conic is split in half repeatedly and each
+    /// piece is replaced with the quadratic Bézier curve with the same control points, until the
+    /// pieces are all within `tolerance` of the conic. That error grows with the distance of the
+    /// weight from 1, so heavily weighted conics, which bend sharply around the control point, are
+    /// split more. A weight of 1 is an ordinary quadratic curve, which isn't split at all. The
+    /// conic is split into at most 32 pieces, so with extreme weights or tiny tolerances, the
+    /// pieces may stray further than `tolerance` from it.
+    ///
+    /// `tolerance` is in the units of this contour, so scale it down if the contour will be scaled
+    /// up later. The weight must be positive and finite; otherwise, a line is added instead.
+    pub fn push_conic(&mut self, ctrl: Vector2F, to: Vector2F, weight: f32, tolerance: f32) {
+        if !(weight > 0.0 && weight.is_finite()) {
+            self.push_endpoint(to);
+            return;
+        }
+
+        let from = self.last_position().unwrap_or_default();
+        let depth = conic_subdivision_depth(from, ctrl, to, weight, tolerance);
+        self.push_conic_as_quadratics(from, ctrl, to, weight, depth);
+    }
+
+    fn push_conic_as_quadratics(&mut self,
+                                from: Vector2F,
+                                ctrl: Vector2F,
+                                to: Vector2F,
+                                weight: f32,
+                                depth: u32) {
+        if depth == 0 {
+            self.push_quadratic(ctrl, to);
+            return;
+        }
+
+        // Split the conic in half. Both halves share a new weight.
+        let scale = 1.0 / (1.0 + weight);
+        let (ctrl0, ctrl1) = ((from + ctrl * weight) * scale, (ctrl * weight + to) * scale);
+        let mid = (ctrl0 + ctrl1) * 0.5;
+        let weight = (0.5 + weight * 0.5).sqrt();
+        self.push_conic_as_quadratics(from, ctrl0, mid, weight, depth - 1);
+        self.push_conic_as_quadratics(mid, ctrl1, to, weight, depth - 1);
+    }
+
     /// Marks this contour as closed, which results in an implicit line from the end back to the
     /// starting point.
     #[inline]
@@ -913,7 +997,31 @@ impl Contour {
     /// of the path (clockwise or counterclockwise) and is necessary to avoid flipped normals.
     pub fn dilate(&mut self, amount: Vector2F, orientation: Orientation) {
         ContourDilator::new(self, amount, orientation).dilate();
-        self.bounds = self.bounds.dilate(amount);
+
+        // When thinning, points at concave corners move outward, so shrinking the bounds wouldn't
+        // enclose them. Recompute the bounds from scratch instead.
+        if amount.x() >= 0.0 && amount.y() >= 0.0 {
+            self.bounds = self.bounds.dilate(amount);
+        } else {
+            for (point_index, point) in self.points.iter().enumerate() {
+                union_rect(&mut self.bounds, *point, point_index == 0);
+            }
+        }
+    }
+
+    // Returns twice the signed area of the polygon formed by the points of this contour. The sign
+    // gives the winding.
+    fn signed_area(&self) -> f32 {
+        let mut prev_position = match self.last_position() {
+            None => return 0.0,
+            Some(position) => position,
+        };
+        let mut area = 0.0;
+        for &next_position in &self.points {
+            area += prev_position.det(next_position);
+            prev_position = next_position;
+        }
+        area
     }
 
     // Use this function to keep bounds up to date when mutating paths. See `Outline::transform()`
@@ -1082,6 +1190,29 @@ bitflags! {
     }
 }
 
+// Returns how many times a conic must be split in half for the quadratic curves with the same
+// control points as the pieces to be within `tolerance` of it.
+//
+// The error bound is from Skia's `SkConic::computeQuadPOW2()`. Each split quarters it, so the
+// depth is the base 4 logarithm of the ratio of the error to the tolerance, rounded up and
+// clamped to `MAX_CONIC_SUBDIVISION_DEPTH`. Nonpositive tolerances get the maximum depth.
+fn conic_subdivision_depth(from: Vector2F,
+                           ctrl: Vector2F,
+                           to: Vector2F,
+                           weight: f32,
+                           tolerance: f32)
+                           -> u32 {
+    let a = weight - 1.0;
+    let k = a / (4.0 * (2.0 + a));
+    let error = ((from - ctrl * 2.0 + to) * k).length();
+    if error <= tolerance {
+        return 0;
+    }
+    // A nonpositive tolerance makes this infinite or NaN, both of which `f32::min()` clamps.
+    let depth = ((error / tolerance).log2() * 0.5).ceil();
+    depth.min(MAX_CONIC_SUBDIVISION_DEPTH as f32) as u32
+}
+
 #[inline]
 pub(crate) fn union_rect(bounds: &mut RectF, new_point: Vector2F, first: bool) {
     if first {
@@ -1090,3 +1221,148 @@ pub(crate) fn union_rect(bounds: &mut RectF, new_point: Vector2F, first: bool) {
         *bounds = bounds.union_point(new_point)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::outline::{Contour, ContourIterFlags, Outline};
+    use crate::stroke::{OutlineStrokeToFill, StrokeStyle};
+    use pathfinder_geometry::rect::RectF;
+    use pathfinder_geometry::transform2d::Transform2F;
+    use pathfinder_geometry::vector::{Vector2F, vec2f};
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use super::conic_subdivision_depth;
+
+    // Checks that thinning moved every edge of `outer` inward, but not past the center.
+    fn assert_strictly_inside(inner: RectF, outer: RectF) {
+        assert!(inner.min_x() > outer.min_x() && inner.min_y() > outer.min_y() &&
+                inner.max_x() < outer.max_x() && inner.max_y() < outer.max_y() &&
+                inner.contains_point(outer.center()),
+                "{:?} isn't inside {:?}",
+                inner,
+                outer);
+    }
+
+    #[test]
+    fn eroding_thin_stroke_removes_it() {
+        let mut line = Contour::new();
+        line.push_endpoint(vec2f(10.0, 10.0));
+        line.push_endpoint(vec2f(90.0, 60.0));
+        let mut path = Outline::new();
+        path.push_contour(line);
+        let style = StrokeStyle { line_width: 2.0, ..StrokeStyle::default() };
+        let mut stroke_to_fill = OutlineStrokeToFill::new(&path, style);
+        stroke_to_fill.offset();
+        let mut stroke = stroke_to_fill.into_outline();
+        assert!(!stroke.is_empty());
+
+        // Eroding by more than the half-width would turn the stroke inside out.
+        stroke.dilate(vec2f(-1.5, -1.5));
+        assert!(stroke.is_empty());
+        assert_eq!(stroke.bounds(), RectF::default());
+    }
+
+    #[test]
+    fn eroding_removes_only_features_thinner_than_the_amount() {
+        let thin_rect = RectF::new(vec2f(0.0, 0.0), vec2f(2.0, 50.0));
+        let thick_rect = RectF::new(vec2f(10.0, 0.0), vec2f(20.0, 50.0));
+        let mut outline = Outline::new();
+        outline.push_contour(Contour::from_rect(thin_rect));
+        outline.push_contour(Contour::from_rect(thick_rect));
+        let area_before = outline.contours()[1].signed_area();
+
+        outline.dilate(vec2f(-1.5, -1.5));
+        assert_eq!(outline.contours().len(), 1);
+        let contour = &outline.contours()[0];
+        assert!(contour.signed_area() * area_before > 0.0);
+        assert_strictly_inside(contour.bounds(), thick_rect);
+        assert_eq!(outline.bounds(), contour.bounds());
+    }
+
+    #[test]
+    fn eroding_partway_keeps_orientation() {
+        let rect = RectF::new(vec2f(0.0, 0.0), vec2f(40.0, 20.0));
+        let mut ellipse = Contour::new();
+        ellipse.push_ellipse(&Transform2F::from_scale(vec2f(15.0, 10.0))
+                                          .translate(vec2f(100.0, 50.0)));
+        ellipse.close();
+
+        for contour in &[Contour::from_rect(rect), ellipse] {
+            let bounds_before = contour.bounds();
+            let area_before = contour.signed_area();
+            let mut outline = Outline::new();
+            outline.push_contour(contour.clone());
+
+            outline.dilate(vec2f(-3.0, -2.0));
+            assert_eq!(outline.contours().len(), 1);
+            let area_after = outline.contours()[0].signed_area();
+            assert!(area_after * area_before > 0.0);
+            assert!(area_after.abs() < area_before.abs());
+            assert_strictly_inside(outline.bounds(), bounds_before);
+        }
+    }
+
+    fn conic(ctrl: Vector2F, to: Vector2F, weight: f32, tolerance: f32) -> Contour {
+        let mut contour = Contour::new();
+        contour.push_endpoint(vec2f(0.0, 0.0));
+        contour.push_conic(ctrl, to, weight, tolerance);
+        contour
+    }
+
+    #[test]
+    fn conic_with_unit_weight_is_one_quadratic() {
+        let contour = conic(vec2f(50.0, 100.0), vec2f(100.0, 0.0), 1.0, 0.01);
+        assert_eq!(contour.points(), &[vec2f(0.0, 0.0), vec2f(50.0, 100.0), vec2f(100.0, 0.0)]);
+        assert!(!contour.point_is_endpoint(1));
+    }
+
+    #[test]
+    fn conic_quarter_circle_stays_on_circle() {
+        // A quarter circle of radius 100 around (0, 100), from (0, 0) to (100, 100).
+        let (center, radius, tolerance) = (vec2f(0.0, 100.0), 100.0, 0.01);
+        let contour = conic(vec2f(100.0, 0.0), vec2f(100.0, 100.0), FRAC_1_SQRT_2, tolerance);
+        assert!(contour.points().len() > 3);
+        assert_eq!(contour.last_position(), Some(vec2f(100.0, 100.0)));
+        for segment in contour.iter(ContourIterFlags::IGNORE_CLOSE_SEGMENT) {
+            assert!(segment.is_quadratic());
+            for step in 0..=16 {
+                let point = segment.sample(step as f32 / 16.0);
+                let distance = (point - center).length();
+                assert!((distance - radius).abs() <= tolerance,
+                        "{:?} is {} from the center",
+                        point,
+                        distance);
+            }
+        }
+    }
+
+    #[test]
+    fn conic_with_invalid_weight_is_line() {
+        for &weight in &[0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let contour = conic(vec2f(50.0, 100.0), vec2f(100.0, 0.0), weight, 0.01);
+            assert_eq!(contour.points(), &[vec2f(0.0, 0.0), vec2f(100.0, 0.0)]);
+        }
+    }
+
+    #[test]
+    fn conic_with_large_weight_is_split_at_most_32_times() {
+        let contour = conic(vec2f(50.0, 100.0), vec2f(100.0, 0.0), 1.0e6, 0.01);
+        let quadratic_count = contour.iter(ContourIterFlags::IGNORE_CLOSE_SEGMENT).count();
+        assert_eq!(quadratic_count, 32);
+        assert!(contour.points()
+                       .iter()
+                       .all(|point| point.x().is_finite() && point.y().is_finite()));
+    }
+
+    #[test]
+    fn conic_subdivision_depth_follows_error_bound() {
+        // The error bound of this conic is 25, and each split quarters it.
+        let (from, ctrl, to) = (vec2f(0.0, 0.0), vec2f(50.0, 100.0), vec2f(100.0, 0.0));
+        assert_eq!(conic_subdivision_depth(from, ctrl, to, 3.0, 25.0), 0);
+        assert_eq!(conic_subdivision_depth(from, ctrl, to, 3.0, 24.9), 1);
+        assert_eq!(conic_subdivision_depth(from, ctrl, to, 3.0, 6.25), 1);
+        assert_eq!(conic_subdivision_depth(from, ctrl, to, 3.0, 6.2), 2);
+        assert_eq!(conic_subdivision_depth(from, ctrl, to, 3.0, 0.0), 5);
+        assert_eq!(conic_subdivision_depth(from, ctrl, to, 3.0, -1.0), 5);
+    }
+}
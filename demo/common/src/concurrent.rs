@@ -42,4 +42,14 @@ impl Executor for DemoExecutor {
             RayonExecutor.build_vector(length, builder)
         }
     }
+
+    fn build_vector_with_min_len<T, F>(&self, length: usize, min_len: usize, builder: F)
+                                       -> Vec<T>
+                                       where T: Send, F: Fn(usize) -> T + Send + Sync {
+        if self.sequential_mode {
+            SequentialExecutor.build_vector(length, builder)
+        } else {
+            RayonExecutor.build_vector_with_min_len(length, min_len, builder)
+        }
+    }
 }
@@ -161,6 +161,7 @@ impl<W> DemoApp<W> where W: Window {
             dest: dest_framebuffer,
             background_color: None,
             show_debug_ui: true,
+            ..RendererOptions::default()
         };
 
         let filter = build_filter(&ui_model);
@@ -276,7 +277,8 @@ impl<W> DemoApp<W> where W: Window {
             } else {
                 Vector2F::zero()
             },
-            subpixel_aa_enabled: self.ui_model.subpixel_aa_effect_enabled,
+            subpixel_aa: self.ui_model.subpixel_aa_effect_enabled.into(),
+            ..BuildOptions::default()
         };
 
         self.scene_proxy.build(build_options);
@@ -78,6 +78,7 @@ impl<W> DemoApp<W> where W: Window {
                     dest: DestFramebuffer::Other(self.scene_framebuffer.take().unwrap()),
                     background_color: clear_color,
                     show_debug_ui: self.options.ui != UIVisibility::None,
+                    ..*self.renderer.options()
                 };
                 2
             }
@@ -89,6 +90,7 @@ impl<W> DemoApp<W> where W: Window {
                     },
                     background_color: clear_color,
                     show_debug_ui: self.options.ui != UIVisibility::None,
+                    ..*self.renderer.options()
                 };
                 1
             }
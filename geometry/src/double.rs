@@ -0,0 +1,237 @@
+// pathfinder/geometry/src/double.rs
+//
+// Copyright © 2020 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Double-precision points and transforms for scenes with very large coordinates.
+//!
+//! Outlines are stored and tiled in single precision, which loses subpixel accuracy once
+//! coordinates reach the millions. To avoid that, keep world coordinates in these types, store
+//! each outline relative to a nearby *anchor* point with `Vector2F64::to_f32_relative_to()`, and
+//! give the path the transform returned by `Transform2F64::to_f32_at()` for that anchor. The large
+//! offsets then cancel out in double precision, and only small device-space values are ever
+//! rounded to single precision.
+
+use crate::transform2d::{Matrix2x2F, Transform2F};
+use crate::vector::{Vector2F, vec2f};
+use std::ops::{Add, Mul, MulAssign, Neg, Sub};
+
+/// 2D points with 64-bit floating point coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vector2F64 {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Vector2F64 {
+    #[inline]
+    pub fn new(x: f64, y: f64) -> Vector2F64 {
+        Vector2F64 { x, y }
+    }
+
+    #[inline]
+    pub fn zero() -> Vector2F64 {
+        Vector2F64::default()
+    }
+
+    /// Rounds this point to single precision.
+    #[inline]
+    pub fn to_f32(self) -> Vector2F {
+        vec2f(self.x as f32, self.y as f32)
+    }
+
+    /// Returns the offset of this point from `anchor`, rounded to single precision.
+    ///
+    /// The subtraction happens in double precision, so the result is accurate as long as the
+    /// point is near the anchor.
+    #[inline]
+    pub fn to_f32_relative_to(self, anchor: Vector2F64) -> Vector2F {
+        (self - anchor).to_f32()
+    }
+}
+
+impl From<Vector2F> for Vector2F64 {
+    #[inline]
+    fn from(vector: Vector2F) -> Vector2F64 {
+        Vector2F64::new(vector.x() as f64, vector.y() as f64)
+    }
+}
+
+impl Add<Vector2F64> for Vector2F64 {
+    type Output = Vector2F64;
+    #[inline]
+    fn add(self, other: Vector2F64) -> Vector2F64 {
+        Vector2F64::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub<Vector2F64> for Vector2F64 {
+    type Output = Vector2F64;
+    #[inline]
+    fn sub(self, other: Vector2F64) -> Vector2F64 {
+        Vector2F64::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<f64> for Vector2F64 {
+    type Output = Vector2F64;
+    #[inline]
+    fn mul(self, factor: f64) -> Vector2F64 {
+        Vector2F64::new(self.x * factor, self.y * factor)
+    }
+}
+
+impl Neg for Vector2F64 {
+    type Output = Vector2F64;
+    #[inline]
+    fn neg(self) -> Vector2F64 {
+        Vector2F64::new(-self.x, -self.y)
+    }
+}
+
+/// An affine transform with 64-bit floating point components, in row-major order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform2F64 {
+    pub m11: f64,
+    pub m12: f64,
+    pub m13: f64,
+    pub m21: f64,
+    pub m22: f64,
+    pub m23: f64,
+}
+
+impl Default for Transform2F64 {
+    #[inline]
+    fn default() -> Transform2F64 {
+        Transform2F64::row_major(1.0, 0.0, 0.0, 0.0, 1.0, 0.0)
+    }
+}
+
+impl Transform2F64 {
+    #[inline]
+    pub fn row_major(m11: f64, m12: f64, m13: f64, m21: f64, m22: f64, m23: f64) -> Transform2F64 {
+        Transform2F64 { m11, m12, m13, m21, m22, m23 }
+    }
+
+    #[inline]
+    pub fn from_scale(scale: Vector2F64) -> Transform2F64 {
+        Transform2F64::row_major(scale.x, 0.0, 0.0, 0.0, scale.y, 0.0)
+    }
+
+    #[inline]
+    pub fn from_rotation(theta: f64) -> Transform2F64 {
+        let (sin, cos) = theta.sin_cos();
+        Transform2F64::row_major(cos, -sin, 0.0, sin, cos, 0.0)
+    }
+
+    #[inline]
+    pub fn from_translation(vector: Vector2F64) -> Transform2F64 {
+        Transform2F64::row_major(1.0, 0.0, vector.x, 0.0, 1.0, vector.y)
+    }
+
+    #[inline]
+    pub fn translation(&self) -> Vector2F64 {
+        Vector2F64::new(self.m13, self.m23)
+    }
+
+    #[inline]
+    pub fn det(&self) -> f64 {
+        self.m11 * self.m22 - self.m12 * self.m21
+    }
+
+    #[inline]
+    pub fn inverse(&self) -> Transform2F64 {
+        let inv_det = 1.0 / self.det();
+        let (m11, m12) = (self.m22 * inv_det, -self.m12 * inv_det);
+        let (m21, m22) = (-self.m21 * inv_det, self.m11 * inv_det);
+        let (m13, m23) = (-(m11 * self.m13 + m12 * self.m23), -(m21 * self.m13 + m22 * self.m23));
+        Transform2F64::row_major(m11, m12, m13, m21, m22, m23)
+    }
+
+    /// Rounds this transform to single precision.
+    ///
+    /// If the translation is large, this loses precision; prefer `to_f32_at()`.
+    #[inline]
+    pub fn to_f32(&self) -> Transform2F {
+        Transform2F {
+            matrix: Matrix2x2F::row_major(self.m11 as f32,
+                                          self.m12 as f32,
+                                          self.m21 as f32,
+                                          self.m22 as f32),
+            vector: vec2f(self.m13 as f32, self.m23 as f32),
+        }
+    }
+
+    /// Returns a single-precision transform for points expressed relative to `anchor`.
+    ///
+    /// Applying the result to `point.to_f32_relative_to(anchor)` gives the same result as applying
+    /// this transform to `point`. The anchor's offset is folded into the translation in double
+    /// precision, so precision is only lost if the transformed points themselves are large.
+    #[inline]
+    pub fn to_f32_at(&self, anchor: Vector2F64) -> Transform2F {
+        (*self * Transform2F64::from_translation(anchor)).to_f32()
+    }
+}
+
+impl Mul<Transform2F64> for Transform2F64 {
+    type Output = Transform2F64;
+    #[inline]
+    fn mul(self, other: Transform2F64) -> Transform2F64 {
+        Transform2F64::row_major(self.m11 * other.m11 + self.m12 * other.m21,
+                               self.m11 * other.m12 + self.m12 * other.m22,
+                               self.m11 * other.m13 + self.m12 * other.m23 + self.m13,
+                               self.m21 * other.m11 + self.m22 * other.m21,
+                               self.m21 * other.m12 + self.m22 * other.m22,
+                               self.m21 * other.m13 + self.m22 * other.m23 + self.m23)
+    }
+}
+
+impl Mul<Vector2F64> for Transform2F64 {
+    type Output = Vector2F64;
+    #[inline]
+    fn mul(self, vector: Vector2F64) -> Vector2F64 {
+        Vector2F64::new(self.m11 * vector.x + self.m12 * vector.y + self.m13,
+                      self.m21 * vector.x + self.m22 * vector.y + self.m23)
+    }
+}
+
+impl MulAssign for Transform2F64 {
+    #[inline]
+    fn mul_assign(&mut self, other: Transform2F64) {
+        *self = *self * other
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::vector::Vector2F;
+    use super::{Transform2F64, Vector2F64};
+
+    #[test]
+    fn test_anchored_transform_keeps_subpixel_precision() {
+        // A view zoomed in on a point ten million units from the origin.
+        let origin = Vector2F64::new(1.0e7, -1.0e7);
+        let transform = Transform2F64::from_translation(Vector2F64::new(50.0, 50.0)) *
+            Transform2F64::from_scale(Vector2F64::new(2.0, 2.0)) *
+            Transform2F64::from_translation(-origin);
+        let anchor = origin + Vector2F64::new(3.0, 4.0);
+
+        for &(x, y) in &[(0.3, 0.7), (1.25, -3.6), (-7.1, 0.45)] {
+            let point = origin + Vector2F64::new(x, y);
+            let expected = (transform * point).to_f32();
+            let anchored = transform.to_f32_at(anchor) * point.to_f32_relative_to(anchor);
+            let rounded = transform.to_f32() * point.to_f32();
+            assert!(distance(anchored, expected) < 0.01);
+            assert!(distance(rounded, expected) > 0.1);
+        }
+    }
+
+    fn distance(a: Vector2F, b: Vector2F) -> f32 {
+        (a - b).length()
+    }
+}
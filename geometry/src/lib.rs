@@ -11,6 +11,8 @@
 //! Basic geometry and linear algebra primitives, optimized with SIMD.
 
 pub mod angle;
+#[cfg(feature = "pf-f64")]
+pub mod double;
 pub mod line_segment;
 pub mod rect;
 pub mod transform2d;
@@ -17,6 +17,7 @@ use pathfinder_simd::default::F32x4;
 use std::ops::{Add, Mul, MulAssign, Sub};
 
 #[derive(Clone, Copy, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LineSegment2F(pub F32x4);
 
 impl LineSegment2F {
@@ -293,6 +294,7 @@ impl MulAssign<Vector2F> for LineSegment2F {
 
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LineSegmentU16 {
     pub from_x: u16,
     pub from_y: u16,
@@ -16,6 +16,7 @@ use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
 
 /// 2D points with 32-bit floating point coordinates.
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vector2F(pub F32x2);
 
 impl Vector2F {
@@ -319,6 +320,7 @@ impl IntoVector2F for f32 {
 
 /// 2D points with 32-bit signed integer coordinates.
 #[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vector2I(pub I32x2);
 
 impl Vector2I {
@@ -468,6 +470,7 @@ impl Hash for Vector2I {
 ///
 /// The w value in the SIMD vector is always 0.0.
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vector3F(pub F32x4);
 
 impl Vector3F {
@@ -572,6 +575,7 @@ impl Sub<Vector3F> for Vector3F {
 
 /// 3D homogeneous points.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vector4F(pub F32x4);
 
 impl Vector4F {
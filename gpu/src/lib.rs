@@ -520,6 +520,7 @@ impl Default for BlendState {
 }
 
 bitflags! {
+    #[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct TextureSamplingFlags: u8 {
         const REPEAT_U    = 0x01;
         const REPEAT_V    = 0x02;
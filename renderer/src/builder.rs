@@ -18,27 +18,32 @@ use crate::gpu_data::{DrawTileBatch, DrawTileBatchD3D9, DrawTileBatchD3D11, Fill
 use crate::gpu_data::{PathBatchIndex, PathSource, PrepareTilesInfoD3D11, PropagateMetadataD3D11};
 use crate::gpu_data::{RenderCommand, SegmentIndicesD3D11, SegmentsD3D11, TileBatchDataD3D11};
 use crate::gpu_data::{TileBatchId, TileBatchTexture, TileObjectPrimitive, TilePathInfoD3D11};
-use crate::options::{PrepareMode, PreparedBuildOptions, PreparedRenderTransform};
+use crate::options::{AAQuality, PrepareMode, PreparedBuildOptions, PreparedRenderTransform};
+use crate::options::{DrawPathProgress, OcclusionPolicy, SubpixelAA, TileOrder, TilePass};
 use crate::paint::{PaintId, PaintInfo, PaintMetadata};
 use crate::scene::{ClipPathId, DisplayItem, DrawPath, DrawPathId, LastSceneInfo, PathId};
-use crate::scene::{Scene, SceneSink};
+use crate::scene::{BuildOutcome, BuildStats, MAX_DRAW_PATHS, Scene, SceneEpoch, SceneId};
+use crate::scene::{OVERDRAW_HISTOGRAM_LEN, OverdrawStats, SceneSink};
 use crate::tile_map::DenseTileMap;
-use crate::tiler::Tiler;
+use crate::tiler::{self, Tiler};
 use crate::tiles::{self, DrawTilingPathInfo, TILE_HEIGHT, TILE_WIDTH, TilingPathInfo};
 use fxhash::FxHashMap;
 use instant::Instant;
+use pathfinder_color::{ColorF, ColorU};
 use pathfinder_content::effects::{BlendMode, Filter};
 use pathfinder_content::fill::FillRule;
-use pathfinder_content::outline::{Outline, PointFlags};
+use pathfinder_content::outline::{ContourIterFlags, Outline, PointFlags};
 use pathfinder_geometry::line_segment::{LineSegment2F, LineSegmentU16};
 use pathfinder_geometry::rect::{RectF, RectI};
 use pathfinder_geometry::transform2d::Transform2F;
-use pathfinder_geometry::vector::{Vector2I, vec2i};
+use pathfinder_geometry::vector::{Vector2F, Vector2I, vec2f, vec2i};
 use pathfinder_gpu::TextureSamplingFlags;
 use pathfinder_simd::default::F32x4;
 use std::borrow::Cow;
 use std::ops::Range;
-use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
 use std::u32;
 
 pub(crate) const ALPHA_TILE_LEVEL_COUNT: usize = 2;
@@ -49,11 +54,59 @@ const CURVE_IS_CUBIC:     u32 = 0x40000000;
 
 const MAX_CLIP_BATCHES: u32 = 32;
 
+// The number of draw paths tiled between checks of the deadline in a time-sliced build.
+const SLICED_BUILD_CHUNK_SIZE: usize = 256;
+
 pub(crate) struct SceneBuilder<'a, 'b, 'c, 'd> {
     pub(crate) scene: &'a mut Scene,
-    built_options: &'b PreparedBuildOptions,
+    pub(crate) built_options: &'b PreparedBuildOptions,
     next_alpha_tile_indices: [AtomicUsize; ALPHA_TILE_LEVEL_COUNT],
     pub(crate) sink: &'c mut SceneSink<'d>,
+    pub(crate) cancel: Option<Arc<AtomicBool>>,
+    pub(crate) dirty_draw_paths: Option<Vec<bool>>,
+    deferred_fills: Option<Mutex<DeferredFills>>,
+    fill_count: AtomicUsize,
+    // The number of fills generated so far, whether or not they've been sent yet.
+    generated_fill_count: AtomicUsize,
+    // The number of draw paths built so far, and the number of tiles in their bounds, for
+    // progress reporting.
+    built_draw_path_count: AtomicUsize,
+    built_tile_count: AtomicUsize,
+    stats: BuildStats,
+    overdraw: Option<OverdrawCounter>,
+}
+
+// Everything that a time-sliced build keeps between slices: the work done so far, including the
+// occluder Z-buffer, and the builder's counters.
+pub(crate) struct SlicedBuildState {
+    // The scene and epoch that the build started with. Changing the scene invalidates the build.
+    scene_id: SceneId,
+    scene_epoch: SceneEpoch,
+    cpu_build_time: Duration,
+    prepare_mode: PrepareMode,
+    paint_metadata: Vec<PaintMetadata>,
+    built_clip_paths: Vec<BuiltPath>,
+    occluders: Option<Occluders>,
+    // The draw paths tiled so far, in order of draw path ID.
+    built_draw_paths: Vec<BuiltDrawPath>,
+    next_alpha_tile_indices: [usize; ALPHA_TILE_LEVEL_COUNT],
+    fill_count: usize,
+    generated_fill_count: usize,
+    built_draw_path_count: usize,
+    built_tile_count: usize,
+    deferred_fills: Option<DeferredFills>,
+    stats: BuildStats,
+    overdraw: Option<OverdrawCounter>,
+}
+
+// Counts the tiles drawn at each tile position of the output, for
+// `BuildOptions::collect_overdraw`.
+#[derive(Default)]
+struct OverdrawCounter {
+    // The number of render targets pushed and not yet popped. Tiles are only counted when this is
+    // zero.
+    render_target_depth: u32,
+    tile_counts: FxHashMap<(i16, i16), u32>,
 }
 
 #[derive(Debug)]
@@ -142,11 +195,238 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
             built_options,
             next_alpha_tile_indices: [AtomicUsize::new(0), AtomicUsize::new(0)],
             sink,
+            cancel: None,
+            dirty_draw_paths: None,
+            deferred_fills: None,
+            fill_count: AtomicUsize::new(0),
+            generated_fill_count: AtomicUsize::new(0),
+            built_draw_path_count: AtomicUsize::new(0),
+            built_tile_count: AtomicUsize::new(0),
+            stats: BuildStats::default(),
+            overdraw: if built_options.collect_overdraw {
+                Some(OverdrawCounter::default())
+            } else {
+                None
+            },
         }
     }
 
-    pub fn build<E>(&mut self, executor: &E) where E: Executor {
+    // Returns statistics about what has been sent so far.
+    pub(crate) fn stats(&self) -> BuildStats {
+        BuildStats {
+            fill_count: self.fill_count.load(Ordering::Relaxed),
+            overdraw: self.overdraw.as_ref().map(OverdrawCounter::stats),
+            ..self.stats
+        }
+    }
+
+    pub fn build<E>(&mut self, executor: &E) -> BuildOutcome where E: Executor {
         let start_time = Instant::now();
+        let paint_metadata = self.begin_build(executor);
+        let prepare_mode = self.built_options.to_prepare_mode(self.sink.renderer_level);
+
+        if self.has_too_many_paths() {
+            return self.finish_cancelled(start_time);
+        }
+
+        if self.built_options.wireframe {
+            if !self.build_wireframe() {
+                return self.finish_cancelled(start_time);
+            }
+
+            let cpu_build_time = Instant::now() - start_time;
+            self.sink.listener.send(RenderCommand::Finish { cpu_build_time });
+            return self.finished_outcome();
+        }
+
+        if let PrepareMode::CPU = prepare_mode {
+            if self.built_options.stream_tiles {
+                if !self.build_and_stream_tiles(&paint_metadata, &prepare_mode, executor) {
+                    return self.finish_cancelled(start_time);
+                }
+
+                let cpu_build_time = Instant::now() - start_time;
+                self.sink.listener.send(RenderCommand::Finish { cpu_build_time });
+                return self.finished_outcome();
+            }
+        }
+
+        let built_paths = match prepare_mode {
+            PrepareMode::CPU | PrepareMode::TransformCPUBinGPU => {
+                Some(self.build_paths_on_cpu(executor, &paint_metadata, &prepare_mode))
+            }
+            PrepareMode::GPU { .. } => None,
+        };
+
+        self.end_build(start_time, &paint_metadata, built_paths, &prepare_mode, executor)
+    }
+
+    // Like `build()`, but stops tiling draw paths once `deadline` has passed, returning the state
+    // to resume from with `resume_build()`. At least one chunk of draw paths is tiled first, so
+    // that every slice makes progress.
+    //
+    // Only tiling on CPU is sliced. Builds that stream tiles, draw wireframes, or tile on GPU run
+    // to completion in the first slice.
+    pub(crate) fn build_until<E>(&mut self, executor: &E, deadline: Instant)
+                                 -> Result<BuildOutcome, Box<SlicedBuildState>>
+                                 where E: Executor {
+        let prepare_mode = self.built_options.to_prepare_mode(self.sink.renderer_level);
+        let can_slice = match prepare_mode {
+            PrepareMode::CPU => !self.built_options.stream_tiles,
+            PrepareMode::TransformCPUBinGPU => true,
+            PrepareMode::GPU { .. } => false,
+        };
+        if !can_slice || self.built_options.wireframe || self.has_too_many_paths() {
+            return Ok(self.build(executor));
+        }
+
+        let start_time = Instant::now();
+        let paint_metadata = self.begin_build(executor);
+
+        if let PrepareMode::CPU = prepare_mode {
+            if self.built_options.deterministic || self.built_options.sort_fills_by_tile {
+                self.deferred_fills = Some(Mutex::new(vec![]));
+            }
+        }
+
+        let built_clip_paths = self.build_clip_paths_on_cpu(executor, &prepare_mode);
+        let occluders = self.build_occluders(&paint_metadata);
+
+        let state = Box::new(SlicedBuildState {
+            scene_id: self.scene.id(),
+            scene_epoch: self.scene.epoch(),
+            cpu_build_time: Duration::default(),
+            prepare_mode,
+            paint_metadata,
+            built_clip_paths,
+            occluders,
+            built_draw_paths: vec![],
+            next_alpha_tile_indices: [0; ALPHA_TILE_LEVEL_COUNT],
+            fill_count: 0,
+            generated_fill_count: 0,
+            built_draw_path_count: 0,
+            built_tile_count: 0,
+            deferred_fills: None,
+            stats: BuildStats::default(),
+            overdraw: None,
+        });
+        self.continue_sliced_build(state, start_time, executor, deadline)
+    }
+
+    // Continues a build paused by `build_until()`. If the scene changed in the meantime, the build
+    // is abandoned as if it had been cancelled.
+    pub(crate) fn resume_build<E>(&mut self,
+                                  mut state: Box<SlicedBuildState>,
+                                  executor: &E,
+                                  deadline: Instant)
+                                  -> Result<BuildOutcome, Box<SlicedBuildState>>
+                                  where E: Executor {
+        // Count the time spent in earlier slices toward the build time.
+        let start_time = Instant::now() - state.cpu_build_time;
+
+        for (level, &index) in state.next_alpha_tile_indices.iter().enumerate() {
+            self.next_alpha_tile_indices[level].store(index, Ordering::Relaxed);
+        }
+        self.fill_count.store(state.fill_count, Ordering::Relaxed);
+        self.generated_fill_count.store(state.generated_fill_count, Ordering::Relaxed);
+        self.built_draw_path_count.store(state.built_draw_path_count, Ordering::Relaxed);
+        self.built_tile_count.store(state.built_tile_count, Ordering::Relaxed);
+        self.deferred_fills = state.deferred_fills.take().map(Mutex::new);
+        self.stats = state.stats;
+        if let Some(overdraw) = state.overdraw.take() {
+            self.overdraw = Some(overdraw);
+        }
+
+        if self.scene.id() != state.scene_id || self.scene.epoch() != state.scene_epoch {
+            self.deferred_fills = None;
+            self.recycle_occluders(state.occluders);
+            return Ok(self.finish_cancelled(start_time));
+        }
+
+        self.continue_sliced_build(state, start_time, executor, deadline)
+    }
+
+    // Tiles draw paths in chunks until they're all tiled, in which case the build is finished, or
+    // until the deadline passes, in which case the state of the build is saved and returned.
+    fn continue_sliced_build<E>(&mut self,
+                                mut state: Box<SlicedBuildState>,
+                                start_time: Instant,
+                                executor: &E,
+                                deadline: Instant)
+                                -> Result<BuildOutcome, Box<SlicedBuildState>>
+                                where E: Executor {
+        let draw_path_count = self.scene.draw_paths().len();
+        let effective_view_box = self.scene.effective_view_box(self.built_options);
+
+        while state.built_draw_paths.len() < draw_path_count {
+            if self.is_cancelled() {
+                self.deferred_fills = None;
+                self.recycle_occluders(state.occluders);
+                return Ok(self.finish_cancelled(start_time));
+            }
+
+            let first_path_index = state.built_draw_paths.len();
+            let chunk_size = SLICED_BUILD_CHUNK_SIZE.min(draw_path_count - first_path_index);
+            let min_len = self.built_options.parallel_chunk_size;
+            let built_draw_paths = executor.build_vector_with_min_len(chunk_size, min_len, |index| {
+                let params = DrawPathBuildParams {
+                    path_build_params: PathBuildParams {
+                        path_id: PathId((first_path_index + index) as u32),
+                        view_box: effective_view_box,
+                        prepare_mode: state.prepare_mode,
+                        built_options: self.built_options,
+                        scene: self.scene,
+                    },
+                    paint_metadata: &state.paint_metadata,
+                    built_clip_paths: &state.built_clip_paths,
+                    occluders: state.occluders.as_ref(),
+                };
+                self.build_draw_path_on_cpu(params, false).0
+            });
+            state.built_draw_paths.extend(built_draw_paths);
+
+            if state.built_draw_paths.len() < draw_path_count && Instant::now() >= deadline {
+                for (level, index) in state.next_alpha_tile_indices.iter_mut().enumerate() {
+                    *index = self.next_alpha_tile_indices[level].load(Ordering::Relaxed);
+                }
+                state.fill_count = self.fill_count.load(Ordering::Relaxed);
+                state.generated_fill_count = self.generated_fill_count.load(Ordering::Relaxed);
+                state.built_draw_path_count =
+                    self.built_draw_path_count.load(Ordering::Relaxed);
+                state.built_tile_count = self.built_tile_count.load(Ordering::Relaxed);
+                state.deferred_fills = self.deferred_fills.take().map(|deferred_fills| {
+                    deferred_fills.into_inner().unwrap()
+                });
+                state.stats = self.stats;
+                state.overdraw = self.overdraw.take();
+                state.cpu_build_time = Instant::now() - start_time;
+                return Err(state);
+            }
+        }
+
+        self.recycle_occluders(state.occluders);
+        let mut built_draw_paths = state.built_draw_paths;
+        self.send_deferred_fills(&mut built_draw_paths);
+
+        let built_paths = BuiltPaths { draw: built_draw_paths, first_draw_path_id: DrawPathId(0) };
+        Ok(self.end_build(start_time,
+                          &state.paint_metadata,
+                          Some(built_paths),
+                          &state.prepare_mode,
+                          executor))
+    }
+
+    // Resets the per-build state of the sink, sends the commands that start a build, and builds
+    // paint data. Returns the paint metadata.
+    fn begin_build<E>(&mut self, executor: &E) -> Vec<PaintMetadata> where E: Executor {
+        self.sink.listener.reset();
+        self.sink.occluder_paint_ranks.clear();
+        self.sink.draw_path_tile_bounds.clear();
+        self.sink.draw_path_tile_bounds.resize(self.scene.draw_paths().len(), None);
+        self.sink.draw_path_tile_counts.clear();
+        self.sink.draw_path_tile_counts.resize(self.scene.draw_paths().len(), 0);
+
+        self.scene.update_stroked_outlines(self.built_options, executor);
 
         // Send the start rendering command.
         let bounding_quad = self.built_options.bounding_quad();
@@ -161,9 +441,23 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
             bounding_quad,
             path_count: total_path_count,
             needs_readable_framebuffer,
+            preserve_mask: self.built_options.preserve_mask,
+            premultiplied_alpha: self.built_options.premultiplied_alpha,
         });
 
-        let prepare_mode = self.built_options.to_prepare_mode(self.sink.renderer_level);
+        // Let the listener know if the view box changed since the last build.
+        let effective_view_box = self.scene.effective_view_box(self.built_options);
+        if self.sink.last_view_box != Some(effective_view_box) {
+            self.sink.listener.send(RenderCommand::ViewBoxChanged(effective_view_box));
+            self.sink.last_view_box = Some(effective_view_box);
+        }
+
+        self.sink.listener.send(RenderCommand::SetColorSpace(self.built_options.color_space));
+
+        if !self.built_options.preserve_mask {
+            let color = self.built_options.mask_clear_color;
+            self.sink.listener.send(RenderCommand::ClearMaskFramebuffer { color });
+        }
 
         let render_transform = match self.built_options.transform {
             PreparedRenderTransform::Transform2D(transform) => transform.inverse(),
@@ -174,20 +468,39 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
         let PaintInfo {
             render_commands,
             paint_metadata,
-        } = self.scene.build_paint_info(&mut self.sink.paint_texture_manager, render_transform);
-        for render_command in render_commands {
+        } = self.scene.build_paint_info(&mut self.sink.paint_texture_manager,
+                                        render_transform,
+                                        self.built_options.global_alpha);
+        if self.built_options.global_alpha < 1.0 {
+            let global_alpha = self.built_options.global_alpha;
+            self.sink.listener.send(RenderCommand::SetGlobalAlpha(global_alpha));
+        }
+        for mut render_command in render_commands {
+            if self.built_options.premultiplied_alpha {
+                if let RenderCommand::UploadTextureMetadata(ref mut metadata) = render_command {
+                    for entry in metadata {
+                        entry.base_color = premultiply_color_f32(entry.base_color);
+                    }
+                }
+            }
             self.sink.listener.send(render_command);
         }
 
-        let built_paths = match prepare_mode {
-            PrepareMode::CPU | PrepareMode::TransformCPUBinGPU => {
-                Some(self.build_paths_on_cpu(executor, &paint_metadata, &prepare_mode))
-            }
-            PrepareMode::GPU { .. } => None,
-        };
+        paint_metadata
+    }
 
+    // Uploads the scene if the GPU needs it, packs and sends the tile batches for the given built
+    // paths, and sends `Finish`.
+    fn end_build<E>(&mut self,
+                    start_time: Instant,
+                    paint_metadata: &[PaintMetadata],
+                    built_paths: Option<BuiltPaths>,
+                    prepare_mode: &PrepareMode,
+                    executor: &E)
+                    -> BuildOutcome
+                    where E: Executor {
         // TODO(pcwalton): Do this earlier?
-        let scene_is_dirty = match (&prepare_mode, &self.sink.last_scene) {
+        let scene_is_dirty = match (prepare_mode, &self.sink.last_scene) {
             (&PrepareMode::GPU { .. }, &None) => true,
             (&PrepareMode::GPU { .. }, &Some(LastSceneInfo {
                  scene_id: ref last_scene_id,
@@ -197,6 +510,10 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
             _ => false,
         };
 
+        if self.is_cancelled() {
+            return self.finish_cancelled(start_time);
+        }
+
         if scene_is_dirty {
             let built_segments = BuiltSegments::from_scene(&self.scene);
             self.sink.listener.send(RenderCommand::UploadSceneD3D11 {
@@ -211,10 +528,173 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
             });
         }
 
-        self.finish_building(&paint_metadata, built_paths, &prepare_mode);
+        if !self.finish_building(paint_metadata, built_paths, prepare_mode, executor) {
+            return self.finish_cancelled(start_time);
+        }
+
+        let cpu_build_time = Instant::now() - start_time;
+        self.sink.listener.send(RenderCommand::Finish { cpu_build_time });
+        self.finished_outcome()
+    }
+
+    // The outcome of a build that ran to completion. The listener may still have refused some of
+    // the last commands.
+    fn finished_outcome(&self) -> BuildOutcome {
+        if self.sink.listener.has_failed() {
+            BuildOutcome::ListenerFailed
+        } else if self.fill_limit_exceeded(0) {
+            BuildOutcome::TooManyFills
+        } else {
+            BuildOutcome::Finished
+        }
+    }
+
+    // Returns true if the build should be abandoned, because it was cancelled, because the
+    // listener refused a command, or because too many fills were generated.
+    #[inline]
+    fn is_cancelled(&self) -> bool {
+        if self.sink.listener.has_failed() || self.fill_limit_exceeded(0) {
+            return true;
+        }
+        match self.cancel {
+            None => false,
+            Some(ref cancel) => cancel.load(Ordering::Relaxed),
+        }
+    }
 
+    // Terminates the command stream for a cancelled build. Only `Finish` is sent, so that
+    // consumers waiting for the end of the frame don't stall. Fills that were already sent aren't
+    // flushed, so a half-built frame is never drawn into the mask; the renderer discards them when
+    // the next frame starts. No tiles are drawn.
+    fn finish_cancelled(&self, start_time: Instant) -> BuildOutcome {
         let cpu_build_time = Instant::now() - start_time;
         self.sink.listener.send(RenderCommand::Finish { cpu_build_time });
+        if self.sink.listener.has_failed() {
+            BuildOutcome::ListenerFailed
+        } else if self.fill_limit_exceeded(0) {
+            BuildOutcome::TooManyFills
+        } else if self.has_too_many_paths() {
+            BuildOutcome::TooManyPaths
+        } else {
+            BuildOutcome::Cancelled
+        }
+    }
+
+    // Returns true if the scene has too many draw paths for their paint ranks to fit in the
+    // Z-buffers.
+    #[inline]
+    fn has_too_many_paths(&self) -> bool {
+        self.scene.draw_paths().len() > MAX_DRAW_PATHS
+    }
+
+    // Returns true if the fills generated so far, plus `pending_fill_count` more that haven't been
+    // counted yet, exceed the limit in the build options.
+    #[inline]
+    pub(crate) fn fill_limit_exceeded(&self, pending_fill_count: usize) -> bool {
+        match self.built_options.max_fills {
+            None => false,
+            Some(max_fills) => {
+                self.generated_fill_count.load(Ordering::Relaxed) + pending_fill_count > max_fills
+            }
+        }
+    }
+
+    // Tiles each draw path in turn and sends its tiles as soon as it's done, so that the renderer
+    // can get to work before tiling is finished. Returns false if the build was cancelled.
+    fn build_and_stream_tiles<E>(&mut self,
+                                 paint_metadata: &[PaintMetadata],
+                                 prepare_mode: &PrepareMode,
+                                 executor: &E)
+                                 -> bool
+                                 where E: Executor {
+        let clip_path_count = self.scene.clip_paths().len();
+        let effective_view_box = self.scene.effective_view_box(self.built_options);
+
+        let built_clip_paths: Vec<_> = (0..clip_path_count).map(|path_index| {
+            self.build_clip_path_on_cpu(PathBuildParams {
+                path_id: PathId(path_index as u32),
+                view_box: effective_view_box,
+                prepare_mode: *prepare_mode,
+                built_options: self.built_options,
+                scene: self.scene,
+            })
+        }).collect();
+
+        let occluders = self.build_occluders(paint_metadata);
+
+        let mut render_target_depth = 0;
+        for display_item in self.scene.display_list() {
+            match *display_item {
+                DisplayItem::PushRenderTarget(render_target_id) => {
+                    let command = RenderCommand::PushRenderTarget(render_target_id);
+                    if let Some(ref mut overdraw) = self.overdraw {
+                        overdraw.observe(&command);
+                    }
+                    self.sink.listener.send(command);
+                    render_target_depth += 1;
+                }
+                DisplayItem::PopRenderTarget => {
+                    if let Some(ref mut overdraw) = self.overdraw {
+                        overdraw.observe(&RenderCommand::PopRenderTarget);
+                    }
+                    self.sink.listener.send(RenderCommand::PopRenderTarget);
+                    render_target_depth -= 1;
+                }
+                DisplayItem::DrawPaths(ref path_id_range) => {
+                    let paint_order = self.scene.paint_order(path_id_range.clone(),
+                                                             self.built_options);
+                    for draw_path_id in paint_order {
+                        if self.is_cancelled() {
+                            return false;
+                        }
+
+                        let params = DrawPathBuildParams {
+                            path_build_params: PathBuildParams {
+                                path_id: draw_path_id.to_path_id(),
+                                view_box: effective_view_box,
+                                prepare_mode: *prepare_mode,
+                                built_options: self.built_options,
+                                scene: self.scene,
+                            },
+                            paint_metadata,
+                            built_clip_paths: &built_clip_paths,
+                            occluders: occluders.as_ref(),
+                        };
+                        let (built_draw_path, _) = self.build_draw_path_on_cpu(params, false);
+
+                        self.sink.listener.send(RenderCommand::FlushFillsD3D9);
+
+                        let mut tile_batch_builder = TileBatchBuilder::new(Some(BuiltPaths {
+                            draw: vec![built_draw_path],
+                            first_draw_path_id: draw_path_id,
+                        }));
+                        tile_batch_builder.render_target_depth = render_target_depth;
+                        tile_batch_builder.build_tile_batches_for_draw_path_display_item(
+                            self.scene,
+                            self.sink,
+                            self.built_options,
+                            draw_path_id..DrawPathId(draw_path_id.0 + 1),
+                            paint_metadata,
+                            prepare_mode);
+                        self.stats.culled_tile_count +=
+                            tile_batch_builder.cull_occluded_tiles(
+                                self.built_options.occlusion_policy.as_deref(),
+                                executor);
+                        tile_batch_builder.sort_tiles(self.built_options.tile_order);
+                        #[cfg(debug_assertions)]
+                        tile_batch_builder.validate_tile_coords(
+                            self.scene.effective_view_box(self.built_options));
+                        tile_batch_builder.send_to(self.sink,
+                                                   &mut self.stats,
+                                                   self.overdraw.as_mut());
+                    }
+                }
+            }
+        }
+
+        self.recycle_occluders(occluders);
+
+        true
     }
 
     fn build_paths_on_cpu<E>(&mut self,
@@ -223,22 +703,36 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
                              prepare_mode: &PrepareMode)
                              -> BuiltPaths
                              where E: Executor {
-        let clip_path_count = self.scene.clip_paths().len();
         let draw_path_count = self.scene.draw_paths().len();
         let effective_view_box = self.scene.effective_view_box(self.built_options);
 
-        let built_clip_paths = executor.build_vector(clip_path_count, |path_index| {
-            self.build_clip_path_on_cpu(PathBuildParams {
-                path_id: PathId(path_index as u32),
-                view_box: effective_view_box,
-                prepare_mode: *prepare_mode,
-                built_options: &self.built_options,
-                scene: &self.scene,
-            })
-        });
+        // Only paths tiled on CPU have fills and alpha tiles that could come out in a different
+        // order.
+        if let PrepareMode::CPU = *prepare_mode {
+            if self.built_options.deterministic || self.built_options.sort_fills_by_tile {
+                self.deferred_fills = Some(Mutex::new(vec![]));
+            }
+        }
 
-        let built_draw_paths = executor.build_vector(draw_path_count, |path_index| {
-            self.build_draw_path_on_cpu(DrawPathBuildParams {
+        let built_clip_paths = self.build_clip_paths_on_cpu(executor, prepare_mode);
+        let occluders = self.build_occluders(paint_metadata);
+
+        // If this is an incremental build, figure out whether we can reuse the paths we built
+        // last time.
+        let cache_key = self.draw_path_cache_key();
+        let reusable_cache = match (cache_key, &self.dirty_draw_paths, &self.sink.draw_path_cache) {
+            (Some(cache_key), Some(dirty_draw_paths), Some(cache)) if
+                    cache.key == cache_key && !too_dirty_to_reuse(dirty_draw_paths) => {
+                Some(cache)
+            }
+            _ => None,
+        };
+
+        let min_len = self.built_options.parallel_chunk_size;
+        let mut built_draw_paths = executor.build_vector_with_min_len(draw_path_count,
+                                                                      min_len,
+                                                                      |path_index| {
+            let params = DrawPathBuildParams {
                 path_build_params: PathBuildParams {
                     path_id: PathId(path_index as u32),
                     view_box: effective_view_box,
@@ -248,16 +742,334 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
                 },
                 paint_metadata: &paint_metadata,
                 built_clip_paths: &built_clip_paths,
+                occluders: occluders.as_ref(),
+            };
+
+            if let (Some(cache), Some(dirty_draw_paths)) = (reusable_cache,
+                                                             &self.dirty_draw_paths) {
+                if !dirty_draw_paths[path_index] {
+                    if let Some(ref cached_path) = cache.paths[path_index] {
+                        return (self.reuse_cached_draw_path(params, cached_path), None);
+                    }
+                }
+            }
+
+            // The second element is the path's new cache entry, or `None` if it was reused.
+            let (built_draw_path, cached_path) =
+                self.build_draw_path_on_cpu(params, cache_key.is_some());
+            (built_draw_path, Some(cached_path))
+        });
+
+        // Update the cache for the next incremental build.
+        if let Some(cache_key) = cache_key {
+            let reused_cache = reusable_cache.is_some();
+            let mut cached_paths = match self.sink.draw_path_cache.take() {
+                Some(cache) if reused_cache => cache.paths,
+                _ => (0..draw_path_count).map(|_| None).collect(),
+            };
+            for (path_index, (_, new_cached_path)) in built_draw_paths.iter_mut().enumerate() {
+                if let Some(new_cached_path) = new_cached_path.take() {
+                    cached_paths[path_index] = new_cached_path;
+                }
+            }
+            self.sink.draw_path_cache = Some(DrawPathCache { key: cache_key, paths: cached_paths });
+        }
+
+        self.recycle_occluders(occluders);
+
+        let mut built_draw_paths: Vec<_> =
+            built_draw_paths.into_iter().map(|(built_draw_path, _)| built_draw_path).collect();
+        self.send_deferred_fills(&mut built_draw_paths);
+
+        BuiltPaths { draw: built_draw_paths, first_draw_path_id: DrawPathId(0) }
+    }
+
+    fn build_clip_paths_on_cpu<E>(&self, executor: &E, prepare_mode: &PrepareMode)
+                                  -> Vec<BuiltPath>
+                                  where E: Executor {
+        let clip_path_count = self.scene.clip_paths().len();
+        let effective_view_box = self.scene.effective_view_box(self.built_options);
+        let min_len = self.built_options.parallel_chunk_size;
+        executor.build_vector_with_min_len(clip_path_count, min_len, |path_index| {
+            self.build_clip_path_on_cpu(PathBuildParams {
+                path_id: PathId(path_index as u32),
+                view_box: effective_view_box,
+                prepare_mode: *prepare_mode,
+                built_options: &self.built_options,
+                scene: &self.scene,
             })
+        })
+    }
+
+    // Sends the fills held back for a deterministic or tile-sorted build.
+    fn send_deferred_fills(&mut self, built_draw_paths: &mut [BuiltDrawPath]) {
+        let mut deferred_fills = match self.deferred_fills.take() {
+            None => return,
+            Some(deferred_fills) => deferred_fills.into_inner().unwrap(),
+        };
+        if self.is_cancelled() {
+            return;
+        }
+
+        if self.built_options.deterministic {
+            self.make_deferred_fills_deterministic(&mut deferred_fills, built_draw_paths);
+        }
+
+        if self.built_options.sort_fills_by_tile {
+            // Each fill names its alpha tile, so the sort doesn't change which tile it lands in.
+            // The sort is stable, so the fills of each tile also keep their relative order.
+            let mut fills: Vec<Fill> =
+                deferred_fills.into_iter().flat_map(|(_, fills)| fills).collect();
+            fills.sort_by_key(|fill| fill.link);
+            self.send_fills(fills);
+            return;
+        }
+
+        for (_, fills) in deferred_fills {
+            self.send_fills(fills);
+        }
+    }
+
+    // Puts deferred fills in the order that a sequential build would have sent them. Alpha tiles
+    // are renumbered in the order their first fills appear, which again matches a sequential
+    // build, since alpha tiles are allocated as fills are generated.
+    fn make_deferred_fills_deterministic(&self,
+                                         deferred_fills: &mut DeferredFills,
+                                         built_draw_paths: &mut [BuiltDrawPath]) {
+        // This is a stable sort, but each path sends its fills only once anyway.
+        deferred_fills.sort_by_key(|&(fill_owner, _)| fill_owner);
+
+        let alpha_tile_count = self.next_alpha_tile_indices[0].load(Ordering::Relaxed);
+        let mut alpha_tile_mapping = vec![AlphaTileId::invalid(); alpha_tile_count];
+        let mut next_alpha_tile_index = 0;
+        for &mut (_, ref mut fills) in deferred_fills.iter_mut() {
+            for fill in fills {
+                let new_alpha_tile_id = &mut alpha_tile_mapping[fill.link as usize];
+                if !new_alpha_tile_id.is_valid() {
+                    *new_alpha_tile_id = AlphaTileId(next_alpha_tile_index);
+                    next_alpha_tile_index += 1;
+                }
+                fill.link = new_alpha_tile_id.0;
+            }
+        }
+
+        let remap = |alpha_tile_id: &mut AlphaTileId| {
+            if alpha_tile_id.is_valid() {
+                *alpha_tile_id = alpha_tile_mapping[alpha_tile_id.0 as usize];
+                debug_assert!(alpha_tile_id.is_valid(), "Alpha tile had no fills!");
+            }
+        };
+        for built_draw_path in built_draw_paths {
+            if let BuiltPathData::CPU(ref mut cpu_data) = built_draw_path.path.data {
+                for tile in &mut cpu_data.tiles.data {
+                    remap(&mut tile.alpha_tile_id);
+                }
+                if let Some(ref mut clip_tiles) = cpu_data.clip_tiles {
+                    for clip in &mut clip_tiles.data {
+                        remap(&mut clip.dest_tile_id);
+                        remap(&mut clip.src_tile_id);
+                    }
+                }
+            }
+        }
+    }
+
+    // Finds draw paths that are opaque, unclipped, axis-aligned rectangles, and returns a map from
+    // each tile that such paths completely cover to the ID of the topmost path covering it. Paths
+    // entirely behind these rectangles can skip tiling. Returns `None` if there are no such paths
+    // or if occlusion can't be determined this early.
+    fn build_occluders(&mut self, paint_metadata: &[PaintMetadata]) -> Option<Occluders> {
+        // Paths drawn to different render targets don't occlude one another, so don't bother.
+        let has_render_targets = self.scene.display_list().iter().any(|display_item| {
+            match *display_item {
+                DisplayItem::PushRenderTarget(_) => true,
+                DisplayItem::PopRenderTarget | DisplayItem::DrawPaths(_) => false,
+            }
         });
+        // Filtered outlines can go anywhere, so neither occluders nor occluded paths are known.
+        // Occlusion policies are only consulted once tiles are built.
+        if has_render_targets || self.built_options.outline_filter.is_some() ||
+                self.built_options.occlusion_policy.is_some() {
+            return None;
+        }
+
+        let mut transform = match self.built_options.transform {
+            PreparedRenderTransform::None => Transform2F::default(),
+            PreparedRenderTransform::Transform2D(transform) => transform,
+            PreparedRenderTransform::Perspective { .. } => return None,
+        };
+        if self.built_options.subpixel_aa.is_enabled() {
+            transform *= Transform2F::from_scale(self.built_options.subpixel_aa.scale())
+        }
+
+        let effective_view_box = self.scene.effective_view_box(self.built_options);
+        let tile_bounds = tiles::round_rect_out_to_tile_bounds(effective_view_box);
+        let mut z_buffer = match self.sink.spare_occluder_z_buffer.take() {
+            None => DenseTileMap::from_builder(|_| -1, tile_bounds),
+            Some(mut z_buffer) => {
+                z_buffer.reset(-1, tile_bounds);
+                z_buffer
+            }
+        };
+        let mut found_occluder = false;
+        let paint_ranks = self.scene.paint_ranks(self.built_options);
+        debug_assert!(paint_ranks.len() <= MAX_DRAW_PATHS);
+
+        for (draw_path_index, draw_path) in self.scene.draw_paths().iter().enumerate() {
+            let paint_metadata = &paint_metadata[draw_path.paint().0 as usize];
+            let draw_path_id = DrawPathId(draw_path_index as u32);
+            if !self.scene.draw_path_is_shown(draw_path_id, self.built_options) ||
+                    draw_path.clip_path().is_some() ||
+                    !paint_metadata.is_opaque || !draw_path.blend_mode().occludes_backdrop() {
+                continue;
+            }
+
+            let path_transform = transform * draw_path.transform();
+            let rect = match axis_aligned_rect(draw_path.fill_outline(), &path_transform) {
+                None => continue,
+                Some(rect) => rect.dilate(draw_path.dilation(self.built_options)),
+            };
+            if self.built_options.is_too_small(rect) {
+                continue;
+            }
 
-        BuiltPaths { draw: built_draw_paths }
+            // Only tiles that the rectangle covers completely are occluded.
+            let tile_size = vec2f(TILE_WIDTH as f32, TILE_HEIGHT as f32);
+            let covered_tiles = RectI::from_points((rect.origin() / tile_size).ceil().to_i32(),
+                                                   (rect.lower_right() / tile_size).floor()
+                                                                                   .to_i32());
+            for tile_y in covered_tiles.min_y()..covered_tiles.max_y() {
+                for tile_x in covered_tiles.min_x()..covered_tiles.max_x() {
+                    if let Some(z_value) = z_buffer.get_mut(vec2i(tile_x, tile_y)) {
+                        *z_value = (*z_value).max(paint_ranks[draw_path_index] as i32);
+                        found_occluder = true;
+                    }
+                }
+            }
+        }
+
+        if found_occluder {
+            Some(Occluders { z_buffer, paint_ranks })
+        } else {
+            self.sink.spare_occluder_z_buffer = Some(z_buffer);
+            None
+        }
+    }
+
+    // Hands the occluder Z-buffer back to the sink so that the next build can reuse it and so that
+    // it can be inspected afterward.
+    fn recycle_occluders(&mut self, occluders: Option<Occluders>) {
+        if let Some(occluders) = occluders {
+            self.sink.spare_occluder_z_buffer = Some(occluders.z_buffer);
+            self.sink.occluder_paint_ranks = occluders.paint_ranks;
+        }
+    }
+
+    // Returns the key that identifies built paths eligible for reuse, or `None` if this isn't an
+    // incremental build or if paths built this way can't be reused.
+    fn draw_path_cache_key(&self) -> Option<DrawPathCacheKey> {
+        let dirty_draw_paths = match self.dirty_draw_paths {
+            None => return None,
+            Some(ref dirty_draw_paths) => dirty_draw_paths,
+        };
+        if dirty_draw_paths.len() != self.scene.draw_paths().len() {
+            return None;
+        }
+
+        // Only paths binned on CPU have anything worth caching.
+        let transform = match self.built_options.transform {
+            PreparedRenderTransform::None => Transform2F::default(),
+            PreparedRenderTransform::Transform2D(transform) => transform,
+            PreparedRenderTransform::Perspective { .. } => return None,
+        };
+        // Clip polygons are rare enough that they aren't worth including in the key. Outline
+        // filters can't be compared at all.
+        if self.built_options.clip_polygon.is_some() ||
+                self.built_options.outline_filter.is_some() ||
+                self.sink.renderer_level != RendererLevel::D3D9 {
+            return None;
+        }
+
+        Some(DrawPathCacheKey {
+            scene_id: self.scene.id(),
+            draw_path_count: dirty_draw_paths.len(),
+            view_box: self.scene.view_box(),
+            transform,
+            dilation: self.built_options.dilation,
+            subpixel_aa: self.built_options.subpixel_aa,
+            aa_quality: self.built_options.aa_quality,
+            scissor: self.built_options.scissor,
+            min_path_size: self.built_options.min_path_size,
+            grid_fit: self.built_options.grid_fit,
+            conservative: self.built_options.conservative,
+        })
+    }
+
+    // Rebuilds a draw path from the cache, allocating fresh alpha tiles for it and resending its
+    // fills.
+    fn reuse_cached_draw_path(&self, params: DrawPathBuildParams, cached_path: &CachedDrawPath)
+                              -> BuiltDrawPath {
+        let DrawPathBuildParams {
+            path_build_params: PathBuildParams { path_id, scene, .. },
+            paint_metadata,
+            ..
+        } = params;
+
+        let first_alpha_tile_index =
+            self.next_alpha_tile_indices[0].fetch_add(cached_path.alpha_tile_count as usize,
+                                                      Ordering::Relaxed);
+        debug_assert!(first_alpha_tile_index + cached_path.alpha_tile_count as usize <=
+                      ALPHA_TILES_PER_LEVEL);
+        let first_alpha_tile_index = first_alpha_tile_index as u32;
+
+        let mut built_path = cached_path.built_path.clone();
+        if let BuiltPathData::CPU(ref mut cpu_data) = built_path.data {
+            for tile in &mut cpu_data.tiles.data {
+                if tile.alpha_tile_id.is_valid() {
+                    tile.alpha_tile_id.0 += first_alpha_tile_index;
+                }
+            }
+        }
+
+        let fills = cached_path.fills.iter().map(|fill| {
+            Fill { link: fill.link + first_alpha_tile_index, ..*fill }
+        }).collect();
+        self.send_or_defer_fills(FillOwner::DrawPath(path_id.0), fills);
+
+        self.report_progress(path_id.to_draw_path_id(), &built_path);
+
+        let path_object = scene.get_draw_path(path_id.to_draw_path_id());
+        let paint_metadata = &paint_metadata[path_object.paint().0 as usize];
+        BuiltDrawPath::new(built_path, path_object, paint_metadata)
+    }
+
+    // Notifies the progress callback, if any, that the given draw path has been built.
+    fn report_progress(&self, draw_path_id: DrawPathId, built_path: &BuiltPath) {
+        let progress = match self.built_options.progress {
+            None => return,
+            Some(ref progress) => progress,
+        };
+        let tile_count = built_path.tile_bounds.area() as usize;
+        progress(DrawPathProgress {
+            draw_path_id,
+            tile_count,
+            built_draw_path_count: self.built_draw_path_count.fetch_add(1, Ordering::Relaxed) + 1,
+            built_tile_count: self.built_tile_count.fetch_add(tile_count, Ordering::Relaxed) +
+                tile_count,
+        });
     }
 
     fn build_clip_path_on_cpu(&self, params: PathBuildParams) -> BuiltPath {
         let PathBuildParams { path_id, view_box, built_options, scene, prepare_mode } = params;
         let path_object = &scene.get_clip_path(path_id.to_clip_path_id());
-        let outline = scene.apply_render_options(path_object.outline(), built_options);
+        let outline = if self.is_cancelled() {
+            Outline::new()
+        } else {
+            scene.apply_render_options(path_object.outline(),
+                                       &Transform2F::default(),
+                                       built_options.dilation,
+                                       built_options)
+        };
 
         let mut tiler = Tiler::new(self,
                                    path_id,
@@ -270,11 +1082,12 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
                                    TilingPathInfo::Clip);
 
         tiler.generate_tiles();
-        self.send_fills(tiler.object_builder.fills);
+        self.send_or_defer_fills(FillOwner::ClipPath(path_id.0), tiler.object_builder.fills);
         tiler.object_builder.built_path
     }
 
-    fn build_draw_path_on_cpu(&self, params: DrawPathBuildParams) -> BuiltDrawPath {
+    fn build_draw_path_on_cpu(&self, params: DrawPathBuildParams, cache: bool)
+                              -> (BuiltDrawPath, Option<CachedDrawPath>) {
         let DrawPathBuildParams {
             path_build_params: PathBuildParams {
                 path_id,
@@ -285,14 +1098,39 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
             },
             paint_metadata,
             built_clip_paths,
+            occluders,
         } = params;
 
         let path_object = scene.get_draw_path(path_id.to_draw_path_id());
-        let outline = scene.apply_render_options(path_object.outline(), built_options);
+        let mut outline = if self.is_cancelled() ||
+                !scene.draw_path_is_shown(path_id.to_draw_path_id(), built_options) ||
+                scene.draw_path_is_culled(path_object, built_options) {
+            Outline::new()
+        } else {
+            let mut outline = scene.apply_render_options(path_object.fill_outline(),
+                                                         &path_object.transform(),
+                                                         path_object.dilation(built_options),
+                                                         built_options);
+            if let Some(ref outline_filter) = built_options.outline_filter {
+                outline_filter(&mut outline, path_id.to_draw_path_id());
+            }
+            outline
+        };
+
+        // If the path is hidden behind opaque rectangles, there's no need to tile it. The result
+        // depends on other paths, so don't cache it.
+        let mut cache = cache;
+        if let Some(occluders) = occluders {
+            if path_is_occluded(occluders, path_id.to_draw_path_id(), outline.bounds(), view_box) {
+                outline = Outline::new();
+                cache = false;
+            }
+        }
 
         let paint_id = path_object.paint();
         let paint_metadata = &paint_metadata[paint_id.0 as usize];
 
+        let tiling_start_time = Instant::now();
         let mut tiler = Tiler::new(self,
                                    path_id,
                                    &outline,
@@ -305,24 +1143,117 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
             paint_id,
             blend_mode: path_object.blend_mode(),
             fill_rule: path_object.fill_rule(),
+            antialias: path_object.antialias(),
+            grid_fit: built_options.grid_fit && path_object.is_text(),
+            conservative: built_options.conservative,
         }));
 
         tiler.generate_tiles();
-        self.send_fills(tiler.object_builder.fills);
 
-        BuiltDrawPath::new(tiler.object_builder.built_path, path_object, paint_metadata)
+        if built_options.collect_timing {
+            self.sink.listener.send(RenderCommand::DrawPathTimingSample {
+                draw_path_id: path_id.to_draw_path_id(),
+                tiling_time: Instant::now() - tiling_start_time,
+            });
+        }
+
+        let cached_path = if cache {
+            CachedDrawPath::new(&tiler.object_builder.built_path, &tiler.object_builder.fills)
+        } else {
+            None
+        };
+
+        self.send_or_defer_fills(FillOwner::DrawPath(path_id.0), tiler.object_builder.fills);
+        self.report_progress(path_id.to_draw_path_id(), &tiler.object_builder.built_path);
+
+        let built_draw_path =
+            BuiltDrawPath::new(tiler.object_builder.built_path, path_object, paint_metadata);
+        (built_draw_path, cached_path)
+    }
+
+    fn send_or_defer_fills(&self, fill_owner: FillOwner, fills: Vec<Fill>) {
+        self.generated_fill_count.fetch_add(fills.len(), Ordering::Relaxed);
+        match self.deferred_fills {
+            Some(ref deferred_fills) => deferred_fills.lock().unwrap().push((fill_owner, fills)),
+            None => self.send_fills(fills),
+        }
     }
 
     fn send_fills(&self, fills: Vec<Fill>) {
-        if !fills.is_empty() {
+        // Fills are only needed for alpha tiles, which the opaque pass doesn't draw.
+        if !fills.is_empty() && !self.is_cancelled() &&
+                self.built_options.tile_pass != TilePass::Opaque {
+            self.fill_count.fetch_add(fills.len(), Ordering::Relaxed);
             self.sink.listener.send(RenderCommand::AddFillsD3D9(fills));
         }
     }
 
-    fn build_tile_batches(&mut self,
-                          paint_metadata: &[PaintMetadata],
-                          prepare_mode: &PrepareMode,
-                          built_paths: Option<BuiltPaths>) {
+    // Sends the flattened outline of each visible draw path in place of its tiles. Returns false
+    // if the build was cancelled.
+    fn build_wireframe(&mut self) -> bool {
+        // Nothing is tiled, so any dirty paths wouldn't be rebuilt in the cache. Start afresh.
+        self.sink.draw_path_cache = None;
+
+        let tolerance = self.built_options.aa_quality.flattening_tolerance();
+        for display_item in self.scene.display_list() {
+            match *display_item {
+                DisplayItem::PushRenderTarget(render_target_id) => {
+                    self.sink.listener.send(RenderCommand::PushRenderTarget(render_target_id))
+                }
+                DisplayItem::PopRenderTarget => {
+                    self.sink.listener.send(RenderCommand::PopRenderTarget)
+                }
+                DisplayItem::DrawPaths(ref path_id_range) => {
+                    let paint_order = self.scene.paint_order(path_id_range.clone(),
+                                                             self.built_options);
+                    for draw_path_id in paint_order {
+                        if self.is_cancelled() {
+                            return false;
+                        }
+
+                        let draw_path = self.scene.get_draw_path(draw_path_id);
+                        if !self.scene.draw_path_is_shown(draw_path_id, self.built_options) ||
+                                self.scene.draw_path_is_culled(draw_path, self.built_options) {
+                            continue;
+                        }
+
+                        let mut outline = self.scene.apply_render_options(
+                            draw_path.fill_outline(),
+                            &draw_path.transform(),
+                            draw_path.dilation(self.built_options),
+                            self.built_options);
+                        if let Some(ref outline_filter) = self.built_options.outline_filter {
+                            outline_filter(&mut outline, draw_path_id);
+                        }
+                        let mut lines = vec![];
+                        for contour in outline.contours() {
+                            for segment in contour.iter(ContourIterFlags::empty()) {
+                                tiler::flatten_segment(&segment, tolerance, &mut |line| {
+                                    lines.push(line)
+                                });
+                            }
+                        }
+                        if !lines.is_empty() {
+                            self.sink.listener.send(RenderCommand::DebugLines {
+                                draw_path_id,
+                                lines,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // Returns false if the build was cancelled before the tile batches could be sent.
+    fn build_tile_batches<E>(&mut self,
+                             paint_metadata: &[PaintMetadata],
+                             prepare_mode: &PrepareMode,
+                             built_paths: Option<BuiltPaths>,
+                             executor: &E)
+                             -> bool
+                             where E: Executor {
         let mut tile_batch_builder = TileBatchBuilder::new(built_paths);
 
         // Prepare display items.
@@ -330,10 +1261,12 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
             match *display_item {
                 DisplayItem::PushRenderTarget(render_target_id) => {
                     tile_batch_builder.draw_commands
-                                      .push(RenderCommand::PushRenderTarget(render_target_id))
+                                      .push(RenderCommand::PushRenderTarget(render_target_id));
+                    tile_batch_builder.render_target_depth += 1;
                 }
                 DisplayItem::PopRenderTarget => {
-                    tile_batch_builder.draw_commands.push(RenderCommand::PopRenderTarget)
+                    tile_batch_builder.draw_commands.push(RenderCommand::PopRenderTarget);
+                    tile_batch_builder.render_target_depth -= 1;
                 }
                 DisplayItem::DrawPaths(ref path_id_range) => {
                     tile_batch_builder.build_tile_batches_for_draw_path_display_item(
@@ -347,20 +1280,42 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
             }
         }
 
+        self.stats.culled_tile_count +=
+            tile_batch_builder.cull_occluded_tiles(self.built_options.occlusion_policy.as_deref(),
+                                                   executor);
+        tile_batch_builder.sort_tiles(self.built_options.tile_order);
+        #[cfg(debug_assertions)]
+        tile_batch_builder.validate_tile_coords(self.scene.effective_view_box(self.built_options));
+
+        // Check for cancellation one last time. Batches are sent all at once, so the listener
+        // never sees a partial set of them.
+        if self.is_cancelled() {
+            return false;
+        }
+
         // Send commands.
-        tile_batch_builder.send_to(&self.sink);
+        tile_batch_builder.send_to(self.sink, &mut self.stats, self.overdraw.as_mut());
+        true
     }
 
-    fn finish_building(&mut self,
-                       paint_metadata: &[PaintMetadata],
-                       built_paths: Option<BuiltPaths>,
-                       prepare_mode: &PrepareMode) {
+    // Returns false if the build was cancelled.
+    fn finish_building<E>(&mut self,
+                          paint_metadata: &[PaintMetadata],
+                          built_paths: Option<BuiltPaths>,
+                          prepare_mode: &PrepareMode,
+                          executor: &E)
+                          -> bool
+                          where E: Executor {
+        if self.is_cancelled() {
+            return false;
+        }
+
         match self.sink.renderer_level {
             RendererLevel::D3D9 => self.sink.listener.send(RenderCommand::FlushFillsD3D9),
             RendererLevel::D3D11 => {}
         }
 
-        self.build_tile_batches(paint_metadata, prepare_mode, built_paths);
+        self.build_tile_batches(paint_metadata, prepare_mode, built_paths, executor)
     }
 
     fn needs_readable_framebuffer(&self) -> bool {
@@ -389,6 +1344,160 @@ impl<'a, 'b, 'c, 'd> SceneBuilder<'a, 'b, 'c, 'd> {
 
 struct BuiltPaths {
     draw: Vec<BuiltDrawPath>,
+    // The ID of the first path in `draw`. This is only nonzero when streaming tiles.
+    first_draw_path_id: DrawPathId,
+}
+
+impl BuiltPaths {
+    #[inline]
+    fn get_draw_path(&self, draw_path_id: DrawPathId) -> &BuiltDrawPath {
+        &self.draw[(draw_path_id.0 - self.first_draw_path_id.0) as usize]
+    }
+}
+
+/// Draw paths built during the last incremental build, retained so that clean paths don't have to
+/// be tiled again.
+pub(crate) struct DrawPathCache {
+    key: DrawPathCacheKey,
+    paths: Vec<Option<CachedDrawPath>>,
+}
+
+// Everything besides the paths themselves that affects tiling. If any of this changes, the cache
+// is useless.
+#[derive(Clone, Copy, PartialEq)]
+struct DrawPathCacheKey {
+    scene_id: SceneId,
+    draw_path_count: usize,
+    view_box: RectF,
+    transform: Transform2F,
+    dilation: Vector2F,
+    subpixel_aa: SubpixelAA,
+    aa_quality: AAQuality,
+    scissor: Option<RectF>,
+    min_path_size: f32,
+    grid_fit: bool,
+    conservative: bool,
+}
+
+// The path that a set of fills was generated for. Clip paths are tiled before draw paths, so they
+// sort first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FillOwner {
+    ClipPath(u32),
+    DrawPath(u32),
+}
+
+type DeferredFills = Vec<(FillOwner, Vec<Fill>)>;
+
+// A built path whose alpha tile IDs (both in the tiles and the fill links) are relative to the
+// path, so that it can be relocated into whatever range of alpha tiles is free in a later build.
+#[derive(Clone)]
+struct CachedDrawPath {
+    built_path: BuiltPath,
+    fills: Vec<Fill>,
+    alpha_tile_count: u32,
+}
+
+impl CachedDrawPath {
+    // Returns `None` if the path can't be cached. Clipped paths refer to the alpha tiles of their
+    // clip paths, which are rebuilt every time, so they aren't cached.
+    fn new(built_path: &BuiltPath, fills: &[Fill]) -> Option<CachedDrawPath> {
+        let mut built_path = (*built_path).clone();
+        let mut alpha_tile_mapping = FxHashMap::default();
+        match built_path.data {
+            BuiltPathData::CPU(ref mut cpu_data) if cpu_data.clip_tiles.is_none() => {
+                for tile in &mut cpu_data.tiles.data {
+                    if tile.alpha_tile_id.is_valid() {
+                        let local_alpha_tile_id = AlphaTileId(alpha_tile_mapping.len() as u32);
+                        alpha_tile_mapping.insert(tile.alpha_tile_id, local_alpha_tile_id);
+                        tile.alpha_tile_id = local_alpha_tile_id;
+                    }
+                }
+            }
+            _ => return None,
+        }
+
+        let fills = fills.iter().map(|fill| {
+            Fill { link: alpha_tile_mapping[&AlphaTileId(fill.link)].0, ..*fill }
+        }).collect();
+
+        Some(CachedDrawPath {
+            built_path,
+            fills,
+            alpha_tile_count: alpha_tile_mapping.len() as u32,
+        })
+    }
+}
+
+// Returns the device-space rectangle that the given outline fills if it's a single axis-aligned
+// rectangle after transformation.
+fn axis_aligned_rect(outline: &Outline, transform: &Transform2F) -> Option<RectF> {
+    let contour = match outline.contours() {
+        [contour] if contour.len() == 4 => contour,
+        _ => return None,
+    };
+
+    if (0..4).any(|point_index| !contour.flags_of(point_index).is_empty()) {
+        return None;
+    }
+    let points: Vec<Vector2F> = (0..4).map(|point_index| {
+        *transform * contour.position_of(point_index)
+    }).collect();
+
+    // Each edge must be horizontal or vertical, and the edges must alternate between the two.
+    for point_index in 0..4 {
+        let (from, to) = (points[point_index], points[(point_index + 1) % 4]);
+        let (next_from, next_to) = (to, points[(point_index + 2) % 4]);
+        let horizontal = from.y() == to.y() && from.x() != to.x();
+        let vertical = from.x() == to.x() && from.y() != to.y();
+        let next_vertical = next_from.x() == next_to.x() && next_from.y() != next_to.y();
+        if !(horizontal && next_vertical || vertical && !next_vertical) {
+            return None;
+        }
+    }
+
+    Some(RectF::from_points(points[0].min(points[2]), points[0].max(points[2])))
+}
+
+// Returns true if every tile that the path touches is covered by an occluder above it.
+fn path_is_occluded(occluders: &Occluders,
+                    draw_path_id: DrawPathId,
+                    path_bounds: RectF,
+                    view_box: RectF)
+                    -> bool {
+    let path_bounds = match path_bounds.intersection(view_box) {
+        None => return false,
+        Some(path_bounds) => path_bounds,
+    };
+    let paint_rank = occluders.paint_ranks[draw_path_id.0 as usize] as i32;
+    let tile_rect = tiles::round_rect_out_to_tile_bounds(path_bounds);
+    for tile_y in tile_rect.min_y()..tile_rect.max_y() {
+        for tile_x in tile_rect.min_x()..tile_rect.max_x() {
+            match occluders.z_buffer.get(vec2i(tile_x, tile_y)) {
+                Some(&z_value) if z_value > paint_rank => {}
+                _ => return false,
+            }
+        }
+    }
+    true
+}
+
+// If more than half of the paths are dirty, the bookkeeping isn't worth it, so we just build
+// everything again.
+fn too_dirty_to_reuse(dirty_draw_paths: &[bool]) -> bool {
+    let dirty_count = dirty_draw_paths.iter().filter(|&&dirty| dirty).count();
+    dirty_count * 2 > dirty_draw_paths.len()
+}
+
+// Multiplies the color channels by alpha, rounding to nearest.
+fn premultiply_color(color: ColorU) -> ColorU {
+    let premultiply = |channel: u8| ((channel as u32 * color.a as u32 + 127) / 255) as u8;
+    ColorU::new(premultiply(color.r), premultiply(color.g), premultiply(color.b), color.a)
+}
+
+// Multiplies the color channels by alpha, without rounding, so that the renderer can undo it.
+fn premultiply_color_f32(color: ColorF) -> ColorF {
+    ColorF::new(color.r() * color.a(), color.g() * color.a(), color.b() * color.a(), color.a())
 }
 
 struct PathBuildParams<'a> {
@@ -403,6 +1512,15 @@ struct DrawPathBuildParams<'a> {
     path_build_params: PathBuildParams<'a>,
     paint_metadata: &'a [PaintMetadata],
     built_clip_paths: &'a [BuiltPath],
+    occluders: Option<&'a Occluders>,
+}
+
+// Tiles known to be covered by opaque rectangles before tiling begins.
+struct Occluders {
+    // Maps each tile to the paint rank of the topmost path that completely covers it, or -1.
+    z_buffer: DenseTileMap<i32>,
+    // The position of each draw path in paint order.
+    paint_ranks: Vec<u32>,
 }
 
 impl BuiltPath {
@@ -517,9 +1635,10 @@ impl ObjectBuilder {
         let tile_size = F32x4::splat(TILE_WIDTH as f32);
         let tile_upper_left = tile_coords.to_f32().0.to_f32x4().xyxy() * tile_size;
 
-        // Convert to 8.8 fixed point.
+        // Convert to 8.8 fixed point. Fills may end on the far edges of the tile, or the last
+        // column of pixels would never be fully covered.
         let segment = (segment.0 - tile_upper_left) * F32x4::splat(256.0);
-        let (min, max) = (F32x4::default(), F32x4::splat((TILE_WIDTH * 256 - 1) as f32));
+        let (min, max) = (F32x4::default(), F32x4::splat((TILE_WIDTH * 256) as f32));
         let segment = segment.clamp(min, max).to_i32x4();
         let (from_x, from_y, to_x, to_y) = (segment[0], segment[1], segment[2], segment[3]);
 
@@ -782,7 +1901,12 @@ impl BuiltSegments {
             built_segments.clip_segment_ranges.push(range);
         }
         for draw_path in scene.draw_paths() {
-            let range = built_segments.draw_segments.add_path(draw_path.outline());
+            let range = if draw_path.transform().is_identity() {
+                built_segments.draw_segments.add_path(draw_path.fill_outline())
+            } else {
+                let outline = draw_path.fill_outline().clone().transformed(&draw_path.transform());
+                built_segments.draw_segments.add_path(&outline)
+            };
             built_segments.draw_segment_ranges.push(range);
         }
 
@@ -840,6 +1964,17 @@ struct TileBatchBuilder {
     clip_batches_d3d11: Option<ClipBatchesD3D11>,
     next_batch_id: TileBatchId,
     level: TileBatchBuilderLevel,
+    // The bounds, in tile coordinates, of the tiles drawn for each draw path that has any.
+    draw_path_tile_bounds: Vec<(DrawPathId, RectI)>,
+    // Maps the paint rank that tiles are tagged with back to their draw path, for each draw path
+    // that has tiles.
+    draw_path_ids_by_paint_rank: FxHashMap<u32, DrawPathId>,
+    // The solid tiles of opaque draw paths, with the index of the run of commands between render
+    // target changes that they belong to. These are only recorded if there's an occlusion policy,
+    // which takes the place of the Z-buffer.
+    occluder_tiles: Vec<(usize, (i16, i16), DrawPathId)>,
+    // The number of render targets that the paths being batched are drawn into, nested.
+    render_target_depth: usize,
 }
 
 enum TileBatchBuilderLevel {
@@ -866,6 +2001,10 @@ impl TileBatchBuilder {
                 None => TileBatchBuilderLevel::D3D11,
                 Some(built_paths) => TileBatchBuilderLevel::D3D9 { built_paths },
             },
+            draw_path_tile_bounds: vec![],
+            draw_path_ids_by_paint_rank: FxHashMap::default(),
+            occluder_tiles: vec![],
+            render_target_depth: 0,
         }
     }
 
@@ -877,8 +2016,27 @@ impl TileBatchBuilder {
                                                      paint_metadata: &[PaintMetadata],
                                                      prepare_mode: &PrepareMode) {
         let mut draw_tile_batch = None;
-        for draw_path_id in draw_path_id_range.start.0..draw_path_id_range.end.0 {
-            let draw_path_id = DrawPathId(draw_path_id);
+        let first_paint_rank = draw_path_id_range.start.0;
+        let render_target_run = if built_options.occlusion_policy.is_some() {
+            self.draw_commands.iter().filter(|command| changes_render_target(command)).count()
+        } else {
+            0
+        };
+        let paint_order = scene.paint_order(draw_path_id_range, built_options);
+        for (paint_rank_offset, draw_path_id) in paint_order.into_iter().enumerate() {
+            // Tiles are ordered in the Z-buffer by their position in paint order, which differs
+            // from their ID if Z indices were assigned.
+            let paint_rank = first_paint_rank + paint_rank_offset as u32;
+
+            // Tiles are colored by the metadata of their paint, which the global alpha doesn't
+            // fade inside render targets.
+            let paint_id = scene.get_draw_path(draw_path_id).paint();
+            let metadata_paint_id = if self.render_target_depth > 0 {
+                scene.render_target_paint(paint_id, paint_metadata)
+            } else {
+                paint_id
+            };
+
             let draw_path = match self.level {
                 TileBatchBuilderLevel::D3D11 { .. } => {
                     match self.prepare_draw_path_for_gpu_binning(scene,
@@ -887,11 +2045,14 @@ impl TileBatchBuilder {
                                                                  prepare_mode,
                                                                  paint_metadata) {
                         None => continue,
-                        Some(built_draw_path) => Cow::Owned(built_draw_path),
+                        Some(mut built_draw_path) => {
+                            built_draw_path.path.paint_id = metadata_paint_id;
+                            Cow::Owned(built_draw_path)
+                        }
                     }
                 }
                 TileBatchBuilderLevel::D3D9 { ref built_paths } => {
-                    Cow::Borrowed(&built_paths.draw[draw_path_id.0 as usize])
+                    Cow::Borrowed(built_paths.get_draw_path(draw_path_id))
                 }
             };
 
@@ -961,6 +2122,23 @@ impl TileBatchBuilder {
                 }
             };
 
+            // Announce the paint. The batch is pushed after this, so this precedes its tiles.
+            if built_options.emit_path_paints {
+                let path_id = match self.level {
+                    TileBatchBuilderLevel::D3D9 { .. } => PathId(paint_rank),
+                    TileBatchBuilderLevel::D3D11 => draw_path_id.to_path_id(),
+                };
+                let mut color = paint_metadata[metadata_paint_id.0 as usize].base_color;
+                if built_options.premultiplied_alpha {
+                    color = premultiply_color(color);
+                }
+                self.draw_commands.push(RenderCommand::SetDrawPathPaint {
+                    path_id,
+                    paint_id: paint_id.0,
+                    color,
+                });
+            }
+
             let draw_tile_batch = draw_tile_batch.as_mut().unwrap();
             match *draw_tile_batch {
                 DrawTileBatch::D3D11(ref mut draw_tile_batch) => {
@@ -976,34 +2154,68 @@ impl TileBatchBuilder {
                         TileBatchBuilderLevel::D3D11 { .. } => unreachable!(),
                     };
 
-                    let cpu_data = match built_paths.draw[draw_path_id.0 as usize].path.data {
+                    let cpu_data = match built_paths.get_draw_path(draw_path_id).path.data {
                         BuiltPathData::CPU(ref cpu_data) => cpu_data,
                         BuiltPathData::GPU | BuiltPathData::TransformCPUBinGPU(_) => {
                             unreachable!()
                         }
                     };
 
+                    let mut tile_bounds: Option<(Vector2I, Vector2I)> = None;
                     for tile in &cpu_data.tiles.data {
                         if tile.alpha_tile_id == AlphaTileId(!0) && tile.backdrop == 0 {
                             continue;
                         }
 
-                        draw_tile_batch.tiles.push(*tile);
+                        // Tiles left out of this pass still go into the Z-buffer below, so that
+                        // every pass culls the same tiles.
+                        let opaque = draw_path.occludes && tile.alpha_tile_id == AlphaTileId(!0);
+                        if built_options.tile_pass.includes(opaque) {
+                            draw_tile_batch.tiles.push(TileObjectPrimitive {
+                                path_id: PathId(paint_rank),
+                                color: metadata_paint_id.0,
+                                ..*tile
+                            });
+                        }
+
+                        let tile_coords = vec2i(tile.tile_x as i32, tile.tile_y as i32);
+                        tile_bounds = Some(match tile_bounds {
+                            None => (tile_coords, tile_coords),
+                            Some((min, max)) => (min.min(tile_coords), max.max(tile_coords)),
+                        });
 
                         if !draw_path.occludes || tile.alpha_tile_id != AlphaTileId(!0) {
                             continue;
                         }
 
-                        let tile_coords = vec2i(tile.tile_x as i32, tile.tile_y as i32);
+                        // An occlusion policy can't be expressed as a Z-buffer, so leave the
+                        // Z-buffer empty, which the GPU never culls against, and cull on CPU.
+                        if built_options.occlusion_policy.is_some() {
+                            self.occluder_tiles.push((render_target_run,
+                                                      (tile.tile_x, tile.tile_y),
+                                                      draw_path_id));
+                            continue;
+                        }
+
                         let z_value = draw_tile_batch.z_buffer_data
                                                      .get_mut(tile_coords)
                                                      .expect("Z value out of bounds!");
-                        *z_value = (*z_value).max(draw_path_id.0 as i32);
+                        debug_assert!(paint_rank as usize <= MAX_DRAW_PATHS);
+                        *z_value = (*z_value).max(paint_rank as i32);
+                    }
+
+                    if let Some((min, max)) = tile_bounds {
+                        let tile_bounds = RectI::from_points(min, max + vec2i(1, 1));
+                        self.draw_path_tile_bounds.push((draw_path_id, tile_bounds));
+                        self.draw_path_ids_by_paint_rank.insert(paint_rank, draw_path_id);
                     }
 
+                    // Clips combine masks for alpha tiles, which the opaque pass doesn't draw.
                     let clip_tiles = match cpu_data.clip_tiles {
-                        None => continue,
-                        Some(ref clip_tiles) => clip_tiles,
+                        Some(ref clip_tiles) if built_options.tile_pass != TilePass::Opaque => {
+                            clip_tiles
+                        }
+                        _ => continue,
                     };
                     for clip_tile in &clip_tiles.data {
                         if clip_tile.dest_tile_id != AlphaTileId(!0) &&
@@ -1042,8 +2254,15 @@ impl TileBatchBuilder {
 
         let effective_view_box = scene.effective_view_box(built_options);
         let draw_path = scene.get_draw_path(draw_path_id);
+        if !scene.draw_path_is_shown(draw_path_id, built_options) {
+            return None;
+        }
 
-        let mut path_bounds = transform * draw_path.outline().bounds();
+        let mut path_bounds = (transform * draw_path.transform()) *
+            draw_path.fill_outline().bounds();
+        if built_options.is_too_small(path_bounds) {
+            return None;
+        }
         match path_bounds.intersection(effective_view_box) {
             Some(intersection) => path_bounds = intersection,
             None => return None,
@@ -1061,11 +2280,111 @@ impl TileBatchBuilder {
                                             paint_id,
                                             blend_mode: draw_path.blend_mode(),
                                             fill_rule: draw_path.fill_rule(),
+                                            antialias: draw_path.antialias(),
+                                            grid_fit: built_options.grid_fit &&
+                                                draw_path.is_text(),
+                                            conservative: built_options.conservative,
                                         }));
         Some(BuiltDrawPath::new(built_path, draw_path, paint_metadata))
     }
 
-    fn send_to(self, sink: &SceneSink) {
+    // Returns the number of tiles culled.
+    //
+    // Batches between render target changes draw to the same target, so they're culled against
+    // their combined Z-buffer: a tile is dropped if a later path covers it with a solid tile, even
+    // if that path landed in a later batch. With an occlusion policy, they're culled against all
+    // of the occluders recorded for them instead.
+    fn cull_occluded_tiles<E>(&mut self,
+                              occlusion_policy: Option<&dyn OcclusionPolicy>,
+                              executor: &E)
+                              -> usize
+                              where E: Executor {
+        let mut occluders_by_run: Vec<FxHashMap<(i16, i16), Vec<DrawPathId>>> = vec![];
+        for &(render_target_run, tile_coords, draw_path_id) in &self.occluder_tiles {
+            if occluders_by_run.len() <= render_target_run {
+                occluders_by_run.resize_with(render_target_run + 1, FxHashMap::default);
+            }
+            occluders_by_run[render_target_run].entry(tile_coords).or_default().push(draw_path_id);
+        }
+
+        let mut culled_tile_count = 0;
+        let render_target_runs = self.draw_commands.split_mut(changes_render_target);
+        for (render_target_run, commands) in render_target_runs.enumerate() {
+            if let Some(occlusion_policy) = occlusion_policy {
+                let occluders = match occluders_by_run.get(render_target_run) {
+                    None => continue,
+                    Some(occluders) => occluders,
+                };
+                for command in commands {
+                    if let RenderCommand::DrawTilesD3D9(ref mut batch) = *command {
+                        let tile_count = batch.tiles.len();
+                        cull_tiles_with_occlusion_policy(batch,
+                                                         occluders,
+                                                         &self.draw_path_ids_by_paint_rank,
+                                                         occlusion_policy,
+                                                         executor);
+                        culled_tile_count += tile_count - batch.tiles.len();
+                    }
+                }
+                continue;
+            }
+
+            let z_buffer = match merge_z_buffers(commands) {
+                None => continue,
+                Some(z_buffer) => z_buffer,
+            };
+            for command in commands {
+                if let RenderCommand::DrawTilesD3D9(ref mut batch) = *command {
+                    let tile_count = batch.tiles.len();
+                    cull_occluded_tiles(batch, &z_buffer, executor);
+                    culled_tile_count += tile_count - batch.tiles.len();
+                }
+            }
+        }
+        culled_tile_count
+    }
+
+    // Sorts the tiles within each batch. Only tiles at the same position overlap, and those keep
+    // their paint order, so this never changes the rendered image.
+    fn sort_tiles(&mut self, tile_order: TileOrder) {
+        if tile_order == TileOrder::PathSorted {
+            return;
+        }
+        for command in &mut self.draw_commands {
+            if let RenderCommand::DrawTilesD3D9(ref mut batch) = *command {
+                batch.tiles.sort_by_key(|tile| (tile.morton_code(), tile.path_id.0));
+            }
+        }
+    }
+
+    // Checks that every tile about to be sent lies within the tile grid of the view box. A tile
+    // outside it would draw garbage far away from its path, so catch it here instead.
+    #[cfg(debug_assertions)]
+    fn validate_tile_coords(&self, effective_view_box: RectF) {
+        let view_box_tile_bounds = tiles::round_rect_out_to_tile_bounds(effective_view_box);
+        for command in &self.draw_commands {
+            if let RenderCommand::DrawTilesD3D9(ref batch) = *command {
+                for tile in &batch.tiles {
+                    let tile_coords = vec2i(tile.tile_x as i32, tile.tile_y as i32);
+                    assert!(view_box_tile_bounds.contains_point(tile_coords),
+                            "tile {:?} of path {:?} is outside the view box tiles {:?}",
+                            tile_coords,
+                            tile.path_id,
+                            view_box_tile_bounds);
+                }
+            }
+        }
+    }
+
+    fn send_to(self,
+               sink: &mut SceneSink,
+               stats: &mut BuildStats,
+               mut overdraw: Option<&mut OverdrawCounter>) {
+        // Keep the tile bounds of each draw path so that they can be inspected after the build.
+        for (draw_path_id, tile_bounds) in self.draw_path_tile_bounds {
+            sink.draw_path_tile_bounds[draw_path_id.0 as usize] = Some(tile_bounds);
+        }
+
         if let Some(clip_batches_d3d11) = self.clip_batches_d3d11 {
             for prepare_batch in clip_batches_d3d11.prepare_batches.into_iter().rev() {
                 if prepare_batch.path_count > 0 {
@@ -1078,11 +2397,56 @@ impl TileBatchBuilder {
             sink.listener.send(command);
         }
         for command in self.draw_commands {
+            if let Some(ref mut overdraw) = overdraw {
+                overdraw.observe(&command);
+            }
+            if let RenderCommand::DrawTilesD3D9(ref batch) = command {
+                for tile in &batch.tiles {
+                    if tile.alpha_tile_id.is_valid() {
+                        stats.alpha_tile_count += 1;
+                    } else {
+                        stats.solid_tile_count += 1;
+                    }
+
+                    // D3D9 tiles are tagged with the paint rank of their path.
+                    if let Some(&draw_path_id) = self.draw_path_ids_by_paint_rank
+                                                     .get(&tile.path_id.0) {
+                        sink.draw_path_tile_counts[draw_path_id.0 as usize] += 1;
+                    }
+                }
+            }
             sink.listener.send(command);
         }
     }
 }
 
+impl OverdrawCounter {
+    fn observe(&mut self, command: &RenderCommand) {
+        match *command {
+            RenderCommand::PushRenderTarget(_) => self.render_target_depth += 1,
+            RenderCommand::PopRenderTarget => {
+                self.render_target_depth = self.render_target_depth.saturating_sub(1)
+            }
+            RenderCommand::DrawTilesD3D9(ref batch) if self.render_target_depth == 0 => {
+                for tile in &batch.tiles {
+                    *self.tile_counts.entry((tile.tile_x, tile.tile_y)).or_insert(0) += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn stats(&self) -> OverdrawStats {
+        let mut stats = OverdrawStats::default();
+        for &count in self.tile_counts.values() {
+            stats.covered_tile_count += 1;
+            stats.drawn_tile_count += count as usize;
+            stats.histogram[(count as usize).min(OVERDRAW_HISTOGRAM_LEN) - 1] += 1;
+        }
+        stats
+    }
+}
+
 struct ClipBatchesD3D11 {
     // Will be submitted in reverse (LIFO) order.
     prepare_batches: Vec<TileBatchDataD3D11>,
@@ -1191,6 +2555,91 @@ struct PreparedClipPath {
     subclip_id: Option<GlobalPathId>,
 }
 
+fn changes_render_target(command: &RenderCommand) -> bool {
+    matches!(*command, RenderCommand::PushRenderTarget(_) | RenderCommand::PopRenderTarget)
+}
+
+// Combines the Z-buffers of the D3D9 tile batches among the given commands, keeping the topmost
+// occluder of each tile. Returns `None` if there are no such batches.
+fn merge_z_buffers(commands: &[RenderCommand]) -> Option<DenseTileMap<i32>> {
+    let mut merged_z_buffer: Option<DenseTileMap<i32>> = None;
+    for command in commands {
+        let z_buffer = match *command {
+            RenderCommand::DrawTilesD3D9(ref batch) => &batch.z_buffer_data,
+            _ => continue,
+        };
+        match merged_z_buffer {
+            None => merged_z_buffer = Some(z_buffer.clone()),
+            Some(ref mut merged_z_buffer) => {
+                // Every batch's Z-buffer covers the tiles of the view box.
+                debug_assert_eq!(merged_z_buffer.rect, z_buffer.rect);
+                for (merged_z_value, &z_value) in merged_z_buffer.data
+                                                                 .iter_mut()
+                                                                 .zip(z_buffer.data.iter()) {
+                    *merged_z_value = (*merged_z_value).max(z_value);
+                }
+            }
+        }
+    }
+    merged_z_buffer
+}
+
+// Removes tiles, alpha or solid, that are entirely hidden behind a solid tile of a later opaque
+// path, so that they never get uploaded. `z_buffer` holds the paint rank of the topmost such tile
+// at each position, across every batch drawn to the same render target. The tile vertex shader
+// performs the same test against the Z-buffer of its own batch, but doing it here saves the
+// bandwidth and vertex work, and also catches tiles hidden by later batches.
+//
+// Culled alpha tiles still have their masks rasterized, since their fills have already been sent
+// by the time the batch is complete.
+//
+// Each tile is tested independently, so the tests are spread across the executor; only the
+// compaction afterward is serial.
+fn cull_occluded_tiles<E>(batch: &mut DrawTileBatchD3D9,
+                          z_buffer: &DenseTileMap<i32>,
+                          executor: &E)
+                          where E: Executor {
+    let (tiles, z_buffer_data) = (&batch.tiles, z_buffer);
+    let tile_visibility = executor.build_vector(tiles.len(), |tile_index| {
+        let tile = &tiles[tile_index];
+        let tile_coords = vec2i(tile.tile_x as i32, tile.tile_y as i32);
+        match z_buffer_data.get(tile_coords) {
+            Some(&z_value) => tile.path_id.0 as i32 >= z_value,
+            None => true,
+        }
+    });
+
+    let mut tile_visibility = tile_visibility.into_iter();
+    batch.tiles.retain(|_| tile_visibility.next().unwrap());
+}
+
+// Removes tiles that the given occlusion policy says are hidden behind a solid tile of another
+// opaque path at the same position. `occluders` lists the paths with such a tile at each position,
+// across every batch drawn to the same render target. The Z-buffers are left empty in this case,
+// so this is the only culling these tiles get.
+fn cull_tiles_with_occlusion_policy<E>(batch: &mut DrawTileBatchD3D9,
+                                       occluders: &FxHashMap<(i16, i16), Vec<DrawPathId>>,
+                                       draw_path_ids_by_paint_rank: &FxHashMap<u32, DrawPathId>,
+                                       occlusion_policy: &dyn OcclusionPolicy,
+                                       executor: &E)
+                                       where E: Executor {
+    let tiles = &batch.tiles;
+    let tile_visibility = executor.build_vector(tiles.len(), |tile_index| {
+        let tile = &tiles[tile_index];
+        let (occluders, draw_path_id) = match (occluders.get(&(tile.tile_x, tile.tile_y)),
+                                               draw_path_ids_by_paint_rank.get(&tile.path_id.0)) {
+            (Some(occluders), Some(&draw_path_id)) => (occluders, draw_path_id),
+            _ => return true,
+        };
+        !occluders.iter().any(|&occluder| {
+            occluder != draw_path_id && occlusion_policy.occludes(occluder, draw_path_id)
+        })
+    });
+
+    let mut tile_visibility = tile_visibility.into_iter();
+    batch.tiles.retain(|_| tile_visibility.next().unwrap());
+}
+
 fn fixup_batch_for_new_path_if_possible(batch_color_texture: &mut Option<TileBatchTexture>,
                                         draw_path: &BuiltDrawPath)
                                         -> bool {
@@ -19,6 +19,23 @@ pub trait Executor {
     /// ```
     fn build_vector<T, F>(&self, length: usize, builder: F) -> Vec<T>
                           where T: Send, F: Fn(usize) -> T + Send + Sync;
+
+    /// Like `build_vector()`, but each task processes at least `min_len` consecutive indices,
+    /// like the Rayon snippet:
+    ///
+    /// ```norun
+    /// (0..length).into_par_iter().with_min_len(min_len).map(builder).collect()
+    /// ```
+    ///
+    /// This amortizes the cost of scheduling tasks when each index is cheap to process. Executors
+    /// that don't split work into tasks can ignore `min_len`, which the default implementation
+    /// does.
+    fn build_vector_with_min_len<T, F>(&self, length: usize, min_len: usize, builder: F)
+                                       -> Vec<T>
+                                       where T: Send, F: Fn(usize) -> T + Send + Sync {
+        let _ = min_len;
+        self.build_vector(length, builder)
+    }
 }
 
 /// An executor that simply executes tasks sequentially in the same thread.
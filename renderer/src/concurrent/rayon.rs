@@ -10,8 +10,9 @@
 
 //! An executor that parallelizes tasks across all CPUs using the Rayon library.
 
-use crate::concurrent::executor::Executor;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use crate::concurrent::executor::{Executor, SequentialExecutor};
+use rayon::ThreadPool;
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
 
 /// An executor that parallelizes tasks across all CPUs using the Rayon library.
 pub struct RayonExecutor;
@@ -21,4 +22,139 @@ impl Executor for RayonExecutor {
                           where T: Send, F: Fn(usize) -> T + Send + Sync {
         (0..length).into_par_iter().map(builder).collect()
     }
+
+    fn build_vector_with_min_len<T, F>(&self, length: usize, min_len: usize, builder: F)
+                                       -> Vec<T>
+                                       where T: Send, F: Fn(usize) -> T + Send + Sync {
+        (0..length).into_par_iter().with_min_len(min_len.max(1)).map(builder).collect()
+    }
+}
+
+/// An executor that parallelizes tasks across the threads of a specific Rayon thread pool, instead
+/// of the global one.
+///
+/// This is useful when embedding Pathfinder in an application that budgets its threads carefully.
+pub struct RayonThreadPoolExecutor<'a> {
+    pool: &'a ThreadPool,
+}
+
+impl<'a> RayonThreadPoolExecutor<'a> {
+    /// Creates an executor that runs all of its tasks in the given pool.
+    #[inline]
+    pub fn new(pool: &'a ThreadPool) -> RayonThreadPoolExecutor<'a> {
+        RayonThreadPoolExecutor { pool }
+    }
+}
+
+impl<'a> Executor for RayonThreadPoolExecutor<'a> {
+    fn build_vector<T, F>(&self, length: usize, builder: F) -> Vec<T>
+                          where T: Send, F: Fn(usize) -> T + Send + Sync {
+        self.pool.install(|| (0..length).into_par_iter().map(builder).collect())
+    }
+
+    fn build_vector_with_min_len<T, F>(&self, length: usize, min_len: usize, builder: F)
+                                       -> Vec<T>
+                                       where T: Send, F: Fn(usize) -> T + Send + Sync {
+        self.pool.install(|| RayonExecutor.build_vector_with_min_len(length, min_len, builder))
+    }
+}
+
+/// An executor that chooses at runtime whether to parallelize tasks across all CPUs using Rayon
+/// or to execute them sequentially in the same thread.
+///
+/// This lets a single code path serve environments with and without threads, or switch to
+/// sequential execution for debugging. To get the same render commands either way, build with
+/// `BuildOptions::deterministic` set.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RayonOrSequentialExecutor {
+    parallel: bool,
+}
+
+impl RayonOrSequentialExecutor {
+    /// Creates an executor that runs tasks in parallel if `parallel` is true and sequentially
+    /// otherwise.
+    #[inline]
+    pub fn new(parallel: bool) -> RayonOrSequentialExecutor {
+        RayonOrSequentialExecutor { parallel }
+    }
+}
+
+impl Executor for RayonOrSequentialExecutor {
+    fn build_vector<T, F>(&self, length: usize, builder: F) -> Vec<T>
+                          where T: Send, F: Fn(usize) -> T + Send + Sync {
+        if self.parallel {
+            RayonExecutor.build_vector(length, builder)
+        } else {
+            SequentialExecutor.build_vector(length, builder)
+        }
+    }
+
+    fn build_vector_with_min_len<T, F>(&self, length: usize, min_len: usize, builder: F)
+                                       -> Vec<T>
+                                       where T: Send, F: Fn(usize) -> T + Send + Sync {
+        if self.parallel {
+            RayonExecutor.build_vector_with_min_len(length, min_len, builder)
+        } else {
+            SequentialExecutor.build_vector(length, builder)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::gpu::options::RendererLevel;
+    use crate::gpu_data::RenderCommand;
+    use crate::options::BuildOptions;
+    use crate::paint::Paint;
+    use crate::scene::{ClipPath, DrawPath, Scene};
+    use pathfinder_color::ColorU;
+    use pathfinder_content::outline::Outline;
+    use pathfinder_geometry::rect::RectF;
+    use pathfinder_geometry::vector::vec2f;
+
+    use super::RayonOrSequentialExecutor;
+
+    // Builds a scene of overlapping rounded rectangles, some of them clipped, and describes the
+    // resulting commands. The `Debug` output of commands only summarizes their buffers, so fills
+    // and tiles are described in full. Build times are left out, as they vary between builds.
+    fn describe_fixture_commands(parallel: bool) -> Vec<String> {
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(256.0, 256.0)));
+        let clip_outline = Outline::from_rect(RectF::new(vec2f(32.5, 24.0), vec2f(160.0, 180.0)));
+        let clip_path = scene.push_clip_path(ClipPath::new(clip_outline));
+        for index in 0..64 {
+            let color = ColorU::new((index * 4) as u8, 0, 255 - (index * 4) as u8, 160);
+            let paint = scene.push_paint(&Paint::from_color(color));
+            let origin = vec2f((index % 8) as f32 * 27.3, (index / 8) as f32 * 29.7);
+            let outline = Outline::from_rect_rounded(RectF::new(origin, vec2f(48.0, 40.0)),
+                                                     vec2f(9.0, 7.0));
+            let mut draw_path = DrawPath::new(outline, paint);
+            if index % 3 == 0 {
+                draw_path.set_clip_path(Some(clip_path));
+            }
+            scene.push_draw_path(draw_path);
+        }
+
+        let options = BuildOptions { deterministic: true, ..BuildOptions::default() };
+        let executor = RayonOrSequentialExecutor::new(parallel);
+        let commands = scene.build_to_vec(options, RendererLevel::D3D9, &executor);
+        commands.iter().map(|command| {
+            match *command {
+                RenderCommand::AddFillsD3D9(ref fills) => format!("AddFillsD3D9({:?})", fills),
+                RenderCommand::DrawTilesD3D9(ref batch) => {
+                    format!("DrawTilesD3D9({:?}, {:?})", batch.tiles, batch.clips)
+                }
+                RenderCommand::Finish { .. } => "Finish".to_owned(),
+                ref command => format!("{:?}", command),
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn test_parallel_and_sequential_builds_match() {
+        let sequential_commands = describe_fixture_commands(false);
+        let parallel_commands = describe_fixture_commands(true);
+        assert!(sequential_commands.iter().any(|command| command.starts_with("DrawTilesD3D9")));
+        assert_eq!(parallel_commands, sequential_commands);
+    }
 }
@@ -139,7 +139,9 @@ fn scene_thread<E>(mut scene: Scene,
             MainToWorkerMsg::ReplaceScene(new_scene) => scene = new_scene,
             MainToWorkerMsg::CopyScene(sender) => sender.send(scene.clone()).unwrap(),
             MainToWorkerMsg::SetViewBox(new_view_box) => scene.set_view_box(new_view_box),
-            MainToWorkerMsg::Build(options) => scene.build(options, &mut sink, &executor),
+            MainToWorkerMsg::Build(options) => {
+                scene.build(options, &mut sink, &executor);
+            }
         }
     }
 }
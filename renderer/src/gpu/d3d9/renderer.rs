@@ -36,10 +36,9 @@ use pathfinder_gpu::{RenderOptions, RenderState, RenderTarget, StencilFunc, Sten
 use pathfinder_gpu::{TextureDataRef, TextureFormat, UniformData};
 use pathfinder_resources::ResourceLoader;
 use pathfinder_simd::default::F32x2;
+use std::mem;
 use std::u32;
 
-const MAX_FILLS_PER_BATCH: usize = 0x10000;
-
 pub(crate) struct RendererD3D9<D> where D: Device {
     // Basic data
     programs: ProgramsD3D9<D>,
@@ -154,7 +153,9 @@ impl<D> RendererD3D9<D> where D: Device {
 
         core.stats.fill_count += fill_batch.len();
 
-        let preserve_alpha_mask_contents = core.alpha_tile_count > 0;
+        let preserve_alpha_mask_contents =
+            core.alpha_tile_count > 0 ||
+            core.framebuffer_flags.contains(FramebufferFlags::MASK_FRAMEBUFFER_IS_DIRTY);
 
         self.pending_fills.reserve(fill_batch.len());
         for fill in fill_batch {
@@ -166,13 +167,43 @@ impl<D> RendererD3D9<D> where D: Device {
 
         core.reallocate_alpha_tile_pages_if_necessary(preserve_alpha_mask_contents);
 
-        if self.buffered_fills.len() + self.pending_fills.len() > MAX_FILLS_PER_BATCH {
+        let max_fills_per_batch = core.options.max_fills_per_batch;
+        debug_assert!(max_fills_per_batch.is_power_of_two(),
+                      "`max_fills_per_batch` must be a power of two!");
+        if core.options.eager_fill_batches {
+            // Top up the buffered batch and draw it as soon as it's full.
+            let pending_fills = mem::take(&mut self.pending_fills);
+            let mut remaining_fills = &pending_fills[..];
+            while !remaining_fills.is_empty() {
+                if self.buffered_fills.len() >= max_fills_per_batch {
+                    self.draw_buffered_fills(core);
+                }
+                let room = max_fills_per_batch - self.buffered_fills.len();
+                let (batch, rest) = remaining_fills.split_at(room.min(remaining_fills.len()));
+                self.buffered_fills.extend_from_slice(batch);
+                remaining_fills = rest;
+            }
+            if self.buffered_fills.len() >= max_fills_per_batch {
+                self.draw_buffered_fills(core);
+            }
+            self.pending_fills = pending_fills;
+            self.pending_fills.clear();
+            return;
+        }
+
+        if self.buffered_fills.len() + self.pending_fills.len() > max_fills_per_batch {
             self.draw_buffered_fills(core);
         }
 
         self.buffered_fills.extend(self.pending_fills.drain(..));
     }
 
+    // Drops fills that were added but never drawn, such as those of a cancelled frame.
+    pub(crate) fn discard_fills(&mut self) {
+        self.buffered_fills.clear();
+        self.pending_fills.clear();
+    }
+
     pub(crate) fn draw_buffered_fills(&mut self, core: &mut RendererCore<D>) {
         if self.buffered_fills.is_empty() {
             return;
@@ -187,9 +218,12 @@ impl<D> RendererD3D9<D> where D: Device {
         let buffered_fills = &mut self.buffered_fills;
         debug_assert!(!buffered_fills.is_empty());
 
+        // A single path can have more fills than fit in a batch, so make sure the buffer is big
+        // enough.
+        let fill_buffer_length = core.options.max_fills_per_batch.max(buffered_fills.len());
         let fill_buffer_id = core.allocator
                                  .allocate_general_buffer::<Fill>(&core.device,
-                                                                  MAX_FILLS_PER_BATCH as u64,
+                                                                  fill_buffer_length as u64,
                                                                   BufferTag("Fill"));
         let fill_vertex_buffer = core.allocator.get_general_buffer(fill_buffer_id);
         debug_assert!(buffered_fills.len() <= u32::MAX as usize);
@@ -228,7 +262,7 @@ impl<D> RendererD3D9<D> where D: Device {
 
         let mut clear_color = None;
         if !core.framebuffer_flags.contains(FramebufferFlags::MASK_FRAMEBUFFER_IS_DIRTY) {
-            clear_color = Some(ColorF::default());
+            clear_color = Some(core.mask_clear_color);
         };
 
         let timer_query = core.timer_query_cache.start_timing_draw_call(&core.device,
@@ -15,6 +15,9 @@ use pathfinder_geometry::rect::RectI;
 use pathfinder_geometry::vector::Vector2I;
 use pathfinder_gpu::{Device, FeatureLevel};
 
+/// The default value of `RendererOptions::max_fills_per_batch`.
+pub const DEFAULT_MAX_FILLS_PER_BATCH: usize = 0x10000;
+
 /// Renderer options that can't be changed after the renderer is created.
 pub struct RendererMode {
     /// The level of hardware features that the renderer will attempt to use.
@@ -30,6 +33,20 @@ pub struct RendererOptions<D> where D: Device {
     pub background_color: Option<ColorF>,
     /// Whether to display the debug UI.
     pub show_debug_ui: bool,
+    /// The maximum number of fills to buffer before drawing them, on the D3D9 level. Smaller
+    /// values use less GPU memory per batch; larger values result in fewer draw calls. Must be a
+    /// power of two.
+    pub max_fills_per_batch: usize,
+    /// Whether the D3D9 level should draw each batch of fills as soon as it holds
+    /// `max_fills_per_batch` fills, rather than waiting for fills that no longer fit.
+    ///
+    /// When commands are streamed to the renderer while the scene is still being built, for
+    /// example through `RenderCommandListener::channel()`, this starts the GPU on the fills as
+    /// early as possible so that it works while the CPU tiles the rest of the scene. Incoming fills
+    /// are split at batch boundaries, so batches never exceed `max_fills_per_batch`, even for
+    /// paths with more fills than that. The trailing partial batch is still drawn when
+    /// `FlushFillsD3D9` arrives, before the tiles that depend on it.
+    pub eager_fill_batches: bool,
 }
 
 /// The GPU API level that Pathfinder will use.
@@ -60,6 +77,8 @@ impl<D> Default for RendererOptions<D> where D: Device {
             dest: DestFramebuffer::default(),
             background_color: None,
             show_debug_ui: false,
+            max_fills_per_batch: DEFAULT_MAX_FILLS_PER_BATCH,
+            eager_fill_batches: false,
         }
     }
 }
@@ -132,6 +132,8 @@ pub(crate) struct RendererCore<D> where D: Device {
     pub(crate) mask_storage: Option<MaskStorage>,
     pub(crate) alpha_tile_count: u32,
     pub(crate) framebuffer_flags: FramebufferFlags,
+    // The color to clear the mask framebuffer to, from `RenderCommand::ClearMaskFramebuffer`.
+    pub(crate) mask_clear_color: ColorF,
 }
 
 // TODO(pcwalton): Remove this.
@@ -258,6 +260,7 @@ impl<D> Renderer<D> where D: Device {
             mask_storage: None,
             alpha_tile_count: 0,
             framebuffer_flags: FramebufferFlags::empty(),
+            mask_clear_color: ColorF::default(),
         };
 
         let level_impl = match core.mode.level {
@@ -337,9 +340,24 @@ impl<D> Renderer<D> where D: Device {
     pub fn render_command(&mut self, command: &RenderCommand) {
         debug!("render command: {:?}", command);
         match *command {
-            RenderCommand::Start { bounding_quad, path_count, needs_readable_framebuffer } => {
-                self.start_rendering(bounding_quad, path_count, needs_readable_framebuffer);
+            RenderCommand::Start {
+                bounding_quad,
+                path_count,
+                needs_readable_framebuffer,
+                preserve_mask,
+                premultiplied_alpha,
+            } => {
+                self.start_rendering(bounding_quad,
+                                     path_count,
+                                     needs_readable_framebuffer,
+                                     preserve_mask,
+                                     premultiplied_alpha);
             }
+            RenderCommand::ClearMaskFramebuffer { color } => self.core.mask_clear_color = color,
+            RenderCommand::ViewBoxChanged(_) |
+            RenderCommand::SetGlobalAlpha(_) |
+            RenderCommand::SetColorSpace(_) |
+            RenderCommand::SetDrawPathPaint { .. } => {}
             RenderCommand::AllocateTexturePage { page_id, ref descriptor } => {
                 self.allocate_pattern_texture_page(page_id, descriptor)
             }
@@ -376,6 +394,7 @@ impl<D> Renderer<D> where D: Device {
             RenderCommand::DrawTilesD3D11(ref batch) => {
                 self.level_impl.require_d3d11().prepare_and_draw_tiles(&mut self.core, batch)
             }
+            RenderCommand::DrawPathTimingSample { .. } | RenderCommand::DebugLines { .. } => {}
             RenderCommand::Finish { cpu_build_time } => {
                 self.core.stats.cpu_build_time = cpu_build_time;
             }
@@ -421,7 +440,9 @@ impl<D> Renderer<D> where D: Device {
     fn start_rendering(&mut self,
                        bounding_quad: BoundingQuad,
                        path_count: usize,
-                       needs_readable_framebuffer: bool) {
+                       needs_readable_framebuffer: bool,
+                       preserve_mask: bool,
+                       premultiplied_alpha: bool) {
         match (&self.core.options.dest, self.core.mode.level) {
             (&DestFramebuffer::Other(_), _) => {
                 self.core
@@ -445,9 +466,22 @@ impl<D> Renderer<D> where D: Device {
             self.draw_stencil(&bounding_quad);
         }
 
+        // Marking the mask framebuffer dirty keeps the first fills from clearing it.
+        if preserve_mask {
+            self.core.framebuffer_flags.insert(FramebufferFlags::MASK_FRAMEBUFFER_IS_DIRTY);
+        }
+
+        self.core.renderer_flags.set(RendererFlags::BASE_COLORS_PREMULTIPLIED, premultiplied_alpha);
+        self.core.mask_clear_color = ColorF::default();
+
         self.core.stats.path_count = path_count;
 
         self.core.render_targets.clear();
+
+        // A cancelled frame ends without flushing its fills. Don't draw them into this one.
+        if let RendererLevelImpl::D3D9(ref mut d3d9_renderer) = self.level_impl {
+            d3d9_renderer.discard_fills();
+        }
     }
 
     fn update_debug_ui(&mut self) {
@@ -658,7 +692,11 @@ impl<D> Renderer<D> where D: Device {
              TEXTURE_METADATA_TEXTURE_WIDTH * 4) as usize;
         let mut texels = Vec::with_capacity(padded_texel_size);
         for entry in metadata {
-            let base_color = entry.base_color.to_f32();
+            // The shaders expect straight alpha.
+            let mut base_color = entry.base_color;
+            if self.core.renderer_flags.contains(RendererFlags::BASE_COLORS_PREMULTIPLIED) {
+                base_color = unpremultiply_color(base_color);
+            }
             let filter_params = self.compute_filter_params(&entry.filter,
                                                            entry.blend_mode,
                                                            entry.color_0_combine_mode);
@@ -1329,7 +1367,17 @@ bitflags! {
         // This will be true if any exotic blend modes are used at the top level (not inside a
         // render target), *and* the output framebuffer is the default framebuffer.
         const INTERMEDIATE_DEST_FRAMEBUFFER_NEEDED = 0x02;
+        // Whether the base colors in the texture metadata have their alpha premultiplied.
+        const BASE_COLORS_PREMULTIPLIED = 0x04;
+    }
+}
+
+// Divides the color channels by alpha. Fully transparent colors are left alone.
+pub(crate) fn unpremultiply_color(color: ColorF) -> ColorF {
+    if color.a() == 0.0 {
+        return color;
     }
+    ColorF::new(color.r() / color.a(), color.g() / color.a(), color.b() / color.a(), color.a())
 }
 
 fn pixel_size_to_tile_size(pixel_size: Vector2I) -> Vector2I {
@@ -11,15 +11,15 @@
 //! Packed data ready to be sent to the GPU.
 
 use crate::builder::{ALPHA_TILES_PER_LEVEL, ALPHA_TILE_LEVEL_COUNT};
-use crate::options::BoundingQuad;
+use crate::options::{BoundingQuad, ColorSpace};
 use crate::paint::PaintCompositeOp;
-use crate::scene::PathId;
+use crate::scene::{DrawPathId, PathId};
 use crate::tile_map::DenseTileMap;
-use pathfinder_color::ColorU;
+use pathfinder_color::{ColorF, ColorU};
 use pathfinder_content::effects::{BlendMode, Filter};
 use pathfinder_content::render_target::RenderTargetId;
 use pathfinder_geometry::line_segment::{LineSegment2F, LineSegmentU16};
-use pathfinder_geometry::rect::RectI;
+use pathfinder_geometry::rect::{RectF, RectI};
 use pathfinder_geometry::transform2d::Transform2F;
 use pathfinder_geometry::vector::{Vector2F, Vector2I};
 use pathfinder_gpu::TextureSamplingFlags;
@@ -34,6 +34,8 @@ pub const TILE_CTRL_MASK_EVEN_ODD: i32 = 0x2;
 
 pub const TILE_CTRL_MASK_0_SHIFT:  i32 = 0;
 
+#[derive(Clone)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RenderCommand {
     // Starts rendering a frame.
     Start {
@@ -48,8 +50,55 @@ pub enum RenderCommand {
         /// This is needed if a path that renders directly to the output framebuffer (i.e. not to a
         /// render target) uses one of the more exotic blend modes.
         needs_readable_framebuffer: bool,
+
+        /// Whether the mask framebuffer should keep the contents left over from the last scene
+        /// instead of being cleared.
+        preserve_mask: bool,
+
+        /// Whether the colors in this frame's commands have their alpha premultiplied.
+        premultiplied_alpha: bool,
     },
 
+    /// Announces the effective view box of the scene, in device pixels.
+    ///
+    /// This is sent right after `Start` on the first build with a given sink, and on later builds
+    /// whenever the view box differs from the previous one, so that consumers of the command
+    /// stream can size their resources without tracking the scene themselves. The renderer
+    /// ignores it.
+    ViewBoxChanged(RectF),
+
+    /// Sets the color that the mask framebuffer is cleared to before the first fills are drawn,
+    /// from `BuildOptions::mask_clear_color`.
+    ///
+    /// This is sent after `Start` (and `ViewBoxChanged`, if any), unless `preserve_mask` is set.
+    /// A nonzero color shows up as extra coverage in every alpha tile, which is useful to visualize
+    /// overdraw or to catch mask initialization bugs. Only the D3D9 level clears the mask
+    /// framebuffer, so the D3D11 renderer ignores this.
+    ClearMaskFramebuffer { color: ColorF },
+
+    /// Announces the opacity that the whole scene is faded to, from `BuildOptions::global_alpha`.
+    ///
+    /// This is sent after `Start` (and `ViewBoxChanged`, if any), and only when the opacity is less
+    /// than 1. The builder has already folded it into the base colors that paths drawn to the main
+    /// output are colored with, so consumers mustn't apply it again. The renderer ignores it.
+    SetGlobalAlpha(f32),
+
+    /// Announces the color space that paint colors are authored in, from
+    /// `BuildOptions::color_space`.
+    ///
+    /// This is sent after `Start` (and `ViewBoxChanged`, if any) at the start of every build, and
+    /// applies to every color in the frame, including the base colors of the texture metadata that
+    /// solid and alpha tiles are colored from. Colors are never converted by the builder, so
+    /// consumers that blend in another space must convert them. The renderer ignores it.
+    SetColorSpace(ColorSpace),
+
+    /// Announces the base color of a draw path's paint, if `BuildOptions::emit_path_paints` is on.
+    ///
+    /// `path_id` is the ID that the path's tiles carry: its position in paint order for
+    /// `DrawTilesD3D9` and its draw path ID for `DrawTilesD3D11`. This is sent before the batch
+    /// containing the path's tiles. The renderer ignores it.
+    SetDrawPathPaint { path_id: PathId, paint_id: u16, color: ColorU },
+
     // Allocates a texture page.
     AllocateTexturePage { page_id: TexturePageId, descriptor: TexturePageDescriptor },
 
@@ -94,19 +143,50 @@ pub enum RenderCommand {
     // Draws a batch of tiles to the render target on top of the stack.
     DrawTilesD3D11(DrawTileBatchD3D11),
 
-    // Presents a rendered frame.
+    /// Reports how long it took to tile a single draw path on CPU.
+    ///
+    /// This is only sent if `BuildOptions::collect_timing` is set. It's purely informational, and
+    /// the renderer ignores it.
+    DrawPathTimingSample {
+        /// The draw path that was tiled.
+        draw_path_id: DrawPathId,
+        /// The time spent tiling, excluding the time spent transforming the outline.
+        tiling_time: Duration,
+    },
+
+    /// The flattened outline of a single draw path, in device space.
+    ///
+    /// When `BuildOptions::wireframe` is set, one of these is sent for each visible draw path in
+    /// paint order, in place of its tiles. The lines are the edges that tiling would have
+    /// rasterized. This is purely informational, and the renderer ignores it.
+    DebugLines {
+        /// The draw path that was flattened.
+        draw_path_id: DrawPathId,
+        /// The edges of the flattened outline.
+        lines: Vec<LineSegment2F>,
+    },
+
+    /// Presents a rendered frame.
+    ///
+    /// This is always the last command of a build, including builds that are cancelled or whose
+    /// listener refuses commands, so it can be used to delimit frames in a stream of commands
+    /// spanning several builds: everything up to and including `Finish` belongs to one frame, and
+    /// the next frame begins with `Start`.
     Finish { cpu_build_time: Duration },
 }
 
 #[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TexturePageId(pub u32);
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TexturePageDescriptor {
     pub size: Vector2I,
 }
 
 #[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TextureLocation {
     pub page: TexturePageId,
     pub rect: RectI,
@@ -114,6 +194,7 @@ pub struct TextureLocation {
 
 /// Information about a batch of tiles to be prepared (postprocessed).
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TileBatchDataD3D11 {
     /// The ID of this batch.
     /// 
@@ -135,6 +216,7 @@ pub struct TileBatchDataD3D11 {
 
 /// Where a path should come from (draw or clip).
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PathSource {
     Draw,
     Clip,
@@ -142,6 +224,7 @@ pub enum PathSource {
 
 /// Information about a batch of tiles to be prepared on GPU.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PrepareTilesInfoD3D11 {
     /// Initial backdrop values for each tile column, packed together.
     pub backdrops: Vec<BackdropInfoD3D11>,
@@ -162,6 +245,7 @@ pub struct PrepareTilesInfoD3D11 {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SegmentsD3D11 {
     pub points: Vec<Vector2F>,
     pub indices: Vec<SegmentIndicesD3D11>,
@@ -169,6 +253,7 @@ pub struct SegmentsD3D11 {
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SegmentIndicesD3D11 {
     pub first_point_index: u32,
     pub flags: u32,
@@ -176,6 +261,7 @@ pub struct SegmentIndicesD3D11 {
 
 /// Information about clips applied to paths in a batch.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClippedPathInfo {
     /// The ID of the batch containing the clips.
     pub clip_batch_id: TileBatchId,
@@ -196,10 +282,12 @@ pub struct ClippedPathInfo {
 /// 
 /// Generally, `PathIndex(!0)` represents no path.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PathBatchIndex(pub u32);
 
 /// Unique ID that identifies a batch of tiles.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TileBatchId(pub u32);
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -216,6 +304,7 @@ pub enum DrawTileBatch {
 
 /// Information needed to draw a batch of tiles in D3D9.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DrawTileBatchD3D9 {
     pub tiles: Vec<TileObjectPrimitive>,
     pub clips: Vec<Clip>,
@@ -230,6 +319,7 @@ pub struct DrawTileBatchD3D9 {
 
 /// Information needed to draw a batch of tiles in D3D11.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DrawTileBatchD3D11 {
     /// Data for the tile batch.
     pub tile_batch_data: TileBatchDataD3D11,
@@ -238,6 +328,7 @@ pub struct DrawTileBatchD3D11 {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TileBatchTexture {
     pub page: TexturePageId,
     pub sampling_flags: TextureSamplingFlags,
@@ -255,6 +346,7 @@ pub struct FillId(pub i32);
 // TODO(pcwalton): Pack better.
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TileObjectPrimitive {
     pub tile_x: i16,
     pub tile_y: i16,
@@ -288,6 +380,7 @@ pub struct AlphaTileD3D11 {
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TilePathInfoD3D11 {
     pub tile_min_x: i16,
     pub tile_min_y: i16,
@@ -303,6 +396,7 @@ pub struct TilePathInfoD3D11 {
 // TODO(pcwalton): Pack better!
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PropagateMetadataD3D11 {
     pub tile_rect: RectI,
     pub tile_offset: u32,
@@ -318,6 +412,7 @@ pub struct PropagateMetadataD3D11 {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DiceMetadataD3D11 {
     pub global_path_id: PathId,
     pub first_global_segment_index: u32,
@@ -327,16 +422,19 @@ pub struct DiceMetadataD3D11 {
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TextureMetadataEntry {
     pub color_0_transform: Transform2F,
     pub color_0_combine_mode: ColorCombineMode,
-    pub base_color: ColorU,
+    /// Kept in floating point, so that premultiplying its alpha is lossless.
+    pub base_color: ColorF,
     pub filter: Filter,
     pub blend_mode: BlendMode,
 }
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ColorCombineMode {
     None,
     SrcIn,
@@ -345,6 +443,7 @@ pub enum ColorCombineMode {
 
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Fill {
     pub line_segment: LineSegmentU16,
     // The meaning of this field depends on whether fills are being done with the GPU rasterizer or
@@ -367,6 +466,7 @@ pub struct ClipMetadata {
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Clip {
     pub dest_tile_id: AlphaTileId,
     pub dest_backdrop: i32,
@@ -398,6 +498,7 @@ pub struct BinSegment {
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BackdropInfoD3D11 {
     pub initial_backdrop: i32,
     // Column number, where 0 is the leftmost column in the tile rect.
@@ -425,8 +526,9 @@ pub(crate) struct FirstTileD3D11 {
     first_tile: i32,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 #[repr(C)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AlphaTileId(pub u32);
 
 impl PathBatchIndex {
@@ -470,6 +572,21 @@ impl Debug for RenderCommand {
     fn fmt(&self, formatter: &mut Formatter) -> DebugResult {
         match *self {
             RenderCommand::Start { .. } => write!(formatter, "Start"),
+            RenderCommand::ViewBoxChanged(view_box) => {
+                write!(formatter, "ViewBoxChanged({:?})", view_box)
+            }
+            RenderCommand::ClearMaskFramebuffer { color } => {
+                write!(formatter, "ClearMaskFramebuffer({:?})", color)
+            }
+            RenderCommand::SetGlobalAlpha(global_alpha) => {
+                write!(formatter, "SetGlobalAlpha({})", global_alpha)
+            }
+            RenderCommand::SetColorSpace(color_space) => {
+                write!(formatter, "SetColorSpace({:?})", color_space)
+            }
+            RenderCommand::SetDrawPathPaint { path_id, paint_id, color } => {
+                write!(formatter, "SetDrawPathPaint({}, {}, {:?})", path_id.0, paint_id, color)
+            }
             RenderCommand::AllocateTexturePage { page_id, descriptor: _ } => {
                 write!(formatter, "AllocateTexturePage({})", page_id.0)
             }
@@ -517,6 +634,15 @@ impl Debug for RenderCommand {
                        batch.tile_batch_data.batch_id,
                        batch.color_texture)
             }
+            RenderCommand::DrawPathTimingSample { draw_path_id, tiling_time } => {
+                write!(formatter,
+                       "DrawPathTimingSample({:?}, {} ms)",
+                       draw_path_id,
+                       tiling_time.as_secs_f64() * 1000.0)
+            }
+            RenderCommand::DebugLines { draw_path_id, ref lines } => {
+                write!(formatter, "DebugLines({:?}, x{})", draw_path_id, lines.len())
+            }
             RenderCommand::Finish { cpu_build_time } => {
                 write!(formatter, "Finish({} ms)", cpu_build_time.as_secs_f64() * 1000.0)
             }
@@ -530,3 +656,48 @@ impl Default for FirstTileD3D11 {
         FirstTileD3D11 { first_tile: -1 }
     }
 }
+
+#[cfg(all(test, feature = "pf-serde"))]
+mod test {
+    use crate::concurrent::executor::SequentialExecutor;
+    use crate::gpu::options::RendererLevel;
+    use crate::options::{BuildOptions, RenderCommandListener};
+    use crate::paint::Paint;
+    use crate::scene::{DrawPath, Scene};
+    use pathfinder_color::ColorU;
+    use pathfinder_content::outline::Outline;
+    use pathfinder_geometry::rect::RectF;
+    use pathfinder_geometry::vector::vec2f;
+    use std::sync::{Arc, Mutex};
+
+    use super::RenderCommand;
+
+    #[test]
+    fn test_serialized_commands_replay_identically() {
+        for &level in &[RendererLevel::D3D9, RendererLevel::D3D11] {
+            let mut scene = Scene::new();
+            scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(100.0, 100.0)));
+            let paint = scene.push_paint(&Paint::from_color(ColorU::new(255, 0, 0, 128)));
+            let outline = Outline::from_rect_rounded(RectF::new(vec2f(10.5, 10.5),
+                                                                vec2f(60.0, 40.0)),
+                                                     vec2f(8.0, 8.0));
+            scene.push_draw_path(DrawPath::new(outline, paint));
+
+            let commands = scene.build_to_vec(BuildOptions::default(), level, &SequentialExecutor);
+            let bytes = bincode::serialize(&commands).unwrap();
+            let deserialized: Vec<RenderCommand> = bincode::deserialize(&bytes).unwrap();
+
+            let replayed = Arc::new(Mutex::new(vec![]));
+            let replayed_for_listener = replayed.clone();
+            let listener = RenderCommandListener::new(Box::new(move |command| {
+                replayed_for_listener.lock().unwrap().push(command)
+            }));
+            listener.replay(&deserialized).unwrap();
+
+            // Render commands can't be compared directly, so compare their serialized forms.
+            let replayed = replayed.lock().unwrap();
+            assert_eq!(replayed.len(), commands.len());
+            assert_eq!(bincode::serialize(&*replayed).unwrap(), bytes);
+        }
+    }
+}
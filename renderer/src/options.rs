@@ -11,11 +11,22 @@
 //! Options that control how rendering is to be performed.
 
 use crate::gpu::options::RendererLevel;
-use crate::gpu_data::RenderCommand;
+use crate::gpu_data::{Fill, RenderCommand};
+use crate::scene::{DrawPathId, PathId, Scene};
+use crate::tiles::{TILE_HEIGHT, TILE_WIDTH};
+use crossbeam_channel::{self, Receiver};
+use pathfinder_color::ColorF;
+use pathfinder_content::outline::Outline;
 use pathfinder_geometry::rect::RectF;
 use pathfinder_geometry::transform2d::Transform2F;
-use pathfinder_geometry::transform3d::Perspective;
-use pathfinder_geometry::vector::{Vector2F, Vector4F};
+use pathfinder_geometry::transform3d::{Perspective, Transform4F};
+use pathfinder_geometry::vector::{Vector2F, Vector2I, Vector3F, Vector4F, vec2f};
+use pathfinder_simd::default::F32x4;
+use std::f32::consts::PI;
+use std::fmt::Write;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 #[allow(deprecated)]
 use pathfinder_content::clip::PolygonClipper3D;
@@ -29,47 +40,890 @@ use pathfinder_content::clip::PolygonClipper3D;
 /// concurrently. However, it requires a multithreaded environment, which may not always be
 /// available.
 pub struct RenderCommandListener<'a> {
-    send_fn: RenderCommandSendFunction<'a>,
+    send_fn: FallibleRenderCommandSendFunction<'a>,
+    failed: AtomicBool,
 }
 
 /// The callback function that receives the render commands from the scene builder.
 pub type RenderCommandSendFunction<'a> = Box<dyn Fn(RenderCommand) + Send + Sync + 'a>;
 
+/// A callback function that receives render commands and can refuse them.
+pub type FallibleRenderCommandSendFunction<'a> =
+    Box<dyn Fn(RenderCommand) -> Result<(), RenderCommandError> + Send + Sync + 'a>;
+
+/// A callback that modifies the outline of a draw path after all transforms have been applied and
+/// before the path is tiled. See `BuildOptions::outline_filter`.
+pub type OutlineFilterFunction = Arc<dyn Fn(&mut Outline, DrawPathId) + Send + Sync>;
+
+/// A callback that's notified each time a draw path finishes building. See
+/// `BuildOptions::progress`.
+pub type ProgressFunction = Arc<dyn Fn(DrawPathProgress) + Send + Sync>;
+
+/// Decides which draw paths hide which others, in place of paint order. See
+/// `BuildOptions::occlusion_policy`.
+pub trait OcclusionPolicy: Send + Sync {
+    /// Returns true if a solid tile of the opaque draw path `candidate` hides the tile of the
+    /// draw path `incumbent` at the same position, so that the latter isn't drawn.
+    fn occludes(&self, candidate: DrawPathId, incumbent: DrawPathId) -> bool;
+}
+
+/// Describes a draw path that just finished building, for progress reporting.
+#[derive(Clone, Copy, Debug)]
+pub struct DrawPathProgress {
+    /// The ID of the draw path.
+    pub draw_path_id: DrawPathId,
+    /// The number of tiles within the bounds of this draw path.
+    pub tile_count: usize,
+    /// The number of draw paths built so far in this build, including this one.
+    pub built_draw_path_count: usize,
+    /// The number of tiles within the bounds of the draw paths built so far in this build,
+    /// including this one.
+    pub built_tile_count: usize,
+}
+
+/// The error that a fallible render command callback returns when it can't accept a command,
+/// for example because a command buffer is full.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RenderCommandError;
+
 impl<'a> RenderCommandListener<'a> {
     /// Wraps a render command callback in a `RenderCommandListener`.
     #[inline]
     pub fn new(send_fn: RenderCommandSendFunction<'a>) -> RenderCommandListener<'a> {
-        RenderCommandListener { send_fn }
+        RenderCommandListener::fallible(Box::new(move |render_command| {
+            send_fn(render_command);
+            Ok(())
+        }))
+    }
+
+    /// Wraps a render command callback that can refuse commands in a `RenderCommandListener`.
+    ///
+    /// Once the callback returns an error, the scene builder stops tiling as soon as it can and
+    /// ends the frame as if the build had been cancelled: fills already sent aren't flushed, no
+    /// tiles are drawn, and `Finish` is sent. That command is still passed to the callback, which
+    /// may refuse it too. `Scene::build_cancellable()` reports such builds as
+    /// `BuildOutcome::ListenerFailed`. Each build starts afresh, so the listener can be reused once
+    /// the consumer has caught up.
+    #[inline]
+    pub fn fallible(send_fn: FallibleRenderCommandSendFunction<'a>)
+                    -> RenderCommandListener<'a> {
+        RenderCommandListener { send_fn, failed: AtomicBool::new(false) }
+    }
+
+    /// Creates a listener that discards every command.
+    ///
+    /// This is useful to measure the cost of building a scene on its own, for example in
+    /// benchmarks, or when only the side effects of building are wanted. The callback is a
+    /// zero-sized closure, so creating this listener doesn't allocate.
+    #[inline]
+    pub fn null() -> RenderCommandListener<'a> {
+        RenderCommandListener::new(Box::new(|_| {}))
+    }
+
+    /// Creates a listener that forwards every command to each of the given listeners, in order.
+    ///
+    /// This is useful to tap the command stream, for example to record commands while also
+    /// sending them to the renderer. Every listener but the last receives its own clone of each
+    /// command. Commands like `AddFillsD3D9` and `DrawTilesD3D9` can carry large buffers, so each
+    /// extra listener adds a corresponding copy.
+    ///
+    /// If one of the listeners refuses a command, the command isn't passed on to the listeners
+    /// after it, and the error is returned, so the build is abandoned as if this listener had
+    /// refused it.
+    pub fn fan_out(listeners: Vec<RenderCommandListener<'a>>) -> RenderCommandListener<'a> {
+        RenderCommandListener::fallible(Box::new(move |render_command| {
+            if let Some((last_listener, other_listeners)) = listeners.split_last() {
+                for listener in other_listeners {
+                    listener.try_send(render_command.clone())?;
+                }
+                last_listener.try_send(render_command)?;
+            }
+            Ok(())
+        }))
+    }
+
+    /// Creates a listener that sends every command into a bounded channel, along with the
+    /// receiving end of that channel.
+    ///
+    /// This decouples scene building from rendering: a consumer thread can receive commands and
+    /// upload them to the GPU while the scene is still being built. When `capacity` commands are
+    /// waiting in the channel, the building thread blocks until the consumer catches up, so
+    /// commands are never dropped or reordered. Once the receiver has been dropped, commands are
+    /// refused, so the build is abandoned as if it had been cancelled.
+    pub fn bounded_channel(capacity: usize)
+                           -> (RenderCommandListener<'a>, Receiver<RenderCommand>) {
+        let (sender, receiver) = crossbeam_channel::bounded(capacity);
+        let listener = RenderCommandListener::fallible(Box::new(move |render_command| {
+            sender.send(render_command).map_err(|_| RenderCommandError)
+        }));
+        (listener, receiver)
+    }
+
+    /// Sends previously captured render commands to this listener, in order.
+    ///
+    /// This replays a frame captured with `Scene::build_to_vec()` without building the scene
+    /// again, for example to restore the renderer's state after losing the GPU context. Each
+    /// command is cloned out of the slice, so a capture can be replayed any number of times.
+    /// Replay stops at the first command that the listener refuses, and the error is returned.
+    pub fn replay(&self, commands: &[RenderCommand]) -> Result<(), RenderCommandError> {
+        for command in commands {
+            (self.send_fn)(command.clone())?;
+        }
+        Ok(())
     }
 
     #[inline]
     pub(crate) fn send(&self, render_command: RenderCommand) {
+        if self.try_send(render_command).is_err() {
+            self.failed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Passes a command to the callback, returning the error if the callback refuses it. Adapters
+    // use this to pass refusals from the listeners they forward to back up to the builder.
+    #[inline]
+    fn try_send(&self, render_command: RenderCommand) -> Result<(), RenderCommandError> {
         (self.send_fn)(render_command)
     }
+
+    // Returns true if the callback has refused a command since the last call to `reset()`.
+    #[inline]
+    pub(crate) fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn reset(&mut self) {
+        *self.failed.get_mut() = false;
+    }
+}
+
+/// A listener adapter that packs fills from many paths into full batches.
+///
+/// Each path's fills are sent in their own `AddFillsD3D9` command, which can leave many small
+/// commands in the stream when a scene contains lots of small paths. This adapter buffers incoming
+/// fills and forwards them downstream only in batches of `max_fills_per_batch` fills. Any other
+/// command first flushes the buffered fills, so fills always arrive before the tiles that depend on
+/// them.
+///
+/// Call `finish()` once building is done to send any fills still buffered. If the downstream
+/// listener refuses a command, the adapter refuses the command that it was forwarding too.
+pub struct CoalescingListener<'a> {
+    shared: Arc<CoalescingListenerShared<'a>>,
+}
+
+struct CoalescingListenerShared<'a> {
+    downstream: RenderCommandListener<'a>,
+    pending_fills: Mutex<Vec<Fill>>,
+    max_fills_per_batch: usize,
+}
+
+impl<'a> CoalescingListener<'a> {
+    /// Creates a new coalescing listener that forwards commands to `downstream`.
+    ///
+    /// `max_fills_per_batch` should typically match `RendererOptions::max_fills_per_batch`.
+    pub fn new(downstream: RenderCommandListener<'a>, max_fills_per_batch: usize)
+               -> CoalescingListener<'a> {
+        assert!(max_fills_per_batch > 0, "`max_fills_per_batch` must be nonzero!");
+        CoalescingListener {
+            shared: Arc::new(CoalescingListenerShared {
+                downstream,
+                pending_fills: Mutex::new(vec![]),
+                max_fills_per_batch,
+            }),
+        }
+    }
+
+    /// Returns a listener that feeds commands into this adapter, suitable for a `SceneSink`.
+    pub fn listener(&self) -> RenderCommandListener<'a> {
+        let shared = self.shared.clone();
+        RenderCommandListener::fallible(Box::new(move |render_command| {
+            shared.send(render_command)
+        }))
+    }
+
+    /// Sends any buffered fills downstream, returning the error if the downstream listener
+    /// refuses them.
+    pub fn finish(&self) -> Result<(), RenderCommandError> {
+        self.shared.flush()
+    }
+}
+
+impl<'a> CoalescingListenerShared<'a> {
+    fn send(&self, render_command: RenderCommand) -> Result<(), RenderCommandError> {
+        match render_command {
+            RenderCommand::AddFillsD3D9(fills) => {
+                let mut batches = vec![];
+                {
+                    let mut pending_fills = self.pending_fills.lock().unwrap();
+                    pending_fills.extend(fills);
+                    while pending_fills.len() >= self.max_fills_per_batch {
+                        let rest = pending_fills.split_off(self.max_fills_per_batch);
+                        batches.push(mem::replace(&mut *pending_fills, rest));
+                    }
+                }
+                for batch in batches {
+                    self.downstream.try_send(RenderCommand::AddFillsD3D9(batch))?;
+                }
+                Ok(())
+            }
+            render_command => {
+                self.flush()?;
+                self.downstream.try_send(render_command)
+            }
+        }
+    }
+
+    fn flush(&self) -> Result<(), RenderCommandError> {
+        let fills = mem::take(&mut *self.pending_fills.lock().unwrap());
+        if fills.is_empty() {
+            return Ok(());
+        }
+        self.downstream.try_send(RenderCommand::AddFillsD3D9(fills))
+    }
+}
+
+/// A listener that draws the tile layout of a command stream as an approximate SVG image, for
+/// debugging.
+///
+/// Each D3D9 tile drawn to the output becomes a square on the tile grid: solid tiles are opaque
+/// and alpha tiles are half transparent, so tiles drawn over one another show through. Tiles of
+/// the same path share a color, but the colors are arbitrary and coverage isn't reconstructed.
+/// Each square's title names the path that the tile is tagged with, which for D3D9 tiles is the
+/// paint rank of its draw path rather than the draw path ID.
+/// Tiles culled by occlusion are never sent, so they're absent from the image. Tiles drawn into
+/// render targets are skipped, and D3D11 tiles are generated on GPU, so they can't be shown.
+///
+/// Only the last frame is kept: each `Start` command clears the tiles received so far. Call
+/// `finish()` once building is done to get the SVG. If a thread panicked while the listener was
+/// recording a command, later commands are refused.
+pub struct SvgListener {
+    state: Arc<Mutex<SvgListenerState>>,
+}
+
+#[derive(Default)]
+struct SvgListenerState {
+    view_box: Option<RectF>,
+    // The number of render targets pushed and not yet popped. Tiles are only drawn when this is
+    // zero.
+    render_target_depth: u32,
+    tiles: Vec<SvgTile>,
+}
+
+#[derive(Clone, Copy)]
+struct SvgTile {
+    tile_x: i16,
+    tile_y: i16,
+    path_id: PathId,
+    solid: bool,
+}
+
+impl SvgListener {
+    /// Creates a new SVG listener with no tiles.
+    #[inline]
+    pub fn new() -> SvgListener {
+        SvgListener { state: Arc::new(Mutex::new(SvgListenerState::default())) }
+    }
+
+    /// Returns a listener that feeds commands into this adapter, suitable for a `SceneSink`.
+    pub fn listener<'a>(&self) -> RenderCommandListener<'a> {
+        let state = self.state.clone();
+        RenderCommandListener::fallible(Box::new(move |render_command| {
+            let mut state = state.lock().map_err(|_| RenderCommandError)?;
+            state.receive(&render_command);
+            Ok(())
+        }))
+    }
+
+    /// Returns an SVG document showing the tiles of the last frame received.
+    ///
+    /// The image covers the view box from the last `ViewBoxChanged` command, or the bounds of the
+    /// tiles if none was received.
+    pub fn finish(&self) -> String {
+        self.state.lock().unwrap().to_svg()
+    }
+}
+
+impl Default for SvgListener {
+    #[inline]
+    fn default() -> SvgListener {
+        SvgListener::new()
+    }
+}
+
+impl SvgListenerState {
+    fn receive(&mut self, render_command: &RenderCommand) {
+        match *render_command {
+            RenderCommand::Start { .. } => {
+                self.render_target_depth = 0;
+                self.tiles.clear();
+            }
+            RenderCommand::ViewBoxChanged(view_box) => self.view_box = Some(view_box),
+            RenderCommand::PushRenderTarget(_) => self.render_target_depth += 1,
+            RenderCommand::PopRenderTarget => {
+                self.render_target_depth = self.render_target_depth.saturating_sub(1)
+            }
+            RenderCommand::DrawTilesD3D9(ref batch) if self.render_target_depth == 0 => {
+                self.tiles.extend(batch.tiles.iter().map(|tile| {
+                    SvgTile {
+                        tile_x: tile.tile_x,
+                        tile_y: tile.tile_y,
+                        path_id: tile.path_id,
+                        solid: !tile.alpha_tile_id.is_valid(),
+                    }
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    fn to_svg(&self) -> String {
+        let tile_size = vec2f(TILE_WIDTH as f32, TILE_HEIGHT as f32);
+        let view_box = self.view_box.unwrap_or_else(|| {
+            self.tiles.iter().fold(None, |bounds: Option<RectF>, tile| {
+                let origin = vec2f(tile.tile_x as f32, tile.tile_y as f32) * tile_size;
+                let tile_rect = RectF::new(origin, tile_size);
+                Some(bounds.map_or(tile_rect, |bounds| bounds.union_rect(tile_rect)))
+            }).unwrap_or_default()
+        });
+
+        // Writing to a `String` can't fail.
+        let mut svg = String::new();
+        writeln!(svg,
+                 "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" \
+                  width=\"{}\" height=\"{}\">",
+                 view_box.origin_x(),
+                 view_box.origin_y(),
+                 view_box.width(),
+                 view_box.height(),
+                 view_box.width(),
+                 view_box.height()).unwrap();
+        for tile in &self.tiles {
+            // Spread the hues of consecutive paths apart so that neighbors are easy to tell apart.
+            let hue = tile.path_id.0.wrapping_mul(137) % 360;
+            writeln!(svg,
+                     "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+                      fill=\"hsl({}, 70%, 50%)\" fill-opacity=\"{}\"><title>path {}, {}\
+                      </title></rect>",
+                     tile.tile_x as i32 * TILE_WIDTH as i32,
+                     tile.tile_y as i32 * TILE_HEIGHT as i32,
+                     TILE_WIDTH,
+                     TILE_HEIGHT,
+                     hue,
+                     if tile.solid { "1" } else { "0.5" },
+                     tile.path_id.0,
+                     if tile.solid { "solid" } else { "alpha" }).unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
 }
 
 /// Options that influence scene building.
+///
+/// Paths are tiled on CPU at the D3D9 level and on GPU at the D3D11 level. Options that act on
+/// tiles, fills, or flattened curves only have an effect when paths are tiled on CPU, so these
+/// are ignored at the D3D11 level: `collect_overdraw`, `stream_tiles`, `sort_fills_by_tile`,
+/// `preserve_mask`, `mask_clear_color`, `aa_quality`, `max_fills`, `grid_fit`, `conservative`,
+/// `tile_pass`, `tile_order`, and `occlusion_policy`, as well as `DrawPath::antialias`.
 #[derive(Clone, Default)]
 pub struct BuildOptions {
     /// A global transform to be applied to the scene.
     pub transform: RenderTransform,
+    /// True if a perspective transform should always clip the scene to the view frustum.
+    ///
+    /// By default, the clipping is skipped when all four corners of the scene bounds are in front
+    /// of the camera (that is, have a positive W after transformation), since the whole scene can
+    /// then be projected safely. That saves work and avoids the tiny seams that clipping can
+    /// introduce. Paths still aren't drawn outside the view box either way. Set this to restore
+    /// the exact frustum clip, for example when inspecting the clip polygon.
+    pub always_clip_perspective: bool,
+    /// True if the output should be flipped vertically, for backends whose framebuffer origin is
+    /// at the bottom left.
+    ///
+    /// The flip is applied after the global transform: a 2D transform is flipped about the
+    /// horizontal center line of the view box, and a perspective transform is flipped in
+    /// normalized device coordinates, so the flip composes correctly with the perspective clip
+    /// polygon and bounding quad. The clip polygon and scissor rectangle options are in the
+    /// flipped coordinate space.
+    pub flip_y: bool,
+    /// The ratio of physical to logical pixels of the display, such as 1.5 on a 1.5× display, or
+    /// `None` to treat the global transform as mapping straight to physical pixels.
+    ///
+    /// When set, the global 2D transform is taken to produce logical pixels: it's scaled by this
+    /// ratio, and its translation is then rounded to whole physical pixels. Tiles and coverage are
+    /// always computed in physical pixels, so this keeps the scene aligned with the tile grid and
+    /// with the pixel grid that `grid_fit` snaps to, instead of blurring edges that were placed on
+    /// whole logical pixels at fractional ratios. The view box, scissor, clip polygon, and dilation
+    /// remain in physical pixels. Perspective transforms already target the physical size of the
+    /// window and are left alone. Ratios that aren't positive and finite are treated as 1.
+    pub device_pixel_ratio: Option<f32>,
     /// Expands outlines by the given number of device pixels. This is useful to perform *stem
     /// darkening* for fonts, to mitigate the thinness of gamma-corrected fonts.
     pub dilation: Vector2F,
-    /// True if subpixel antialiasing for LCD screens is to be performed.
-    pub subpixel_aa_enabled: bool,
+    /// The kind of subpixel antialiasing for LCD screens to perform, if any.
+    ///
+    /// For compatibility with code that used a boolean here, `true.into()` selects horizontal RGB.
+    pub subpixel_aa: SubpixelAA,
+    /// True if a `DrawPathTimingSample` command should be sent for each draw path tiled on CPU.
+    ///
+    /// This is useful for profiling.
+    pub collect_timing: bool,
+    /// True if `BuildStats::overdraw` should count how many tiles are drawn at each tile position
+    /// of the output.
+    ///
+    /// This is useful for profiling.
+    pub collect_overdraw: bool,
+    /// True if each draw path's tiles should be sent as soon as it has been tiled, so that drawing
+    /// starts earlier, at the cost of tiling one path at a time and culling no tiles across paths.
+    pub stream_tiles: bool,
+    /// An optional polygon, in view box coordinates, that every path is clipped to after the
+    /// global transform has been applied.
+    ///
+    /// This is useful for clipping the scene to shapes other than the view box rectangle, such as
+    /// windows with rounded corners. The polygon must be convex, with its vertices listed
+    /// clockwise as seen on screen. Paths are clipped exactly, so tiles on the edge of the polygon
+    /// receive partial coverage. At the D3D11 level, setting a clip polygon moves path
+    /// transformation to the CPU.
+    pub clip_polygon: Option<Vec<Vector2F>>,
+    /// An optional rectangle, in view box coordinates, outside of which nothing is drawn.
+    ///
+    /// Unlike changing the view box, this leaves the coordinate space of the scene alone, which
+    /// makes it suitable for repainting only a damaged region of the output. Paths are clipped to
+    /// the rectangle, so tiles entirely outside it are never generated. Tiles straddling its
+    /// edges receive partial coverage unless the rectangle is aligned to tile boundaries. As with
+    /// a clip polygon, setting a scissor rectangle at the D3D11 level moves path transformation to
+    /// the CPU.
+    pub scissor: Option<RectF>,
+    /// Which pixels along the edges of the view box paths may cover.
+    ///
+    /// The default, `ViewBoxEdges::TileAligned`, is cheapest but only exact for view box edges on
+    /// tile boundaries. Use `ViewBoxEdges::HalfOpen` when adjacent view boxes must meet without
+    /// seams, for example when a scene is rendered as several panels.
+    pub view_box_edges: ViewBoxEdges,
+    /// True if the render commands should be the same no matter which executor builds the scene.
+    ///
+    /// When paths are tiled in parallel, alpha tiles are numbered and fills are sent in whatever
+    /// order the threads happen to finish in. With this option, fills are instead held back until
+    /// every path has been tiled and then sent in path order, with alpha tiles renumbered to
+    /// match, so that the output is identical to that of a sequential build. This is useful for
+    /// reproducible rendering tests. The renumbering costs an extra pass over every fill and
+    /// alpha tile, and holding back fills keeps the renderer from processing them while tiling is
+    /// still in progress.
+    pub deterministic: bool,
+    /// The fewest consecutive paths that each parallel task tiles, or `None` to let the executor
+    /// split the work as finely as it likes.
+    ///
+    /// Rayon splits work into tasks of a single path when it can, and for scenes made of many
+    /// paths that are each cheap to tile, such as small glyphs, scheduling those tasks can cost
+    /// more than the tiling itself. Setting a chunk size of a few dozen paths amortizes that
+    /// overhead. This applies to tiling draw and clip paths and to stroking, and is passed to the
+    /// executor as the `min_len` of `Executor::build_vector_with_min_len()`, so sequential
+    /// executors ignore it.
+    pub parallel_chunk_size: Option<usize>,
+    /// True if fills should be sent sorted by the alpha tile they belong to, so that fills for the
+    /// same tile are contiguous.
+    ///
+    /// This helps the cache behavior of the mask pass, at the cost of a sort. As with
+    /// `deterministic`, fills are held back until tiling is finished, then sent all at once. Fills
+    /// of streamed tiles are sent as each path is tiled, so this has no effect with
+    /// `stream_tiles`.
+    pub sort_fills_by_tile: bool,
+    /// True if the mask framebuffer should keep its contents from the previous scene instead of
+    /// being cleared before the first fills are drawn.
+    ///
+    /// Successive builds then accumulate coverage in the same mask, which enables multi-pass mask
+    /// compositing. Alpha tiles are numbered from zero in every build, so the builds accumulate
+    /// into whichever mask tiles their numbering shares. Occlusion culling is unaffected: the
+    /// Z-buffers are built per batch, so solid tiles only ever cull tiles of their own scene.
+    pub preserve_mask: bool,
+    /// The color to clear the mask framebuffer to before the first fills are drawn. The default
+    /// is transparent black, which is the only color that renders correctly.
+    ///
+    /// Fills add coverage to whatever the mask framebuffer holds, so a nonzero color shows up as
+    /// extra coverage in every alpha tile. This is a debugging aid, for overdraw heatmaps and for
+    /// isolating mask initialization bugs. The color is sent in a
+    /// `RenderCommand::ClearMaskFramebuffer` command, unless `preserve_mask` is set.
+    pub mask_clear_color: ColorF,
+    /// An opacity to apply to the whole scene, clamped to [0, 1]. `None` means fully opaque.
+    ///
+    /// This is useful to fade scenes in and out without changing their paints. The alpha is
+    /// folded into the base color of the paints of paths drawn to the main output, so both solid
+    /// and alpha tiles are faded, and solid tiles are blended rather than overwriting what's
+    /// below. Paths drawn into render targets aren't faded, so content composited from a render
+    /// target is faded once, by the paint that composites it. When the alpha is below 1, a
+    /// `SetGlobalAlpha` command is sent to announce it.
+    pub global_alpha: Option<f32>,
+    /// True if a `SetDrawPathPaint` command should be sent for each draw path, announcing the base
+    /// color of its paint.
+    ///
+    /// This is useful for consumers of the command stream other than the renderer, which would
+    /// otherwise have to resolve each tile's paint through the texture metadata. The command for
+    /// a path is always sent before any tiles that reference it.
+    pub emit_path_paints: bool,
+    /// How closely curves are approximated when paths are tiled.
+    pub aa_quality: AAQuality,
+    /// True if colors in the render commands should have their alpha premultiplied, for
+    /// consumers of the command stream that expect premultiplied alpha.
+    ///
+    /// This affects the `base_color` of each `TextureMetadataEntry` in `UploadTextureMetadata` and
+    /// the `color` of `SetDrawPathPaint`. Coverage never appears in the commands, as the renderer
+    /// computes it from the fills, so nothing else changes. The `Start` command records the
+    /// choice, and the renderer undoes the premultiplication when uploading the metadata. Base
+    /// colors are kept in floating point, so the rendered output is the same either way.
+    pub premultiplied_alpha: bool,
+    /// The color space that paint colors are authored in, and so the space that consumers of the
+    /// command stream should blend them in once they've converted them as needed.
+    ///
+    /// The builder passes colors through unchanged, so this only tags them: it's sent in a
+    /// `RenderCommand::SetColorSpace` command at the start of every build, and applies to the
+    /// colors of solid and alpha tiles alike, as both take their color from the same texture
+    /// metadata. The renderer ignores it and blends in whatever space the framebuffer uses.
+    pub color_space: ColorSpace,
+    /// The maximum number of fills that tiling may generate, or `None` for no limit. Past it, the
+    /// build is abandoned, and `Scene::build_cancellable()` reports `BuildOutcome::TooManyFills`.
+    pub max_fills: Option<usize>,
+    /// Draw paths whose bounds, after all transforms, are narrower and shorter than this many
+    /// device pixels are skipped entirely. Zero, the default, draws every path.
+    ///
+    /// This is useful for zoomed-out views of detailed scenes, such as maps, where many paths
+    /// shrink to less than a pixel and tiling them costs more than they contribute. Skipped paths
+    /// don't occlude anything. Only 2D transforms are supported; with a perspective transform,
+    /// every path is drawn. With subpixel antialiasing, widths are measured in subpixels.
+    pub min_path_size: f32,
+    /// True if draw paths with the same paint and blend mode should be drawn together where that
+    /// doesn't change the output, so that fewer batches are needed.
+    ///
+    /// Within each run of draw paths, a path is moved earlier to join the last path with its paint
+    /// only if it doesn't overlap any of the paths it would move past; otherwise it keeps its
+    /// place in Z order. Overlap is judged from tile-aligned bounds, and paths with destructive
+    /// blend modes are never moved past, so the rendered result is unchanged. Grouping costs some
+    /// CPU time, so this is off by default.
+    pub group_by_paint: bool,
+    /// True if each draw path should be sent as the edges of its flattened outline, in
+    /// `RenderCommand::DebugLines` commands, instead of being tiled.
+    ///
+    /// This is a debugging aid for inspecting curve flattening: the outlines are transformed,
+    /// clipped, and flattened exactly as for tiling on CPU, so the lines are the edges that would
+    /// have been rasterized. Nothing is drawn, so these commands are meant to be drawn as an
+    /// overlay by the consumer. At the D3D11 level, curves are flattened on GPU instead, so the
+    /// lines only approximate what would have been drawn there.
+    pub wireframe: bool,
+    /// True if the near-horizontal and near-vertical edges of draw paths flagged as text should be
+    /// snapped to the nearest pixel boundary, to sharpen small text. Diagonal edges aren't snapped.
+    pub grid_fit: bool,
+    /// True if every tile that an edge of a draw path passes through should be drawn as fully
+    /// covered, for selection halos and picking buffers. Clip paths are still rasterized exactly.
+    pub conservative: bool,
+    /// An optional callback that modifies the outline of each draw path before it's tiled, for
+    /// geometry changes that can't be expressed as a 2D or perspective transform, such as warps.
+    ///
+    /// The filter receives the outline in device pixels, after the per-path and global transforms,
+    /// dilation, and clipping have been applied, along with the ID of the draw path. It runs
+    /// once per path on the executor's worker threads, so it must be `Send + Sync`, and paths may
+    /// be filtered in any order. Clip paths aren't filtered. At the D3D11 level, setting a filter
+    /// moves path transformation to the CPU. Since filtered outlines can't be predicted, setting a
+    /// filter turns off occlusion culling before tiling and the reuse of paths across incremental
+    /// builds.
+    pub outline_filter: Option<OutlineFilterFunction>,
+    /// An optional callback that's notified each time a draw path finishes building, for
+    /// driving a progress bar.
+    ///
+    /// Like the outline filter, this runs on the executor's worker threads, so notifications may
+    /// arrive concurrently and out of order. The running counts in each notification come from
+    /// atomic counters, so each notification has distinct counts, and the one with the largest
+    /// counts is the latest. Draw paths tiled on GPU (at the D3D11 level without any option that
+    /// moves transformation to the CPU) aren't built individually and don't send notifications.
+    /// These notifications are separate from the render commands and don't affect them.
+    pub progress: Option<ProgressFunction>,
+    /// Which tiles to draw, for renderers that draw opaque and translucent geometry in separate
+    /// passes.
+    ///
+    /// The default, `TilePass::All`, draws everything. To split a frame into an opaque prepass and
+    /// a translucent pass, build the scene twice with the same sink, once with `TilePass::Opaque`
+    /// and once with `TilePass::Translucent`, using `Scene::build_incremental()` with no dirty
+    /// paths for the second build so that it reuses the tiled paths from the first. Both passes
+    /// cull occluded tiles against all of the opaque tiles in the scene and send the same
+    /// Z-buffers, so they're consistent with each other and together draw exactly what a single
+    /// build would. That relies on culling across paths, so it doesn't hold when tiles are
+    /// streamed.
+    pub tile_pass: TilePass,
+    /// The order in which tiles are drawn within each batch.
+    ///
+    /// By default, tiles are drawn path by path, in paint order. `TileOrder::Morton` sorts each
+    /// batch along a Z-order curve instead, for texture cache locality, without changing the image.
+    pub tile_order: TileOrder,
+    /// An optional policy that decides which draw paths hide which others.
+    ///
+    /// By default, a solid tile of an opaque draw path hides the tiles of every path painted
+    /// before it at the same position. A policy replaces that rule, for example to keep some
+    /// paths on top of everything or to keep some paths from hiding anything. Unless Z indices
+    /// are assigned, comparing draw path IDs reproduces the default for paths that overlap.
+    ///
+    /// The policy only decides which tiles are culled, not the order in which the rest are drawn,
+    /// so a path kept on top only shows through where the paths painted after it are culled.
+    /// Only solid tiles of opaque paths are ever offered as occluders. The policy is consulted on
+    /// the executor's worker threads. Setting a policy turns off occlusion culling before tiling.
+    pub occlusion_policy: Option<Arc<dyn OcclusionPolicy>>,
 }
 
 impl BuildOptions {
-    pub(crate) fn prepare(self, bounds: RectF) -> PreparedBuildOptions {
+    // Returns the scissor rectangle to clip paths to exactly, if any. Clipping paths exactly to a
+    // rectangle is just what half-open view box edges need, so the view box is folded in here.
+    fn prepare_scissor(&self, view_box: RectF) -> Option<RectF> {
+        match (self.view_box_edges, self.scissor) {
+            (ViewBoxEdges::TileAligned, scissor) => scissor,
+            (ViewBoxEdges::HalfOpen, None) => Some(view_box),
+            (ViewBoxEdges::HalfOpen, Some(scissor)) => {
+                Some(scissor.intersection(view_box).unwrap_or_default())
+            }
+        }
+    }
+
+    pub(crate) fn prepare(self, bounds: RectF, view_box: RectF) -> PreparedBuildOptions {
+        let subpixel_aa_scale = self.subpixel_aa.scale();
+        let scissor = self.prepare_scissor(view_box);
+        let transform = match self.device_pixel_ratio {
+            Some(ratio) if ratio > 0.0 && ratio.is_finite() => {
+                self.transform.scaled_to_physical_pixels(ratio)
+            }
+            _ => self.transform,
+        };
+        let transform = if self.flip_y { transform.flipped_y(view_box) } else { transform };
         PreparedBuildOptions {
-            transform: self.transform.prepare(bounds),
+            transform: transform.prepare(bounds, self.always_clip_perspective),
             dilation: self.dilation,
-            subpixel_aa_enabled: self.subpixel_aa_enabled,
+            subpixel_aa: self.subpixel_aa,
+            collect_timing: self.collect_timing,
+            collect_overdraw: self.collect_overdraw,
+            stream_tiles: self.stream_tiles,
+            deterministic: self.deterministic,
+            parallel_chunk_size: self.parallel_chunk_size.unwrap_or(1).max(1),
+            sort_fills_by_tile: self.sort_fills_by_tile,
+            preserve_mask: self.preserve_mask,
+            mask_clear_color: self.mask_clear_color,
+            global_alpha: self.global_alpha.map_or(1.0, |alpha| alpha.clamp(0.0, 1.0)),
+            emit_path_paints: self.emit_path_paints,
+            aa_quality: self.aa_quality,
+            premultiplied_alpha: self.premultiplied_alpha,
+            color_space: self.color_space,
+            max_fills: self.max_fills,
+            min_path_size: self.min_path_size,
+            group_by_paint: self.group_by_paint,
+            wireframe: self.wireframe,
+            grid_fit: self.grid_fit,
+            conservative: self.conservative,
+            outline_filter: self.outline_filter,
+            progress: self.progress,
+            tile_pass: self.tile_pass,
+            tile_order: self.tile_order,
+            occlusion_policy: self.occlusion_policy,
+            isolated_draw_path: None,
+            scissor: scissor.map(|scissor| scissor * subpixel_aa_scale),
+            clip_polygon: self.clip_polygon.map(|clip_polygon| {
+                // Subpixel AA oversamples device space, so scale the polygon to match.
+                clip_polygon.into_iter().map(|point| point * subpixel_aa_scale).collect()
+            }),
+            bounds,
+            view_box,
+        }
+    }
+}
+
+/// The subpixel layout of the LCD panel that subpixel antialiasing is performed for.
+///
+/// Subpixel antialiasing renders paths at three times the horizontal resolution. The resulting
+/// coverage is turned into per-channel values by the defringing kernel of `PatternFilter::Text`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SubpixelAA {
+    /// No subpixel antialiasing; grayscale antialiasing only.
+    None,
+    /// Subpixels are laid out horizontally, red first. This is the most common layout.
+    HorizontalRGB,
+}
+
+impl Default for SubpixelAA {
+    #[inline]
+    fn default() -> SubpixelAA {
+        SubpixelAA::None
+    }
+}
+
+impl From<bool> for SubpixelAA {
+    /// Converts the old `subpixel_aa_enabled` flag: `true` means horizontal RGB.
+    #[inline]
+    fn from(enabled: bool) -> SubpixelAA {
+        if enabled {
+            SubpixelAA::HorizontalRGB
+        } else {
+            SubpixelAA::None
+        }
+    }
+}
+
+impl SubpixelAA {
+    /// Returns true if subpixel antialiasing is to be performed at all.
+    #[inline]
+    pub fn is_enabled(self) -> bool {
+        self != SubpixelAA::None
+    }
+
+    /// The factor by which paths are scaled before tiling.
+    #[inline]
+    pub fn scale(self) -> Vector2F {
+        match self {
+            SubpixelAA::None => vec2f(1.0, 1.0),
+            SubpixelAA::HorizontalRGB => vec2f(3.0, 1.0),
+        }
+    }
+}
+
+/// The policy for which pixels along the edges of the view box paths may cover.
+///
+/// Paths are tiled in scene space, so unless the view box starts and ends on tile boundaries, the
+/// tiles along its edges also cover pixels just outside of it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ViewBoxEdges {
+    /// Paths are only cut off at the view box where their edges cross it, and otherwise extend to
+    /// the boundaries of the tiles that the view box overlaps.
+    ///
+    /// Tiles straddling an edge can then cover pixels just outside of the view box, and where a
+    /// path's own edges were cut off outside of it, tiles straddling the right or bottom edge can
+    /// miss pixels just inside. Two view boxes sharing an edge that isn't on a tile boundary can
+    /// therefore both cover, or both miss, the pixels next to it. This costs nothing extra.
+    TileAligned,
+    /// Paths are clipped exactly to the view box, which covers the half-open ranges
+    /// `[min_x, max_x)` and `[min_y, max_y)`: the pixels just inside its left and top edges belong
+    /// to it, and the pixels just outside its right and bottom edges belong to its neighbors. A
+    /// pixel that an edge passes through is covered in proportion to the part of it inside.
+    ///
+    /// View boxes that share an edge then split the coverage of the pixels along it exactly, with
+    /// none covered twice or missed. This clips every path against the view box, as a scissor
+    /// rectangle does, so at the D3D11 level it moves path transformation to the CPU.
+    HalfOpen,
+}
+
+impl Default for ViewBoxEdges {
+    #[inline]
+    fn default() -> ViewBoxEdges {
+        ViewBoxEdges::TileAligned
+    }
+}
+
+/// Which tiles a build draws. See `BuildOptions::tile_pass`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TilePass {
+    /// All tiles are drawn.
+    All,
+    /// Only the solid tiles of opaque draw paths are drawn. These completely cover the pixels
+    /// under them, so a depth prepass can draw them in any order. No fills are sent.
+    Opaque,
+    /// Every tile that `TilePass::Opaque` leaves out is drawn: alpha tiles, which need the fills
+    /// that are sent with them, and the solid tiles of translucent draw paths.
+    Translucent,
+}
+
+impl Default for TilePass {
+    #[inline]
+    fn default() -> TilePass {
+        TilePass::All
+    }
+}
+
+impl TilePass {
+    // Returns true if this pass draws tiles that are, or aren't, solid tiles of opaque paths.
+    #[inline]
+    pub(crate) fn includes(self, opaque: bool) -> bool {
+        match self {
+            TilePass::All => true,
+            TilePass::Opaque => opaque,
+            TilePass::Translucent => !opaque,
         }
     }
 }
 
+/// The color space that paint colors are expressed in. See `BuildOptions::color_space`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorSpace {
+    /// Colors are sRGB-encoded, as colors in CSS and SVG are.
+    Srgb,
+    /// Colors are linear.
+    Linear,
+}
+
+impl Default for ColorSpace {
+    #[inline]
+    fn default() -> ColorSpace {
+        ColorSpace::Srgb
+    }
+}
+
+/// The order of tiles within a batch. See `BuildOptions::tile_order`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TileOrder {
+    /// Tiles are grouped by draw path, in paint order.
+    PathSorted,
+    /// Tiles are sorted by the Morton code of their coordinates (see `tiles::morton_code()`), and
+    /// tiles at the same position by paint order.
+    Morton,
+}
+
+impl Default for TileOrder {
+    #[inline]
+    fn default() -> TileOrder {
+        TileOrder::PathSorted
+    }
+}
+
+/// How closely curves are approximated when paths are tiled on CPU.
+///
+/// Coverage within a tile is always computed analytically, so this doesn't change how edges are
+/// antialiased. Instead, it controls how finely curves are flattened into lines before tiling:
+/// coarser flattening produces fewer fills but can show facets on large curves.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AAQuality {
+    /// Curves may deviate from their lines by up to one pixel. This is suitable for thumbnails.
+    Fast,
+    /// Curves may deviate from their lines by up to a quarter of a pixel.
+    Normal,
+    /// Curves may deviate from their lines by up to a sixteenth of a pixel.
+    High,
+    /// Curves may deviate from their lines by up to the given number of device pixels.
+    ///
+    /// Flattening happens after the global transform, so the tolerance is the same on screen at
+    /// every zoom level. Tolerances below 1/256 of a pixel, the precision of fills, would only
+    /// multiply the number of lines without improving the output, so they're raised to that.
+    Custom(f32),
+}
+
+impl Default for AAQuality {
+    #[inline]
+    fn default() -> AAQuality {
+        AAQuality::Normal
+    }
+}
+
+impl AAQuality {
+    /// The maximum distance, in device pixels, between a curve and the lines approximating it.
+    ///
+    /// This is never less than `MIN_FLATTENING_TOLERANCE`, which bounds how many times curves are
+    /// subdivided.
+    #[inline]
+    pub(crate) fn flattening_tolerance(self) -> f32 {
+        match self {
+            AAQuality::Fast => 1.0,
+            AAQuality::Normal => 0.25,
+            AAQuality::High => 0.0625,
+            AAQuality::Custom(tolerance) => tolerance.max(MIN_FLATTENING_TOLERANCE),
+        }
+    }
+}
+
+// The smallest flattening tolerance that makes a difference: fills store their endpoints in
+// units of 1/256 of a pixel.
+const MIN_FLATTENING_TOLERANCE: f32 = 1.0 / 256.0;
+
 /// A global transform to apply to the scene.
 #[derive(Clone)]
 pub enum RenderTransform {
@@ -87,8 +941,164 @@ impl Default for RenderTransform {
 }
 
 impl RenderTransform {
+    /// Maps a point in device pixels back into scene coordinates by undoing this transform. This
+    /// is useful for hit testing.
+    ///
+    /// Returns `None` if there's no such point: if the transform is degenerate, or, for a
+    /// perspective transform, if the ray through the point never hits the plane of the scene in
+    /// front of the viewer (for example, because the point lies above the horizon).
+    pub fn unproject(&self, point: Vector2F) -> Option<Vector2F> {
+        match *self {
+            RenderTransform::Transform2D(ref transform) => {
+                if transform.matrix.det() == 0.0 {
+                    return None;
+                }
+                Some(transform.inverse() * point)
+            }
+            RenderTransform::Perspective(ref perspective) => {
+                unproject_perspective(perspective, point)
+            }
+        }
+    }
+
+    /// For a perspective transform, returns the polygon, in scene coordinates, that remains after
+    /// the scene bounds are clipped to the view frustum. Only the parts of paths inside this
+    /// polygon are rendered. This is useful for visualizing perspective clipping.
+    ///
+    /// `scene_bounds` should be the bounds of the scene, as returned by `Scene::bounds()`. As when
+    /// building without `BuildOptions::always_clip_perspective`, scene bounds entirely in front of
+    /// the camera are returned unclipped.
+    ///
+    /// Returns `None` for 2D transforms, which don't clip.
+    pub fn clip_polygon(&self, scene_bounds: RectF) -> Option<Vec<Vector2F>> {
+        match self.prepare(scene_bounds, false) {
+            PreparedRenderTransform::Perspective { clip_polygon, .. } => Some(clip_polygon),
+            PreparedRenderTransform::None | PreparedRenderTransform::Transform2D(_) => None,
+        }
+    }
+
+    /// Returns a transform that applies this transform and then `other`.
+    ///
+    /// Two 2D transforms compose into a 2D transform. A 2D transform before a perspective
+    /// transform is folded into the perspective matrix, and so is a 2D transform after one, which
+    /// then applies to device pixels. Two perspective transforms compose into one that projects
+    /// the output of the first, in device pixels, onto the scene plane of the second; the result
+    /// only clips against the view frustum of the second. Perspective results keep the window size
+    /// of the last perspective transform.
+    pub fn then(&self, other: &RenderTransform) -> RenderTransform {
+        match (self, other) {
+            (RenderTransform::Transform2D(first), RenderTransform::Transform2D(second)) => {
+                RenderTransform::Transform2D(*second * *first)
+            }
+            (RenderTransform::Transform2D(first), RenderTransform::Perspective(second)) => {
+                RenderTransform::Perspective(Perspective {
+                    transform: second.transform * transform_2d_to_4d(first),
+                    ..*second
+                })
+            }
+            (RenderTransform::Perspective(first), RenderTransform::Transform2D(second)) => {
+                // Move into device pixels to apply the 2D transform, then back.
+                let viewport = viewport_transform(first.window_size);
+                let second = viewport.inverse() * *second * viewport;
+                RenderTransform::Perspective(Perspective {
+                    transform: transform_2d_to_4d(&second) * first.transform,
+                    ..*first
+                })
+            }
+            (RenderTransform::Perspective(first), RenderTransform::Perspective(second)) => {
+                // Take the output of the first projection to device pixels, flattened onto the
+                // plane Z = 0, where the second projection expects scene coordinates.
+                let viewport = transform_2d_to_4d(&viewport_transform(first.window_size));
+                let flatten = Transform4F::from_scale(Vector4F::new(1.0, 1.0, 0.0, 1.0));
+                RenderTransform::Perspective(Perspective {
+                    transform: second.transform * flatten * viewport * first.transform,
+                    ..*second
+                })
+            }
+        }
+    }
+
+    /// Returns the transform `t` of the way from this transform to `other`, for animating between
+    /// two camera poses. This transform is returned at 0 and `other` at 1; values outside that
+    /// range extrapolate.
+    ///
+    /// Interpolating matrix entries directly would shrink and skew the scene partway through a
+    /// rotation, so both transforms are instead decomposed into translation, rotation, and an
+    /// upper triangular scale and shear matrix, which are interpolated separately, with rotations
+    /// taking the shorter way around. Perspective transforms are also decomposed into a projection
+    /// and the window size is interpolated as well. Interpolating between a 2D transform and a
+    /// perspective transform falls back to interpolating the matrix entries of the 2D
+    /// transform's perspective equivalent, so it can distort the scene partway and always returns
+    /// a perspective transform. The same fallback is used for perspective transforms that squash
+    /// the scene to a line or a point and so can't be decomposed.
+    pub fn interpolate(&self, other: &RenderTransform, t: f32) -> RenderTransform {
+        let (from, to) = match (self, other) {
+            (RenderTransform::Transform2D(from), RenderTransform::Transform2D(to)) => {
+                let (from, to) = (DecomposedTransform2F::new(from), DecomposedTransform2F::new(to));
+                return RenderTransform::Transform2D(from.lerp(&to, t).compose());
+            }
+            (RenderTransform::Transform2D(from), RenderTransform::Perspective(to)) => {
+                (perspective_from_2d(from, to.window_size), *to)
+            }
+            (RenderTransform::Perspective(from), RenderTransform::Transform2D(to)) => {
+                (*from, perspective_from_2d(to, from.window_size))
+            }
+            (RenderTransform::Perspective(from), RenderTransform::Perspective(to)) => {
+                (*from, *to)
+            }
+        };
+
+        let window_size = from.window_size.to_f32().lerp(to.window_size.to_f32(), t);
+        let transform = match (self, other) {
+            (RenderTransform::Perspective(_), RenderTransform::Perspective(_)) => {
+                match (DecomposedTransform4F::new(&from.transform),
+                       DecomposedTransform4F::new(&to.transform)) {
+                    (Some(from), Some(to)) => Some(from.lerp(&to, t).compose()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let transform = transform.unwrap_or_else(|| from.transform.lerp(1.0 - t, &to.transform));
+        RenderTransform::Perspective(Perspective::new(&transform, window_size.round().to_i32()))
+    }
+
+    // Returns this transform followed by a scale from logical to physical pixels, with the
+    // translation rounded to whole physical pixels. Perspective transforms are returned unchanged.
+    fn scaled_to_physical_pixels(self, device_pixel_ratio: f32) -> RenderTransform {
+        match self {
+            RenderTransform::Transform2D(transform) => {
+                let transform = Transform2F::from_scale(device_pixel_ratio) * transform;
+                RenderTransform::Transform2D(Transform2F {
+                    matrix: transform.matrix,
+                    vector: transform.vector.round(),
+                })
+            }
+            RenderTransform::Perspective(_) => self,
+        }
+    }
+
+    // Returns this transform followed by a vertical flip of the output.
+    fn flipped_y(self, view_box: RectF) -> RenderTransform {
+        match self {
+            RenderTransform::Transform2D(transform) => {
+                let flip_origin = vec2f(0.0, view_box.min_y() + view_box.max_y());
+                let flip = Transform2F::from_translation(flip_origin) *
+                    Transform2F::from_scale(vec2f(1.0, -1.0));
+                RenderTransform::Transform2D(flip * transform)
+            }
+            RenderTransform::Perspective(perspective) => {
+                let flip = Transform4F::from_scale(Vector4F::new(1.0, -1.0, 1.0, 1.0));
+                RenderTransform::Perspective(Perspective {
+                    transform: flip * perspective.transform,
+                    ..perspective
+                })
+            }
+        }
+    }
+
     #[allow(deprecated)]
-    fn prepare(&self, bounds: RectF) -> PreparedRenderTransform {
+    fn prepare(&self, bounds: RectF, always_clip: bool) -> PreparedRenderTransform {
         let perspective = match self {
             RenderTransform::Transform2D(ref transform) => {
                 if transform.is_identity() {
@@ -99,12 +1109,13 @@ impl RenderTransform {
             RenderTransform::Perspective(ref perspective) => *perspective,
         };
 
-        let mut points = vec![
-            bounds.origin().to_4d(),
-            bounds.upper_right().to_4d(),
-            bounds.lower_right().to_4d(),
-            bounds.lower_left().to_4d(),
+        let original_points = [
+            bounds.origin(),
+            bounds.upper_right(),
+            bounds.lower_right(),
+            bounds.lower_left(),
         ];
+        let mut points: Vec<_> = original_points.iter().map(|point| point.to_4d()).collect();
         debug!("-----");
         debug!("bounds={:?} ORIGINAL quad={:?}", bounds, points);
         for point in &mut points {
@@ -121,6 +1132,15 @@ impl RenderTransform {
         ];
         debug!("... PERSPECTIVE-DIVIDED points = {:?}", quad);
 
+        // If the whole quad is in front of the camera, it can be projected without clipping.
+        if !always_clip && points.iter().all(|point| point.w() > 0.0) {
+            return PreparedRenderTransform::Perspective {
+                perspective,
+                clip_polygon: original_points.to_vec(),
+                quad,
+            };
+        }
+
         points = PolygonClipper3D::new(points).clip();
         debug!("... CLIPPED quad={:?}", points);
         for point in &mut points {
@@ -139,10 +1159,322 @@ impl RenderTransform {
     }
 }
 
-pub(crate) struct PreparedBuildOptions {
+// Casts a ray through the given window point and intersects it with the Z = 0 plane, which is
+// where the scene lies.
+fn unproject_perspective(perspective: &Perspective, point: Vector2F) -> Option<Vector2F> {
+    // Undo the viewport transform that `Perspective` applies.
+    let window_size = perspective.window_size.to_f32();
+    let ndc_x = point.x() / window_size.x() * 2.0 - 1.0;
+    let ndc_y = 1.0 - point.y() / window_size.y() * 2.0;
+
+    let inverse_transform = perspective.transform.inverse();
+    let near = inverse_transform * Vector4F::new(ndc_x, ndc_y, -1.0, 1.0);
+    let far = inverse_transform * Vector4F::new(ndc_x, ndc_y, 1.0, 1.0);
+    if near.w() == 0.0 || far.w() == 0.0 {
+        return None;
+    }
+    let (near, far) = (near.to_3d(), far.to_3d());
+
+    // If the ray is parallel to the scene plane, it never hits it.
+    let delta_z = far.z() - near.z();
+    if delta_z.abs() < UNPROJECT_EPSILON {
+        return None;
+    }
+    let t = -near.z() / delta_z;
+    let hit = vec2f(near.x() + (far.x() - near.x()) * t, near.y() + (far.y() - near.y()) * t);
+
+    // Make sure the hit is in front of the viewer.
+    let projected_hit = perspective.transform * hit.to_4d();
+    if projected_hit.w() <= 0.0 || !hit.x().is_finite() || !hit.y().is_finite() {
+        return None;
+    }
+    Some(hit)
+}
+
+const UNPROJECT_EPSILON: f32 = 1e-6;
+
+// Returns the transform from normalized device coordinates to device pixels that `Perspective`
+// applies after projecting.
+fn viewport_transform(window_size: Vector2I) -> Transform2F {
+    let half_window_size = window_size.to_f32() * 0.5;
+    Transform2F::from_translation(half_window_size) *
+        Transform2F::from_scale(half_window_size * vec2f(1.0, -1.0))
+}
+
+// Returns a 3D transform that applies the given 2D transform to X and Y, including in homogeneous
+// coordinates, and leaves Z and W alone.
+fn transform_2d_to_4d(transform: &Transform2F) -> Transform4F {
+    Transform4F::row_major(transform.m11(), transform.m12(), 0.0, transform.m13(),
+                           transform.m21(), transform.m22(), 0.0, transform.m23(),
+                           0.0,             0.0,             1.0, 0.0,
+                           0.0,             0.0,             0.0, 1.0)
+}
+
+// Returns the perspective transform, with the given window size, equivalent to the given 2D
+// transform.
+fn perspective_from_2d(transform: &Transform2F, window_size: Vector2I) -> Perspective {
+    let transform = viewport_transform(window_size).inverse() * *transform;
+    Perspective::new(&transform_2d_to_4d(&transform), window_size)
+}
+
+// A 2D transform split into a translation, then a rotation, then an upper triangular matrix
+// combining scale and shear. Each part can be interpolated on its own.
+struct DecomposedTransform2F {
+    translation: Vector2F,
+    rotation: f32,
+    // The upper triangular matrix, as its top left, top right, and bottom right entries.
+    scale_shear: Vector4F,
+}
+
+impl DecomposedTransform2F {
+    fn new(transform: &Transform2F) -> DecomposedTransform2F {
+        // The first column of the upper triangular matrix is (scale, 0), so the rotation is just
+        // the angle of the first column of the transform. This works even for singular
+        // transforms.
+        let rotation = f32::atan2(transform.m21(), transform.m11());
+        let (sin, cos) = rotation.sin_cos();
+        let column_0 = vec2f(transform.m11(), transform.m21());
+        let column_1 = vec2f(transform.m12(), transform.m22());
+        let (axis_0, axis_1) = (vec2f(cos, sin), vec2f(-sin, cos));
+        DecomposedTransform2F {
+            translation: transform.translation(),
+            rotation,
+            scale_shear: Vector4F::new(column_0.dot(axis_0),
+                                       column_1.dot(axis_0),
+                                       column_1.dot(axis_1),
+                                       0.0),
+        }
+    }
+
+    fn lerp(&self, other: &DecomposedTransform2F, t: f32) -> DecomposedTransform2F {
+        // Take the shorter way around.
+        let mut rotation_delta = (other.rotation - self.rotation) % (2.0 * PI);
+        if rotation_delta > PI {
+            rotation_delta -= 2.0 * PI;
+        } else if rotation_delta < -PI {
+            rotation_delta += 2.0 * PI;
+        }
+        DecomposedTransform2F {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation + rotation_delta * t,
+            scale_shear: self.scale_shear.lerp(other.scale_shear, t),
+        }
+    }
+
+    fn compose(&self) -> Transform2F {
+        let scale_shear = Transform2F::row_major(self.scale_shear.x(), self.scale_shear.y(), 0.0,
+                                                 0.0,                  self.scale_shear.z(), 0.0);
+        Transform2F::from_translation(self.translation) *
+            Transform2F::from_rotation(self.rotation) *
+            scale_shear
+    }
+}
+
+// A 3D transform split into a projection, then an upper triangular matrix combining scale and
+// shear, then a translation, then a rotation. Each part can be interpolated on its own.
+//
+// For a camera transform, which is a projection applied to a rigid view transform, the
+// projection lands in the first two parts, and the translation and rotation are exactly the
+// view transform, so moving the camera interpolates naturally.
+struct DecomposedTransform4F {
+    // The bottom row of the projection matrix, which is otherwise the identity.
+    projection: Vector4F,
+    // The upper triangular matrix, as its columns.
+    scale_shear: [Vector4F; 3],
+    translation: Vector4F,
+    // A unit quaternion, as expected by `Transform4F::from_rotation_quaternion()`.
+    rotation: F32x4,
+}
+
+impl DecomposedTransform4F {
+    // Returns `None` if the upper left 3x3 matrix of the transform is singular.
+    fn new(transform: &Transform4F) -> Option<DecomposedTransform4F> {
+        let columns = transform.to_columns();
+        let column = |index: usize| Vector3F(columns[index] * F32x4::new(1.0, 1.0, 1.0, 0.0));
+        let (column_0, column_1, column_2) = (column(0), column(1), column(2));
+        let translation = column(3);
+        let bottom_row = Vector4F::new(columns[0].w(), columns[1].w(), columns[2].w(),
+                                       columns[3].w());
+
+        // The projection only changes the bottom row, so solve for the bottom row of the
+        // projection matrix that produces it.
+        let det = dot_3d(column_0, column_1.cross(column_2));
+        if det.abs() < DECOMPOSE_EPSILON {
+            return None;
+        }
+        let projection = (column_1.cross(column_2).scale(bottom_row.x()) +
+                          column_2.cross(column_0).scale(bottom_row.y()) +
+                          column_0.cross(column_1).scale(bottom_row.z())).scale(1.0 / det);
+        let projection = Vector4F::new(projection.x(),
+                                       projection.y(),
+                                       projection.z(),
+                                       bottom_row.w() - dot_3d(projection, translation));
+
+        // Factor the upper left matrix into an upper triangular matrix times a rotation by
+        // Gram-Schmidt orthogonalizing its rows, from the bottom up. If it's a reflection, flip
+        // the first row of the rotation so that the rotation is proper.
+        let row = |index: usize| {
+            Vector3F::new(columns[0][index], columns[1][index], columns[2][index])
+        };
+        let (row_0, row_1, row_2) = (row(0), row(1), row(2));
+        let axis_2 = row_2.normalize();
+        let axis_1 = (row_1 - axis_2.scale(dot_3d(row_1, axis_2))).normalize();
+        let mut axis_0 = (row_0 - axis_2.scale(dot_3d(row_0, axis_2)) -
+                          axis_1.scale(dot_3d(row_0, axis_1))).normalize();
+        if det < 0.0 {
+            axis_0 = -axis_0;
+        }
+        let (u00, u01, u02) = (dot_3d(row_0, axis_0), dot_3d(row_0, axis_1), dot_3d(row_0, axis_2));
+        let (u11, u12, u22) = (dot_3d(row_1, axis_1), dot_3d(row_1, axis_2), dot_3d(row_2, axis_2));
+
+        // Move the translation past the upper triangular matrix by back substitution.
+        let z = translation.z() / u22;
+        let y = (translation.y() - u12 * z) / u11;
+        let x = (translation.x() - u01 * y - u02 * z) / u00;
+
+        Some(DecomposedTransform4F {
+            projection,
+            scale_shear: [
+                Vector4F::new(u00, 0.0, 0.0, 0.0),
+                Vector4F::new(u01, u11, 0.0, 0.0),
+                Vector4F::new(u02, u12, u22, 0.0),
+            ],
+            translation: Vector4F::new(x, y, z, 1.0),
+            rotation: quaternion_from_rows(axis_0, axis_1, axis_2),
+        })
+    }
+
+    fn lerp(&self, other: &DecomposedTransform4F, t: f32) -> DecomposedTransform4F {
+        DecomposedTransform4F {
+            projection: self.projection.lerp(other.projection, t),
+            scale_shear: [
+                self.scale_shear[0].lerp(other.scale_shear[0], t),
+                self.scale_shear[1].lerp(other.scale_shear[1], t),
+                self.scale_shear[2].lerp(other.scale_shear[2], t),
+            ],
+            translation: self.translation.lerp(other.translation, t),
+            rotation: slerp_quaternions(self.rotation, other.rotation, t),
+        }
+    }
+
+    fn compose(&self) -> Transform4F {
+        let (p, s) = (self.projection, &self.scale_shear);
+        let projection = Transform4F::row_major(1.0,   0.0,   0.0,   0.0,
+                                                0.0,   1.0,   0.0,   0.0,
+                                                0.0,   0.0,   1.0,   0.0,
+                                                p.x(), p.y(), p.z(), p.w());
+        let scale_shear = Transform4F {
+            c0: s[0].0,
+            c1: s[1].0,
+            c2: s[2].0,
+            c3: F32x4::new(0.0, 0.0, 0.0, 1.0),
+        };
+        projection *
+            scale_shear *
+            Transform4F::from_translation(self.translation) *
+            Transform4F::from_rotation_quaternion(self.rotation)
+    }
+}
+
+const DECOMPOSE_EPSILON: f32 = 1e-6;
+
+#[inline]
+fn dot_3d(a: Vector3F, b: Vector3F) -> f32 {
+    a.x() * b.x() + a.y() * b.y() + a.z() * b.z()
+}
+
+#[inline]
+fn dot_4d(a: F32x4, b: F32x4) -> f32 {
+    let products = a * b;
+    products.x() + products.y() + products.z() + products.w()
+}
+
+// Returns the unit quaternion for the rotation matrix with the given rows.
+fn quaternion_from_rows(row_0: Vector3F, row_1: Vector3F, row_2: Vector3F) -> F32x4 {
+    // Shepperd's method: divide by the largest of the four components to stay accurate.
+    let (m00, m01, m02) = (row_0.x(), row_0.y(), row_0.z());
+    let (m10, m11, m12) = (row_1.x(), row_1.y(), row_1.z());
+    let (m20, m21, m22) = (row_2.x(), row_2.y(), row_2.z());
+    let trace = m00 + m11 + m22;
+    if trace > 0.0 {
+        let s = 2.0 * (1.0 + trace).sqrt();
+        F32x4::new((m21 - m12) / s, (m02 - m20) / s, (m10 - m01) / s, 0.25 * s)
+    } else if m00 > m11 && m00 > m22 {
+        let s = 2.0 * (1.0 + m00 - m11 - m22).sqrt();
+        F32x4::new(0.25 * s, (m01 + m10) / s, (m02 + m20) / s, (m21 - m12) / s)
+    } else if m11 > m22 {
+        let s = 2.0 * (1.0 - m00 + m11 - m22).sqrt();
+        F32x4::new((m01 + m10) / s, 0.25 * s, (m12 + m21) / s, (m02 - m20) / s)
+    } else {
+        let s = 2.0 * (1.0 - m00 - m11 + m22).sqrt();
+        F32x4::new((m02 + m20) / s, (m12 + m21) / s, 0.25 * s, (m10 - m01) / s)
+    }
+}
+
+// Spherically interpolates between two unit quaternions, taking the shorter way around.
+fn slerp_quaternions(from: F32x4, mut to: F32x4, t: f32) -> F32x4 {
+    let mut cos_angle = dot_4d(from, to);
+    if cos_angle < 0.0 {
+        to *= F32x4::splat(-1.0);
+        cos_angle = -cos_angle;
+    }
+
+    // Nearly identical rotations can't be divided by the sine of the angle between them, but
+    // they lerp just as well.
+    let (from_weight, to_weight) = if cos_angle > 1.0 - DECOMPOSE_EPSILON {
+        (1.0 - t, t)
+    } else {
+        let angle = cos_angle.min(1.0).acos();
+        let sin_angle = angle.sin();
+        (((1.0 - t) * angle).sin() / sin_angle, (t * angle).sin() / sin_angle)
+    };
+    let quaternion = from * F32x4::splat(from_weight) + to * F32x4::splat(to_weight);
+    quaternion * F32x4::splat(1.0 / dot_4d(quaternion, quaternion).sqrt())
+}
+
+/// Build options that have been prepared for a particular scene, ready to be reused for several
+/// builds.
+///
+/// Preparing options does some nontrivial work, such as clipping the scene to the view frustum
+/// for perspective transforms. When the options don't change from frame to frame, prepare them
+/// once with `Scene::prepare_build_options()` and pass them to `Scene::build_prepared()`. The
+/// result only depends on the scene's bounds and view box, so it stays valid across changes to
+/// the scene's paths as long as neither of those changes; `is_valid_for()` checks this.
+pub struct PreparedBuildOptions {
     pub(crate) transform: PreparedRenderTransform,
     pub(crate) dilation: Vector2F,
-    pub(crate) subpixel_aa_enabled: bool,
+    pub(crate) subpixel_aa: SubpixelAA,
+    pub(crate) collect_timing: bool,
+    pub(crate) collect_overdraw: bool,
+    pub(crate) stream_tiles: bool,
+    pub(crate) clip_polygon: Option<Vec<Vector2F>>,
+    pub(crate) scissor: Option<RectF>,
+    pub(crate) deterministic: bool,
+    pub(crate) parallel_chunk_size: usize,
+    pub(crate) sort_fills_by_tile: bool,
+    pub(crate) preserve_mask: bool,
+    pub(crate) mask_clear_color: ColorF,
+    pub(crate) global_alpha: f32,
+    pub(crate) emit_path_paints: bool,
+    pub(crate) aa_quality: AAQuality,
+    pub(crate) premultiplied_alpha: bool,
+    pub(crate) color_space: ColorSpace,
+    pub(crate) max_fills: Option<usize>,
+    pub(crate) min_path_size: f32,
+    pub(crate) group_by_paint: bool,
+    pub(crate) wireframe: bool,
+    pub(crate) grid_fit: bool,
+    pub(crate) conservative: bool,
+    pub(crate) outline_filter: Option<OutlineFilterFunction>,
+    pub(crate) progress: Option<ProgressFunction>,
+    pub(crate) tile_pass: TilePass,
+    pub(crate) tile_order: TileOrder,
+    pub(crate) occlusion_policy: Option<Arc<dyn OcclusionPolicy>>,
+    // If set, every other draw path is treated as hidden.
+    pub(crate) isolated_draw_path: Option<DrawPathId>,
+    // The scene bounds and view box that these options were prepared for.
+    pub(crate) bounds: RectF,
+    pub(crate) view_box: RectF,
 }
 
 #[derive(Clone, Copy)]
@@ -153,6 +1485,19 @@ pub(crate) enum PrepareMode {
 }
 
 impl PreparedBuildOptions {
+    /// Returns true if these options can be used to build the given scene: that is, if the scene's
+    /// bounds and view box are the same as when the options were prepared.
+    #[inline]
+    pub fn is_valid_for(&self, scene: &Scene) -> bool {
+        self.bounds == scene.bounds() && self.view_box == scene.view_box()
+    }
+
+    // Returns true if a path with the given device-space bounds is too small to be drawn.
+    #[inline]
+    pub(crate) fn is_too_small(&self, path_bounds: RectF) -> bool {
+        path_bounds.width() < self.min_path_size && path_bounds.height() < self.min_path_size
+    }
+
     #[inline]
     pub(crate) fn bounding_quad(&self) -> BoundingQuad {
         match self.transform {
@@ -165,6 +1510,10 @@ impl PreparedBuildOptions {
     pub(crate) fn to_prepare_mode(&self, renderer_level: RendererLevel) -> PrepareMode {
         match renderer_level {
             RendererLevel::D3D9 => PrepareMode::CPU,
+            RendererLevel::D3D11 if self.clip_polygon.is_some() || self.scissor.is_some() ||
+                    self.outline_filter.is_some() => {
+                PrepareMode::TransformCPUBinGPU
+            }
             RendererLevel::D3D11 => {
                 match self.transform {
                     PreparedRenderTransform::Perspective { .. } => PrepareMode::TransformCPUBinGPU,
@@ -201,3 +1550,53 @@ impl PreparedRenderTransform {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::concurrent::executor::SequentialExecutor;
+    use crate::gpu::options::RendererLevel;
+    use crate::paint::Paint;
+    use crate::scene::{BuildOutcome, DrawPath, Scene, SceneSink};
+    use pathfinder_color::ColorU;
+    use pathfinder_content::outline::Outline;
+    use pathfinder_geometry::rect::RectF;
+    use pathfinder_geometry::vector::vec2f;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+
+    use super::{BuildOptions, RenderCommandError, RenderCommandListener};
+
+    fn build_with_listener(listener: RenderCommandListener) -> BuildOutcome {
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(100.0, 100.0)));
+        let paint = scene.push_paint(&Paint::from_color(ColorU::black()));
+        let outline = Outline::from_rect(RectF::new(vec2f(10.5, 10.5), vec2f(60.0, 40.0)));
+        scene.push_draw_path(DrawPath::new(outline, paint));
+
+        let mut sink = SceneSink::new(listener, RendererLevel::D3D9);
+        scene.build_cancellable(BuildOptions::default(),
+                                &mut sink,
+                                &SequentialExecutor,
+                                Arc::new(AtomicBool::new(false)))
+    }
+
+    #[test]
+    fn test_fan_out_passes_refusals_up() {
+        let refusing_listener =
+            RenderCommandListener::fallible(Box::new(|_| Err(RenderCommandError)));
+        let listener = RenderCommandListener::fan_out(vec![refusing_listener,
+                                                           RenderCommandListener::null()]);
+        assert_eq!(build_with_listener(listener), BuildOutcome::ListenerFailed);
+
+        let listener = RenderCommandListener::fan_out(vec![RenderCommandListener::null(),
+                                                           RenderCommandListener::null()]);
+        assert_eq!(build_with_listener(listener), BuildOutcome::Finished);
+    }
+
+    #[test]
+    fn test_bounded_channel_refuses_commands_after_receiver_is_dropped() {
+        let (listener, receiver) = RenderCommandListener::bounded_channel(1024);
+        drop(receiver);
+        assert_eq!(build_with_listener(listener), BuildOutcome::ListenerFailed);
+    }
+}
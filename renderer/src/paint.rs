@@ -86,6 +86,7 @@ pub struct GradientId(pub u32);
 
 /// How an overlay is to be composited over a base color.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PaintCompositeOp {
     /// The source that overlaps the destination, replaces the destination.
     SrcIn,
@@ -122,8 +123,18 @@ impl Paint {
     }
 
     /// Creates a paint from a gradient.
+    ///
+    /// Gradients that can only ever produce one color are turned into solid color paints, which
+    /// are cheaper to draw: gradients without color stops are transparent, and linear gradients
+    /// whose stops all have the same color (including those with a single stop) are that color.
+    /// Radial gradients with stops are always kept, because they're transparent outside the cone
+    /// that their circles sweep out.
     #[inline]
     pub fn from_gradient(gradient: Gradient) -> Paint {
+        if let Some(color) = solid_gradient_color(&gradient) {
+            return Paint::from_color(color);
+        }
+
         Paint {
             base_color: ColorU::white(),
             overlay: Some(PaintOverlay {
@@ -315,11 +326,12 @@ pub(crate) struct PaintInfo {
     pub(crate) render_commands: Vec<RenderCommand>,
     /// The metadata for each paint.
     ///
-    /// The indices of this vector are paint IDs.
+    /// The indices of this vector are paint IDs. If the scene is faded by a global alpha and has
+    /// render targets, unfaded copies of every paint follow; see `render_target_paint()`.
     pub(crate) paint_metadata: Vec<PaintMetadata>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct PaintMetadata {
     /// Metadata associated with the color texture, if applicable.
     pub(crate) color_texture_metadata: Option<PaintColorTextureMetadata>,
@@ -330,7 +342,7 @@ pub(crate) struct PaintMetadata {
     pub(crate) is_opaque: bool,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct PaintColorTextureMetadata {
     /// The location of the paint.
     pub(crate) location: TextureLocation,
@@ -364,7 +376,7 @@ pub(crate) struct RenderTargetMetadata {
     pub(crate) location: TextureLocation,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum PaintFilter {
     None,
     RadialGradient {
@@ -395,9 +407,15 @@ impl Palette {
         RenderTargetId { scene: self.scene_id.0, render_target: id }
     }
 
+    pub(crate) fn render_target_size(&self, render_target_id: RenderTargetId) -> Vector2I {
+        debug_assert_eq!(render_target_id.scene, self.scene_id.0);
+        self.render_targets[render_target_id.render_target as usize].size()
+    }
+
     pub(crate) fn build_paint_info(&mut self,
                                    texture_manager: &mut PaintTextureManager,
-                                   render_transform: Transform2F)
+                                   render_transform: Transform2F,
+                                   global_alpha: f32)
                                    -> PaintInfo {
         // Assign render target locations.
         let mut transient_paint_locations = vec![];
@@ -417,6 +435,22 @@ impl Palette {
         // Calculate texture transforms.
         self.calculate_texture_transforms(&mut paint_metadata, texture_manager, render_transform);
 
+        // Fold the global alpha into the base colors. Color textures are combined with the base
+        // color's alpha, so this fades them too. Only the main output is faded, so keep unfaded
+        // copies for paths drawn into render targets.
+        if global_alpha < 1.0 {
+            let unfaded_paint_metadata = if self.render_targets.is_empty() {
+                vec![]
+            } else {
+                paint_metadata.clone()
+            };
+            for metadata in &mut paint_metadata {
+                metadata.base_color.a = (metadata.base_color.a as f32 * global_alpha).round() as u8;
+                metadata.is_opaque = false;
+            }
+            paint_metadata.extend(unfaded_paint_metadata);
+        }
+
         // Create texture metadata.
         let texture_metadata = self.create_texture_metadata(&paint_metadata);
         let mut render_commands = vec![RenderCommand::UploadTextureMetadata(texture_metadata)];
@@ -437,6 +471,17 @@ impl Palette {
         PaintInfo { render_commands, paint_metadata }
     }
 
+    // Returns the ID of the metadata to use for the given paint in paths drawn into render
+    // targets, which aren't faded by the global alpha.
+    pub(crate) fn render_target_paint(&self, paint_id: PaintId, paint_metadata: &[PaintMetadata])
+                                      -> PaintId {
+        if paint_metadata.len() > self.paints.len() {
+            PaintId(paint_id.0 + self.paints.len() as u16)
+        } else {
+            paint_id
+        }
+    }
+
     fn assign_render_target_locations(&self,
                                       texture_manager: &mut PaintTextureManager,
                                       transient_paint_locations: &mut Vec<TextureLocation>)
@@ -651,7 +696,7 @@ impl Palette {
                 } else {
                     ColorCombineMode::None
                 },
-                base_color: paint_metadata.base_color,
+                base_color: paint_metadata.base_color.to_f32(),
                 filter: paint_metadata.filter(),
                 blend_mode: paint_metadata.blend_mode,
             }
@@ -898,3 +943,20 @@ impl PaintColorTextureMetadata {
         }
     }
 }
+
+// Returns the color of the given gradient if it can only ever produce one color.
+fn solid_gradient_color(gradient: &Gradient) -> Option<ColorU> {
+    let (first_stop, other_stops) = match gradient.stops().split_first() {
+        None => return Some(ColorU::transparent_black()),
+        Some(stops) => stops,
+    };
+    match gradient.geometry {
+        GradientGeometry::Linear(_) => {}
+        GradientGeometry::Radial { .. } => return None,
+    }
+    if other_stops.iter().all(|stop| stop.color == first_stop.color) {
+        Some(first_stop.color)
+    } else {
+        None
+    }
+}
@@ -10,30 +10,57 @@
 
 //! The vector scene to be rendered.
 
-use crate::builder::SceneBuilder;
+use crate::builder::{DrawPathCache, SceneBuilder, SlicedBuildState};
 use crate::concurrent::executor::Executor;
 use crate::gpu::options::RendererLevel;
 use crate::gpu::renderer::Renderer;
-use crate::gpu_data::RenderCommand;
+#[cfg(any(test, feature = "testing"))]
+use crate::gpu_data::Fill;
+use crate::gpu_data::{AlphaTileId, RenderCommand, TILE_CTRL_MASK_EVEN_ODD};
 use crate::options::{BuildOptions, PreparedBuildOptions};
-use crate::options::{PreparedRenderTransform, RenderCommandListener};
-use crate::paint::{MergedPaletteInfo, Paint, PaintId, PaintInfo, PaintTextureManager, Palette};
+use crate::options::{PreparedRenderTransform, RenderCommandError, RenderCommandListener};
+use crate::options::RenderTransform;
+use crate::paint::{MergedPaletteInfo, Paint, PaintId, PaintInfo, PaintMetadata};
+use crate::paint::{PaintTextureManager, Palette};
+use crate::tile_map::DenseTileMap;
+use crate::tiles;
+use instant::Instant;
+use pathfinder_content::dash::OutlineDash;
 use pathfinder_content::effects::BlendMode;
 use pathfinder_content::fill::FillRule;
 use pathfinder_content::outline::Outline;
+use pathfinder_content::pattern::Pattern;
+use pathfinder_content::stroke::{OutlineStrokeToFill, StrokeStyle};
 use pathfinder_content::render_target::RenderTargetId;
-use pathfinder_geometry::rect::RectF;
+use pathfinder_geometry::line_segment::LineSegmentU16;
+use pathfinder_geometry::rect::{RectF, RectI};
 use pathfinder_geometry::transform2d::Transform2F;
-use pathfinder_geometry::vector::{Vector2I, vec2f};
+use pathfinder_geometry::vector::{Vector2F, Vector2I, vec2f, vec2i};
 use pathfinder_gpu::Device;
+use std::borrow::Cow;
 use std::mem;
 use std::ops::Range;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::u64;
 
 static NEXT_SCENE_ID: AtomicUsize = AtomicUsize::new(0);
 
+/// The most draw paths that a scene may contain and still be built.
+///
+/// Draw paths are ordered for occlusion culling by storing their positions in paint order in
+/// `i32` Z-buffers, so larger scenes would wrap around and corrupt occlusion. Building a scene
+/// with more draw paths than this reports `BuildOutcome::TooManyPaths` instead.
+pub const MAX_DRAW_PATHS: usize = i32::MAX as usize;
+
+// The narrowest that a stroke may be, in device pixels. Thinner strokes are widened to this so
+// that they don't disappear. This matches the hairline width of the canvas API.
+const HAIRLINE_STROKE_WIDTH: f32 = 0.0333;
+
+// How many groups back a draw path may move to join a group with its paint when grouping by paint.
+// This keeps grouping linear in the number of paths.
+const MAX_PAINT_GROUP_LOOKBACK: usize = 64;
+
 /// The vector scene to be rendered.
 #[derive(Clone)]
 pub struct Scene {
@@ -74,7 +101,12 @@ impl Scene {
     /// render target. Otherwise, it goes to the main output.
     ///
     /// Returns an ID which can later be used to retrieve the path via `get_draw_path()`.
-    pub fn push_draw_path(&mut self, draw_path: DrawPath) -> DrawPathId {
+    pub fn push_draw_path(&mut self, mut draw_path: DrawPath) -> DrawPathId {
+        draw_path.stroked_outline = draw_path.stroke.map(|stroke_style| {
+            StrokedOutline::new(&draw_path, stroke_style)
+        });
+
+        debug_assert!(self.draw_paths.len() < u32::MAX as usize, "Draw path ID overflow!");
         let draw_path_index = DrawPathId(self.draw_paths.len() as u32);
         self.draw_paths.push(draw_path);
         self.push_draw_path_with_index(draw_path_index);
@@ -82,7 +114,8 @@ impl Scene {
     }
 
     fn push_draw_path_with_index(&mut self, draw_path_id: DrawPathId) {
-        let new_path_bounds = self.draw_paths[draw_path_id.0 as usize].outline.bounds();
+        let draw_path = &self.draw_paths[draw_path_id.0 as usize];
+        let new_path_bounds = draw_path.transform * draw_path.fill_outline().bounds();
         self.bounds = self.bounds.union_rect(new_path_bounds);
 
         let end_path_id = DrawPathId(draw_path_id.0 + 1);
@@ -122,9 +155,35 @@ impl Scene {
         self.display_list.push(DisplayItem::PopRenderTarget);
     }
 
+    /// Draws the contents of the given render target as a rectangle of its size, placed with the
+    /// given transform, and returns the ID of the new draw path.
+    ///
+    /// This is how content that repeats many times, such as the icons of a map, can be tiled only
+    /// once: draw it into a render target serving as a template, then place the template wherever
+    /// it's needed. Each placement costs no more to tile than a rectangle, and the template is
+    /// sampled rather than retiled, so placements scaled up look blurry. Placements are ordinary
+    /// draw paths, so they're clipped, hidden, and occluded by opaque paths painted above them
+    /// according to the tiles that their transformed rectangles cover. As templates may be partly
+    /// transparent, placements never occlude anything themselves.
+    pub fn place_render_target(&mut self, render_target_id: RenderTargetId, transform: Transform2F)
+                               -> DrawPathId {
+        let size = self.palette.render_target_size(render_target_id);
+        let mut pattern = Pattern::from_render_target(render_target_id, size);
+        pattern.apply_transform(transform);
+        let paint_id = self.push_paint(&Paint::from_pattern(pattern));
+
+        let outline = Outline::from_rect(RectF::new(Vector2F::zero(), size.to_f32()));
+        let mut draw_path = DrawPath::new(outline, paint_id);
+        draw_path.set_transform(transform);
+        self.push_draw_path(draw_path)
+    }
+
     /// Adds all elements in a scene to this one.
     ///
-    /// This includes draw paths, clip paths, render targets, and paints.
+    /// This includes draw paths, clip paths, render targets, and paints. The appended draw paths
+    /// keep their transforms and paints and are drawn on top of the existing ones. The bounds and
+    /// view box of this scene grow to enclose those of the appended scene, unless either view box
+    /// is empty, in which case the other one is used.
     pub fn append_scene(&mut self, scene: Scene) {
         let MergedPaletteInfo {
             render_target_mapping,
@@ -150,6 +209,16 @@ impl Scene {
                 }),
                 fill_rule: draw_path.fill_rule,
                 blend_mode: draw_path.blend_mode,
+                transform: draw_path.transform,
+                dilation: draw_path.dilation,
+                antialias: draw_path.antialias,
+                text: draw_path.text,
+                stroke: draw_path.stroke,
+                dash_array: draw_path.dash_array,
+                dash_offset: draw_path.dash_offset,
+                stroked_outline: draw_path.stroked_outline,
+                visible: draw_path.visible,
+                z_index: draw_path.z_index,
                 name: draw_path.name,
             });
         }
@@ -173,6 +242,14 @@ impl Scene {
             }
         }
 
+        // Merge bounds and view boxes.
+        self.bounds = self.bounds.union_rect(scene.bounds);
+        if self.view_box.is_empty() {
+            self.view_box = scene.view_box;
+        } else if !scene.view_box.is_empty() {
+            self.view_box = self.view_box.union_rect(scene.view_box);
+        }
+
         // Bump epoch.
         self.epoch.next();
     }
@@ -180,9 +257,18 @@ impl Scene {
     #[inline]
     pub(crate) fn build_paint_info(&mut self,
                                    texture_manager: &mut PaintTextureManager,
-                                   render_transform: Transform2F)
+                                   render_transform: Transform2F,
+                                   global_alpha: f32)
                                    -> PaintInfo {
-        self.palette.build_paint_info(texture_manager, render_transform)
+        self.palette.build_paint_info(texture_manager, render_transform, global_alpha)
+    }
+
+    #[inline]
+    pub(crate) fn render_target_paint(&self,
+                                      paint_id: PaintId,
+                                      paint_metadata: &[PaintMetadata])
+                                      -> PaintId {
+        self.palette.render_target_paint(paint_id, paint_metadata)
     }
 
     /// Defines a new paint, which specifies how paths are to be filled or stroked. Returns a paint
@@ -224,9 +310,159 @@ impl Scene {
         self.epoch.next();
     }
 
+    /// Returns the device-space bounds of everything that will be drawn when this scene is built
+    /// with the given options, clipped to the view box.
+    ///
+    /// Draw paths that lie entirely outside the view box, the bounds of the clip polygon, or the
+    /// scissor rectangle contribute nothing. If nothing is visible, a zero-sized rectangle is
+    /// returned. Clip paths aren't taken into account, so the result may be larger than strictly
+    /// necessary.
+    ///
+    /// With a 2D transform, this is cheap, as only the bounds of each path are transformed. With a
+    /// perspective transform, each path must be clipped and projected, so this is about as
+    /// expensive as preparing the outlines for tiling.
+    pub fn rendered_bounds(&self, options: BuildOptions) -> RectF {
+        let prepared_options = options.prepare(self.bounds, self.view_box);
+        let visible_bounds = match self.visible_bounds(&prepared_options) {
+            None => return RectF::default(),
+            Some(visible_bounds) => visible_bounds,
+        };
+
+        let mut rendered_bounds: Option<RectF> = None;
+        for draw_path in self.draw_paths.iter().filter(|draw_path| draw_path.visible) {
+            let path_bounds = match prepared_options.transform {
+                PreparedRenderTransform::Perspective { .. } => {
+                    self.apply_render_options(draw_path.fill_outline(),
+                                              &draw_path.transform(),
+                                              draw_path.dilation(&prepared_options),
+                                              &prepared_options).bounds()
+                }
+                PreparedRenderTransform::None | PreparedRenderTransform::Transform2D(_) => {
+                    let path_bounds = draw_path_bounds_2d(draw_path, &prepared_options);
+                    if prepared_options.is_too_small(path_bounds) {
+                        continue;
+                    }
+                    path_bounds
+                }
+            };
+
+            if let Some(path_bounds) = path_bounds.intersection(visible_bounds) {
+                rendered_bounds = Some(match rendered_bounds {
+                    None => path_bounds,
+                    Some(rendered_bounds) => rendered_bounds.union_rect(path_bounds),
+                });
+            }
+        }
+
+        rendered_bounds.unwrap_or_default()
+    }
+
+    /// Returns a rough estimate of the number of tiles that building this scene with the given
+    /// options will produce, for deciding whether a build is worth starting.
+    ///
+    /// This sums the areas, in tiles, of the device-space bounding boxes of the visible draw paths,
+    /// so it's cheap: no outline is flattened, clipped, or tiled. The estimate is usually high,
+    /// since paths rarely fill their bounding boxes and occlusion isn't accounted for, and clip
+    /// paths aren't counted. With a perspective transform, each path's bounds are projected
+    /// rather than its outline, so the estimate is coarser still.
+    pub fn estimated_tile_count(&self, options: BuildOptions) -> usize {
+        let prepared_options = options.prepare(self.bounds, self.view_box);
+        let visible_bounds = match self.visible_bounds(&prepared_options) {
+            None => return 0,
+            Some(visible_bounds) => visible_bounds,
+        };
+
+        let mut tile_count = 0;
+        for draw_path in self.draw_paths.iter().filter(|draw_path| draw_path.visible) {
+            if self.draw_path_is_culled(draw_path, &prepared_options) {
+                continue;
+            }
+            let path_bounds = match prepared_options.transform {
+                PreparedRenderTransform::Perspective { ref perspective, ref clip_polygon, .. } => {
+                    // Only the part in front of the camera, inside the clip polygon, is projected.
+                    let path_bounds = draw_path.transform() * draw_path.fill_outline().bounds();
+                    match polygon_bounds(clip_polygon)
+                            .and_then(|clip_bounds| path_bounds.intersection(clip_bounds)) {
+                        None => continue,
+                        Some(path_bounds) => *perspective * path_bounds,
+                    }
+                }
+                PreparedRenderTransform::None | PreparedRenderTransform::Transform2D(_) => {
+                    draw_path_bounds_2d(draw_path, &prepared_options)
+                }
+            };
+            if let Some(path_bounds) = path_bounds.intersection(visible_bounds) {
+                let tile_size = tiles::round_rect_out_to_tile_bounds(path_bounds).size();
+                tile_count += tile_size.x() as usize * tile_size.y() as usize;
+            }
+        }
+        tile_count
+    }
+
+    // Returns the part of device space that paths can be drawn to: the effective view box,
+    // narrowed to the bounds of the clip polygon and the scissor rectangle, if any. Returns `None`
+    // if nothing can be drawn.
+    fn visible_bounds(&self, options: &PreparedBuildOptions) -> Option<RectF> {
+        let mut visible_bounds = self.effective_view_box(options);
+        if let Some(ref clip_polygon) = options.clip_polygon {
+            visible_bounds = visible_bounds.intersection(polygon_bounds(clip_polygon)?)?;
+        }
+        if let Some(scissor) = options.scissor {
+            visible_bounds = visible_bounds.intersection(scissor)?;
+        }
+        Some(visible_bounds)
+    }
+
+    // Returns true if the given draw path should be drawn at all with the given options: that is,
+    // if it isn't hidden and no other draw path is being built in isolation.
+    #[inline]
+    pub(crate) fn draw_path_is_shown(&self,
+                                     draw_path_id: DrawPathId,
+                                     options: &PreparedBuildOptions)
+                                     -> bool {
+        match options.isolated_draw_path {
+            Some(isolated_draw_path_id) if isolated_draw_path_id != draw_path_id => false,
+            _ => self.draw_paths[draw_path_id.0 as usize].visible,
+        }
+    }
+
+    // Returns true if the bounds of the given draw path show that it can't be visible with the
+    // given options, so that it needn't be transformed or tiled at all.
+    //
+    // This is conservative: paths for which this returns false may still turn out to be invisible.
+    pub(crate) fn draw_path_is_culled(&self,
+                                      draw_path: &DrawPath,
+                                      options: &PreparedBuildOptions)
+                                      -> bool {
+        match options.transform {
+            PreparedRenderTransform::Perspective { ref clip_polygon, .. } => {
+                // The perspective clip polygon is in scene space.
+                let path_bounds = draw_path.transform() * draw_path.fill_outline().bounds();
+                match polygon_bounds(clip_polygon) {
+                    None => true,
+                    Some(clip_polygon_bounds) => {
+                        path_bounds.intersection(clip_polygon_bounds).is_none()
+                    }
+                }
+            }
+            PreparedRenderTransform::None | PreparedRenderTransform::Transform2D(_) => {
+                let path_bounds = draw_path_bounds_2d(draw_path, options);
+                if options.is_too_small(path_bounds) {
+                    return true;
+                }
+                match self.visible_bounds(options) {
+                    None => true,
+                    Some(visible_bounds) => path_bounds.intersection(visible_bounds).is_none(),
+                }
+            }
+        }
+    }
+
     #[allow(deprecated)]
     pub(crate) fn apply_render_options(&self,
                                        original_outline: &Outline,
+                                       path_transform: &Transform2F,
+                                       dilation: Vector2F,
                                        options: &PreparedBuildOptions)
                                        -> Outline {
         let mut outline;
@@ -236,6 +472,16 @@ impl Scene {
                 ref clip_polygon,
                 ..
             } => {
+                // The clip polygon is in scene space, so the path transform has to be applied
+                // before clipping.
+                let transformed_outline;
+                let original_outline = if path_transform.is_identity() {
+                    original_outline
+                } else {
+                    transformed_outline = original_outline.clone().transformed(path_transform);
+                    &transformed_outline
+                };
+
                 if original_outline.is_outside_polygon(clip_polygon) {
                     outline = Outline::new();
                 } else {
@@ -251,31 +497,109 @@ impl Scene {
                 // TODO(pcwalton): Short circuit.
                 outline = (*original_outline).clone();
                 outline.close_all_contours();
-                if options.transform.is_2d() || options.subpixel_aa_enabled {
+                if options.transform.is_2d() || options.subpixel_aa.is_enabled() ||
+                        !path_transform.is_identity() {
                     let mut transform = match options.transform {
                         PreparedRenderTransform::Transform2D(transform) => transform,
                         PreparedRenderTransform::None => Transform2F::default(),
                         PreparedRenderTransform::Perspective { .. } => unreachable!(),
                     };
-                    if options.subpixel_aa_enabled {
-                        transform *= Transform2F::from_scale(vec2f(3.0, 1.0))
+                    if options.subpixel_aa.is_enabled() {
+                        transform *= Transform2F::from_scale(options.subpixel_aa.scale())
                     }
+                    transform *= *path_transform;
                     outline.transform(&transform);
                 }
             }
         }
 
-        if !options.dilation.is_zero() {
-            outline.dilate(options.dilation);
+        if !dilation.is_zero() {
+            outline.dilate(dilation);
+        }
+
+        if let Some(ref clip_polygon) = options.clip_polygon {
+            if outline.is_outside_polygon(clip_polygon) {
+                outline = Outline::new();
+            } else {
+                outline.clip_against_polygon(clip_polygon);
+            }
+        }
+
+        if let Some(scissor) = options.scissor {
+            if outline.bounds().intersection(scissor).is_none() {
+                outline = Outline::new();
+            } else {
+                let scissor_polygon = [
+                    scissor.origin(),
+                    scissor.upper_right(),
+                    scissor.lower_right(),
+                    scissor.lower_left(),
+                ];
+                outline.clip_against_polygon(&scissor_polygon);
+            }
         }
 
         outline
     }
 
+    // Widens strokes that would be thinner than a hairline with the given options, expanding them
+    // again where the width changed since they were last expanded.
+    pub(crate) fn update_stroked_outlines<E>(&mut self,
+                                             options: &PreparedBuildOptions,
+                                             executor: &E)
+                                             where E: Executor {
+        // The scale of a perspective transform varies across the scene, so only per-path
+        // transforms are taken into account then.
+        let global_transform = match options.transform {
+            PreparedRenderTransform::Transform2D(transform) => transform,
+            PreparedRenderTransform::None | PreparedRenderTransform::Perspective { .. } => {
+                Transform2F::default()
+            }
+        };
+
+        let draw_paths = &self.draw_paths;
+        let min_len = options.parallel_chunk_size;
+        let new_stroked_outlines = executor.build_vector_with_min_len(draw_paths.len(),
+                                                                      min_len,
+                                                                      |draw_path_index| {
+            let draw_path = &draw_paths[draw_path_index];
+            let mut stroke_style = match draw_path.stroke {
+                None => return None,
+                Some(stroke_style) => stroke_style,
+            };
+
+            // The smaller scale is the one that could make the stroke too thin.
+            let scales = (global_transform * draw_path.transform).extract_scale();
+            let scale = f32::min(scales.x(), scales.y());
+            if scale > 0.0 && stroke_style.line_width * scale < HAIRLINE_STROKE_WIDTH {
+                stroke_style.line_width = HAIRLINE_STROKE_WIDTH / scale;
+            }
+
+            match draw_path.stroked_outline {
+                Some(ref stroked_outline) if stroked_outline.style == stroke_style => None,
+                _ => Some(StrokedOutline::new(draw_path, stroke_style)),
+            }
+        });
+
+        let mut changed = false;
+        for (draw_path, new_stroked_outline) in self.draw_paths.iter_mut()
+                                                               .zip(new_stroked_outlines) {
+            if let Some(new_stroked_outline) = new_stroked_outline {
+                draw_path.stroked_outline = Some(new_stroked_outline);
+                changed = true;
+            }
+        }
+
+        // Outlines uploaded to the GPU are keyed by epoch, so make sure they get uploaded again.
+        if changed {
+            self.epoch.next();
+        }
+    }
+
     #[inline]
     pub(crate) fn effective_view_box(&self, render_options: &PreparedBuildOptions) -> RectF {
-        if render_options.subpixel_aa_enabled {
-            self.view_box * vec2f(3.0, 1.0)
+        if render_options.subpixel_aa.is_enabled() {
+            self.view_box * render_options.subpixel_aa.scale()
         } else {
             self.view_box
         }
@@ -286,14 +610,162 @@ impl Scene {
     /// The given executor will be used to prepare these commands. Typically, this will be a
     /// `SequentialExecutor` to prepare commands on a single thread or `RayonExecutor` to prepare
     /// commands in parallel across multiple threads.
+    ///
+    /// Returns statistics about the fills and tiles that were sent.
     #[inline]
     pub fn build<'a, 'b, E>(&mut self,
                             options: BuildOptions,
                             sink: &'b mut SceneSink<'a>,
                             executor: &E)
+                            -> BuildStats
                             where E: Executor {
-        let prepared_options = options.prepare(self.bounds);
-        SceneBuilder::new(self, &prepared_options, sink).build(executor)
+        let prepared_options = options.prepare(self.bounds, self.view_box);
+        let mut scene_builder = SceneBuilder::new(self, &prepared_options, sink);
+        scene_builder.build(executor);
+        scene_builder.stats()
+    }
+
+    /// Prepares build options for this scene, so that they can be reused for several builds with
+    /// `build_prepared()`.
+    ///
+    /// The prepared options remain valid as long as the bounds and view box of this scene don't
+    /// change. Draw paths can be added, removed, or modified freely in the meantime.
+    #[inline]
+    pub fn prepare_build_options(&self, options: BuildOptions) -> PreparedBuildOptions {
+        options.prepare(self.bounds, self.view_box)
+    }
+
+    /// Like `build()`, but uses options previously prepared with `prepare_build_options()`
+    /// instead of preparing them again.
+    ///
+    /// The options must have been prepared for this scene's current bounds and view box, which
+    /// `PreparedBuildOptions::is_valid_for()` checks. Debug builds panic if they weren't; release
+    /// builds render with a stale transform and clip.
+    #[inline]
+    pub fn build_prepared<'a, 'b, E>(&mut self,
+                                     prepared_options: &PreparedBuildOptions,
+                                     sink: &'b mut SceneSink<'a>,
+                                     executor: &E)
+                                     -> BuildStats
+                                     where E: Executor {
+        debug_assert!(prepared_options.is_valid_for(self),
+                      "Build options were prepared for a different bounds or view box!");
+        let mut scene_builder = SceneBuilder::new(self, prepared_options, sink);
+        scene_builder.build(executor);
+        scene_builder.stats()
+    }
+
+    /// Like `build()`, but only draws the draw path with the given ID, as if every other draw path
+    /// were hidden.
+    ///
+    /// This is useful to debug the rendering of a single path in the context of its scene: the
+    /// path is transformed and clipped as it would be in a full build, but nothing else is drawn,
+    /// so nothing occludes it. Clip paths are still built, so the path is clipped as usual.
+    pub fn build_draw_path_only<'a, 'b, E>(&mut self,
+                                           draw_path_id: DrawPathId,
+                                           options: BuildOptions,
+                                           sink: &'b mut SceneSink<'a>,
+                                           executor: &E)
+                                           -> BuildStats
+                                           where E: Executor {
+        let mut prepared_options = options.prepare(self.bounds, self.view_box);
+        prepared_options.isolated_draw_path = Some(draw_path_id);
+        let mut scene_builder = SceneBuilder::new(self, &prepared_options, sink);
+        scene_builder.build(executor);
+        scene_builder.stats()
+    }
+
+    /// Like `build()`, but abandons the build if `cancel` becomes true while it's in progress.
+    ///
+    /// The flag is checked before each path is tiled and again right before the tile batches are
+    /// sent. Tile batches are sent all at once, so a cancelled build never sends a partial set of
+    /// them. A cancelled build still sends `Finish` so that anything waiting on the end of the
+    /// frame (e.g. `SceneProxy::render()`) doesn't stall, but it draws nothing: fills already
+    /// sent aren't flushed, and the renderer discards them when the next frame starts.
+    pub fn build_cancellable<'a, 'b, E>(&mut self,
+                                        options: BuildOptions,
+                                        sink: &'b mut SceneSink<'a>,
+                                        executor: &E,
+                                        cancel: Arc<AtomicBool>)
+                                        -> BuildOutcome
+                                        where E: Executor {
+        let prepared_options = options.prepare(self.bounds, self.view_box);
+        let mut scene_builder = SceneBuilder::new(self, &prepared_options, sink);
+        scene_builder.cancel = Some(cancel);
+        scene_builder.build(executor)
+    }
+
+    /// Like `build()`, but stops once `deadline` has passed, so that a large scene can be built a
+    /// slice at a time across several frames.
+    ///
+    /// Draw paths are tiled in chunks, and the deadline is checked after each chunk; at least one
+    /// chunk is tiled per slice. If the deadline passes before every path is tiled, this returns
+    /// `BuildProgress::Paused` with a cursor to pass to `resume_build()` later. Occlusion data and
+    /// the paths tiled so far are kept in the cursor, and occluded tiles are culled and tile
+    /// batches packed only once every path has been tiled.
+    ///
+    /// Commands are sent to the sink as they're generated, so the commands of one build are spread
+    /// over all of its slices, and `Finish` is only sent at the end. Don't hand the sink's
+    /// commands to a renderer that expects a whole frame until the build has finished. Only tiling
+    /// on CPU is sliced: builds that stream tiles, draw wireframes, or tile on GPU always finish
+    /// in the first slice.
+    pub fn build_until<'a, 'b, E>(&mut self,
+                                  options: BuildOptions,
+                                  sink: &'b mut SceneSink<'a>,
+                                  executor: &E,
+                                  deadline: Instant)
+                                  -> BuildProgress
+                                  where E: Executor {
+        let prepared_options = options.prepare(self.bounds, self.view_box);
+        let result = SceneBuilder::new(self, &prepared_options, sink).build_until(executor,
+                                                                                  deadline);
+        BuildProgress::new(result, prepared_options)
+    }
+
+    /// Continues a build paused by `build_until()`, stopping again once `deadline` has passed.
+    ///
+    /// The cursor must be resumed with the same sink that the build started with, and the scene
+    /// must not change between slices. If it does, the build is abandoned and finishes with
+    /// `BuildOutcome::Cancelled`.
+    pub fn resume_build<'a, 'b, E>(&mut self,
+                                   cursor: BuildCursor,
+                                   sink: &'b mut SceneSink<'a>,
+                                   executor: &E,
+                                   deadline: Instant)
+                                   -> BuildProgress
+                                   where E: Executor {
+        let BuildCursor { options, state } = cursor;
+        let result = SceneBuilder::new(self, &options, sink).resume_build(state,
+                                                                          executor,
+                                                                          deadline);
+        BuildProgress::new(result, *options)
+    }
+
+    /// Like `build()`, but reuses the tiling work from the last incremental build for draw paths
+    /// that haven't changed.
+    ///
+    /// `dirty_draw_paths` must contain one entry per draw path, set to true if that path changed
+    /// (in outline, paint, fill rule, etc.) since the last call to this method with the same sink.
+    /// Clean paths are copied from the cache instead of being tiled again, though their fills are
+    /// still resent.
+    ///
+    /// Paths are only reused if the scene, view box, and build options are the same as last time,
+    /// the renderer level is D3D9, and no perspective transform is in use. Clipped paths are always
+    /// rebuilt. If more than half of the paths are dirty, everything is rebuilt, since the cache
+    /// bookkeeping would cost more than it saves. Occlusion is recomputed from scratch for every
+    /// build, so it's always correct regardless of which paths were reused.
+    pub fn build_incremental<'a, 'b, E>(&mut self,
+                                        options: BuildOptions,
+                                        sink: &'b mut SceneSink<'a>,
+                                        executor: &E,
+                                        dirty_draw_paths: &[bool])
+                                        -> BuildStats
+                                        where E: Executor {
+        let prepared_options = options.prepare(self.bounds, self.view_box);
+        let mut scene_builder = SceneBuilder::new(self, &prepared_options, sink);
+        scene_builder.dirty_draw_paths = Some(dirty_draw_paths.to_vec());
+        scene_builder.build(executor);
+        scene_builder.stats()
     }
 
     #[inline]
@@ -323,6 +795,76 @@ impl Scene {
         &self.draw_paths[draw_path_id.0 as usize]
     }
 
+    /// Shows or hides the draw path with the given ID.
+    ///
+    /// Hidden paths stay in the scene, so path IDs remain stable, but they're skipped when
+    /// building: they aren't tiled and don't occlude other paths. If you build incrementally,
+    /// mark the path dirty in the next build after changing its visibility.
+    pub fn set_draw_path_visible(&mut self, draw_path_id: DrawPathId, visible: bool) {
+        let draw_path = &mut self.draw_paths[draw_path_id.0 as usize];
+        if draw_path.visible != visible {
+            draw_path.visible = visible;
+            self.epoch.next();
+        }
+    }
+
+    /// Permanently removes the draw paths whose bounds don't intersect the given rectangle, in
+    /// scene coordinates, and compacts the remaining ones.
+    ///
+    /// This trims a large scene down to the part that can still become visible, such as the pages
+    /// around the viewport of a document. Unlike hiding paths, it frees them and renumbers the
+    /// survivors, keeping their order; the returned vector maps each old draw path ID to its new
+    /// one, or to `None` if the path was removed. A path's bounds are those of its transformed
+    /// outline, including its stroke. Paths drawn to render targets are always kept, as their
+    /// coordinates aren't those of the scene. Clip paths, paints, render targets, the scene bounds,
+    /// and the view box are left alone.
+    pub fn retain_intersecting(&mut self, rect: RectF) -> Vec<Option<DrawPathId>> {
+        // Find the paths drawn to the output, outside any render target.
+        let mut drawn_to_output = vec![false; self.draw_paths.len()];
+        let mut render_target_depth = 0;
+        for display_item in &self.display_list {
+            match *display_item {
+                DisplayItem::PushRenderTarget(_) => render_target_depth += 1,
+                DisplayItem::PopRenderTarget => render_target_depth -= 1,
+                DisplayItem::DrawPaths(ref range) if render_target_depth == 0 => {
+                    let range = (range.start.0 as usize)..(range.end.0 as usize);
+                    drawn_to_output[range].iter_mut().for_each(|drawn| *drawn = true);
+                }
+                DisplayItem::DrawPaths(_) => {}
+            }
+        }
+
+        let mut draw_path_mapping = Vec::with_capacity(self.draw_paths.len());
+        let mut retained_draw_paths = Vec::with_capacity(self.draw_paths.len());
+        for (draw_path, drawn_to_output) in self.draw_paths.drain(..).zip(drawn_to_output) {
+            let bounds = draw_path.transform * draw_path.fill_outline().bounds();
+            if drawn_to_output && !bounds.intersects(rect) {
+                draw_path_mapping.push(None);
+            } else {
+                draw_path_mapping.push(Some(DrawPathId(retained_draw_paths.len() as u32)));
+                retained_draw_paths.push(draw_path);
+            }
+        }
+        self.draw_paths = retained_draw_paths;
+
+        // Rebuild the display list with the new IDs.
+        let old_display_list = mem::take(&mut self.display_list);
+        for display_item in old_display_list {
+            match display_item {
+                DisplayItem::DrawPaths(range) => {
+                    let range = (range.start.0 as usize)..(range.end.0 as usize);
+                    for &new_draw_path_id in draw_path_mapping[range].iter().flatten() {
+                        self.push_draw_path_with_index(new_draw_path_id);
+                    }
+                }
+                display_item => self.display_list.push(display_item),
+            }
+        }
+
+        self.epoch.next();
+        draw_path_mapping
+    }
+
     /// Returns the clip path with the given ID.
     #[inline]
     pub fn get_clip_path(&self, clip_path_id: ClipPathId) -> &ClipPath {
@@ -341,6 +883,105 @@ impl Scene {
         self.id
     }
 
+    /// Returns the draw paths in the given range in the order they are to be painted: by Z index,
+    /// and then by ID. If `BuildOptions::group_by_paint` is set, non-overlapping paths are then
+    /// moved next to earlier paths with the same paint.
+    pub(crate) fn paint_order(&self,
+                              draw_path_id_range: Range<DrawPathId>,
+                              options: &PreparedBuildOptions)
+                              -> Vec<DrawPathId> {
+        let mut draw_path_ids: Vec<_> =
+            (draw_path_id_range.start.0..draw_path_id_range.end.0).map(DrawPathId).collect();
+        // This is a stable sort, so ties are broken by ID.
+        draw_path_ids.sort_by_key(|draw_path_id| {
+            self.draw_paths[draw_path_id.0 as usize].z_index()
+        });
+        if options.group_by_paint {
+            draw_path_ids = self.group_by_paint(draw_path_ids, options);
+        }
+        draw_path_ids
+    }
+
+    // Reorders the given draw paths, which are in paint order, so that paths with the same paint
+    // and blend mode are adjacent wherever that can't change the output.
+    //
+    // Each path joins the latest group with its paint, provided that it doesn't overlap any group
+    // after that one; otherwise it starts a new group. Paths with destructive blend modes affect
+    // their whole tiles, so they always start a new group that nothing may move past.
+    fn group_by_paint(&self, draw_path_ids: Vec<DrawPathId>, options: &PreparedBuildOptions)
+                      -> Vec<DrawPathId> {
+        struct PaintGroup {
+            key: Option<(PaintId, BlendMode)>,
+            tile_bounds: Option<RectI>,
+            draw_path_ids: Vec<DrawPathId>,
+        }
+
+        let mut groups: Vec<PaintGroup> = vec![];
+        for draw_path_id in draw_path_ids {
+            let draw_path = &self.draw_paths[draw_path_id.0 as usize];
+            let (key, tile_bounds) = if draw_path.blend_mode().is_destructive() {
+                (None, None)
+            } else {
+                let bounds = draw_path_bounds_2d(draw_path, options);
+                (Some((draw_path.paint(), draw_path.blend_mode())),
+                 Some(tiles::round_rect_out_to_tile_bounds(bounds)))
+            };
+
+            let mut target_group_index = None;
+            if let (Some(key), Some(tile_bounds)) = (key, tile_bounds) {
+                let candidate_groups = groups.iter().enumerate().rev();
+                for (group_index, group) in candidate_groups.take(MAX_PAINT_GROUP_LOOKBACK) {
+                    if group.key == Some(key) {
+                        target_group_index = Some(group_index);
+                        break;
+                    }
+                    match group.tile_bounds {
+                        Some(group_tile_bounds) if !group_tile_bounds.intersects(tile_bounds) => {}
+                        _ => break,
+                    }
+                }
+            }
+
+            match target_group_index {
+                Some(group_index) => {
+                    let group = &mut groups[group_index];
+                    group.tile_bounds = match (group.tile_bounds, tile_bounds) {
+                        (Some(group_tile_bounds), Some(tile_bounds)) => {
+                            Some(RectI::from_points(
+                                group_tile_bounds.origin().min(tile_bounds.origin()),
+                                group_tile_bounds.lower_right().max(tile_bounds.lower_right())))
+                        }
+                        _ => None,
+                    };
+                    group.draw_path_ids.push(draw_path_id);
+                }
+                None => {
+                    groups.push(PaintGroup { key, tile_bounds, draw_path_ids: vec![draw_path_id] })
+                }
+            }
+        }
+
+        groups.into_iter().flat_map(|group| group.draw_path_ids).collect()
+    }
+
+    /// Returns the position of each draw path in paint order, indexed by draw path ID.
+    ///
+    /// Paths are only reordered within a single display item, so each path's position stays
+    /// within the range of IDs of its display item.
+    pub(crate) fn paint_ranks(&self, options: &PreparedBuildOptions) -> Vec<u32> {
+        let mut paint_ranks: Vec<u32> = (0..self.draw_paths.len() as u32).collect();
+        for display_item in &self.display_list {
+            if let DisplayItem::DrawPaths(ref draw_path_id_range) = *display_item {
+                let paint_order = self.paint_order(draw_path_id_range.clone(), options);
+                for (offset, draw_path_id) in paint_order.into_iter().enumerate() {
+                    paint_ranks[draw_path_id.0 as usize] = draw_path_id_range.start.0 +
+                        offset as u32;
+                }
+            }
+        }
+        paint_ranks
+    }
+
     #[inline]
     pub(crate) fn epoch(&self) -> SceneEpoch {
         self.epoch
@@ -353,17 +994,74 @@ impl Scene {
                                    executor: E)
                                    -> Vec<RenderCommand>
                                    where D: Device, E: Executor {
+        self.build_to_vec(build_options, renderer.mode().level, &executor)
+    }
+
+    /// Builds the scene for the given renderer level and returns the render commands in the order
+    /// they were sent, without needing a renderer or a listener.
+    ///
+    /// The commands appear in exactly the order a listener would have received them. With a
+    /// `SequentialExecutor`, that order is deterministic, which makes the result suitable for
+    /// snapshot tests comparing command streams.
+    pub fn build_to_vec<E>(&mut self,
+                           build_options: BuildOptions,
+                           renderer_level: RendererLevel,
+                           executor: &E)
+                           -> Vec<RenderCommand>
+                           where E: Executor {
         let commands = Arc::new(Mutex::new(vec![]));
         let commands_for_listener = commands.clone();
         let listener = RenderCommandListener::new(Box::new(move |command| {
             commands_for_listener.lock().unwrap().push(command)
         }));
-        let mut sink = SceneSink::new(listener, renderer.mode().level);
-        self.build(build_options, &mut sink, &executor);
+        let mut sink = SceneSink::new(listener, renderer_level);
+        self.build(build_options, &mut sink, executor);
         let mut commands = commands.lock().unwrap();
         mem::replace(&mut *commands, vec![])
     }
 
+    /// Builds the scene ahead of time into a `BuiltFrame` that can be kept and replayed to a
+    /// listener later without tiling the scene again.
+    ///
+    /// This is useful to prepare scenes that are about to be shown, such as the pages just
+    /// offscreen in a document viewer. Pass a `RayonExecutor` to tile in parallel; the frame can
+    /// also be built on a worker thread and sent back, as it owns all of its commands. Before
+    /// replaying the frame, check that it's still current with `BuiltFrame::is_valid_for()`.
+    pub fn build_frame<E>(&mut self,
+                          build_options: BuildOptions,
+                          renderer_level: RendererLevel,
+                          executor: &E)
+                          -> BuiltFrame
+                          where E: Executor {
+        let transform = build_options.transform.clone();
+        let prepared_options = self.prepare_build_options(build_options);
+
+        // Tiles carry their draw path's position in paint order, so keep the way back.
+        let paint_ranks = self.paint_ranks(&prepared_options);
+        let mut draw_path_ids_by_paint_rank = vec![DrawPathId(0); paint_ranks.len()];
+        for (draw_path_index, &paint_rank) in paint_ranks.iter().enumerate() {
+            draw_path_ids_by_paint_rank[paint_rank as usize] = DrawPathId(draw_path_index as u32);
+        }
+
+        let commands = Arc::new(Mutex::new(vec![]));
+        let commands_for_listener = commands.clone();
+        let listener = RenderCommandListener::new(Box::new(move |command| {
+            commands_for_listener.lock().unwrap().push(command)
+        }));
+        let mut sink = SceneSink::new(listener, renderer_level);
+        let stats = self.build_prepared(&prepared_options, &mut sink, executor);
+        let mut commands = commands.lock().unwrap();
+        BuiltFrame {
+            commands: mem::take(&mut *commands),
+            stats,
+            bounds: self.bounds,
+            view_box: self.view_box,
+            transform,
+            subpixel_aa_scale: prepared_options.subpixel_aa.scale(),
+            draw_path_ids_by_paint_rank,
+        }
+    }
+
     /// A convenience method to build a scene and send the resulting commands to the given
     /// renderer.
     pub fn build_and_render<D, E>(&mut self,
@@ -378,6 +1076,332 @@ impl Scene {
     }
 }
 
+/// The result of a build that can be cancelled.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BuildOutcome {
+    /// The build ran to completion, and all render commands were sent.
+    Finished,
+    /// The build was cancelled. No tiles were drawn, unless tile streaming was enabled, in which
+    /// case the tiles of paths built before cancellation may have been.
+    Cancelled,
+    /// The listener refused a command. Unless this happened while the last commands were being
+    /// sent, the build was abandoned as if it had been cancelled.
+    ListenerFailed,
+    /// Tiling generated more fills than `BuildOptions::max_fills` allows, and the build was
+    /// abandoned as if it had been cancelled.
+    TooManyFills,
+    /// The scene has more than `MAX_DRAW_PATHS` draw paths. Nothing was built.
+    TooManyPaths,
+}
+
+/// The result of one slice of a build started with `Scene::build_until()`.
+pub enum BuildProgress {
+    /// The build is over, with the given outcome.
+    Finished(BuildOutcome),
+    /// The deadline passed before the build was over. Pass the cursor to `Scene::resume_build()`
+    /// to continue.
+    Paused(BuildCursor),
+}
+
+/// A paused build, holding everything needed to resume it with `Scene::resume_build()`.
+///
+/// Dropping a cursor abandons its build without sending `Finish`.
+pub struct BuildCursor {
+    options: Box<PreparedBuildOptions>,
+    state: Box<SlicedBuildState>,
+}
+
+impl BuildProgress {
+    fn new(result: Result<BuildOutcome, Box<SlicedBuildState>>, options: PreparedBuildOptions)
+           -> BuildProgress {
+        match result {
+            Ok(outcome) => BuildProgress::Finished(outcome),
+            Err(state) => {
+                BuildProgress::Paused(BuildCursor { options: Box::new(options), state })
+            }
+        }
+    }
+}
+
+/// Statistics about the work that a build produced, useful for profiling overlays.
+///
+/// Tile counts are taken after occluded tiles have been culled, so they reflect what the renderer
+/// actually draws. At the D3D11 level, fills and tiles are generated on GPU, so the counts are all
+/// zero.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct BuildStats {
+    /// The number of fills sent to the renderer.
+    pub fill_count: usize,
+    /// The number of tiles drawn with a mask.
+    pub alpha_tile_count: usize,
+    /// The number of tiles drawn fully covered, without a mask.
+    pub solid_tile_count: usize,
+    /// The number of tiles dropped because solid tiles of paths painted above them hid them.
+    pub culled_tile_count: usize,
+    /// How many times each tile of the output is drawn to, if `BuildOptions::collect_overdraw`
+    /// was set.
+    pub overdraw: Option<OverdrawStats>,
+}
+
+/// The number of buckets in `OverdrawStats::histogram`.
+pub const OVERDRAW_HISTOGRAM_LEN: usize = 8;
+
+/// Statistics about how many tiles are drawn on top of each other, collected when
+/// `BuildOptions::collect_overdraw` is set.
+///
+/// Only tiles drawn to the output are counted, not tiles drawn into render targets. Tiles culled
+/// by occlusion aren't drawn, so they don't count either.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct OverdrawStats {
+    /// The number of tile positions of the output drawn to at least once.
+    pub covered_tile_count: usize,
+    /// The total number of tiles drawn to the output.
+    pub drawn_tile_count: usize,
+    /// The number of tile positions drawn to exactly `i + 1` times, at index `i`. The last bucket
+    /// also counts the positions drawn to more often than that.
+    pub histogram: [usize; OVERDRAW_HISTOGRAM_LEN],
+}
+
+impl OverdrawStats {
+    /// Returns the average number of tiles drawn at each covered tile position, or zero if
+    /// nothing was drawn.
+    pub fn average(&self) -> f32 {
+        if self.covered_tile_count == 0 {
+            return 0.0;
+        }
+        self.drawn_tile_count as f32 / self.covered_tile_count as f32
+    }
+}
+
+/// The render commands of a scene built ahead of time with `Scene::build_frame()`.
+///
+/// A built frame can be replayed to a listener any number of times. It records the scene's bounds
+/// and view box and the global transform it was built with, and goes stale as soon as any of them
+/// changes. Draw paths edited after the build aren't tracked, so rebuild the frame after editing
+/// the scene.
+#[derive(Clone)]
+pub struct BuiltFrame {
+    commands: Vec<RenderCommand>,
+    stats: BuildStats,
+    bounds: RectF,
+    view_box: RectF,
+    transform: RenderTransform,
+    subpixel_aa_scale: Vector2F,
+    draw_path_ids_by_paint_rank: Vec<DrawPathId>,
+}
+
+impl BuiltFrame {
+    /// Returns the render commands of this frame, in the order they were sent.
+    #[inline]
+    pub fn commands(&self) -> &[RenderCommand] {
+        &self.commands
+    }
+
+    /// Returns statistics about the build that produced this frame.
+    #[inline]
+    pub fn stats(&self) -> BuildStats {
+        self.stats
+    }
+
+    /// Returns true if this frame still matches the given scene rendered with the given global
+    /// transform, that is, if neither the scene's bounds and view box nor the transform changed
+    /// since the frame was built.
+    pub fn is_valid_for(&self, scene: &Scene, transform: &RenderTransform) -> bool {
+        self.bounds == scene.bounds() &&
+            self.view_box == scene.view_box() &&
+            render_transforms_equal(&self.transform, transform)
+    }
+
+    /// Returns the fills of the alpha tiles drawn at the given tile coordinates, in the order they
+    /// were sent.
+    ///
+    /// This is meant for regression tests of the tiler, which can check exactly which segments
+    /// landed in a tile without decoding the fill batches. Only frames built at the D3D9 level
+    /// contain fills and tiles, so this always returns an empty vector at the D3D11 level.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn fills_for_tile(&self, tile_coords: Vector2I) -> Vec<Fill> {
+        let mut alpha_tile_ids = vec![];
+        for command in &self.commands {
+            if let RenderCommand::DrawTilesD3D9(ref batch) = *command {
+                alpha_tile_ids.extend(batch.tiles.iter().filter(|tile| {
+                    tile.tile_x as i32 == tile_coords.x() && tile.tile_y as i32 == tile_coords.y()
+                }).map(|tile| tile.alpha_tile_id.0));
+            }
+        }
+
+        let mut fills = vec![];
+        for command in &self.commands {
+            if let RenderCommand::AddFillsD3D9(ref batch) = *command {
+                fills.extend(batch.iter().filter(|fill| alpha_tile_ids.contains(&fill.link)));
+            }
+        }
+        fills
+    }
+
+    /// Returns the frontmost draw path drawn to the output at the given point, in device pixels,
+    /// that covers at least `min_coverage` of the pixel there.
+    ///
+    /// This is for hit testing without keeping a separate index of the scene's geometry. The
+    /// coverage is worked out from the fills and backdrops of the frame's tiles, including clips,
+    /// just as the tile shaders do, so a threshold around 0.5 makes antialiased edges count as
+    /// hits only where they're mostly covered. Paints are ignored, so fully transparent paths can
+    /// be hit, and paths drawn to render targets never are. To find which point of the scene was
+    /// hit, pass the same point to `RenderTransform::unproject()`. Only frames built at the D3D9
+    /// level contain tiles, so this always returns `None` at the D3D11 level.
+    pub fn draw_path_at(&self, point: Vector2F, min_coverage: f32) -> Option<DrawPathId> {
+        let point = point * self.subpixel_aa_scale;
+        let tile_size = vec2i(tiles::TILE_WIDTH as i32, tiles::TILE_HEIGHT as i32);
+        let pixel = point.floor().to_i32();
+        let tile_coords = vec2i(pixel.x().div_euclid(tile_size.x()),
+                                pixel.y().div_euclid(tile_size.y()));
+        let local_pixel = pixel - tile_coords * tile_size;
+
+        let mut render_target_depth = 0;
+        let mut best_paint_rank = None;
+        for command in &self.commands {
+            let batch = match *command {
+                RenderCommand::PushRenderTarget(_) => {
+                    render_target_depth += 1;
+                    continue;
+                }
+                RenderCommand::PopRenderTarget => {
+                    render_target_depth -= 1;
+                    continue;
+                }
+                RenderCommand::DrawTilesD3D9(ref batch) if render_target_depth == 0 => batch,
+                _ => continue,
+            };
+
+            for tile in &batch.tiles {
+                if tile.tile_x as i32 != tile_coords.x() || tile.tile_y as i32 != tile_coords.y() ||
+                        matches!(best_paint_rank, Some(best) if tile.path_id.0 <= best) {
+                    continue;
+                }
+
+                let mut coverage = tile.backdrop as f32;
+                if tile.alpha_tile_id.is_valid() {
+                    let clip = batch.clips.iter().find(|clip| {
+                        clip.dest_tile_id == tile.alpha_tile_id
+                    });
+                    coverage += match clip {
+                        None => self.mask_value(tile.alpha_tile_id, local_pixel),
+                        Some(clip) => {
+                            let dest = self.mask_value(clip.dest_tile_id, local_pixel) +
+                                clip.dest_backdrop as f32;
+                            let src = self.mask_value(clip.src_tile_id, local_pixel) +
+                                clip.src_backdrop as f32;
+                            f32::min(dest.abs(), src.abs())
+                        }
+                    };
+                }
+
+                let coverage = if (tile.ctrl as i32 & TILE_CTRL_MASK_EVEN_ODD) != 0 {
+                    1.0 - (1.0 - coverage.rem_euclid(2.0)).abs()
+                } else {
+                    coverage.abs().min(1.0)
+                };
+                if coverage >= min_coverage {
+                    best_paint_rank = Some(tile.path_id.0);
+                }
+            }
+        }
+
+        best_paint_rank.map(|paint_rank| self.draw_path_ids_by_paint_rank[paint_rank as usize])
+    }
+
+    // Returns the value that the fills of the given alpha tile accumulate in its mask at the
+    // given pixel, before the backdrop is added.
+    fn mask_value(&self, alpha_tile_id: AlphaTileId, local_pixel: Vector2I) -> f32 {
+        let mut value = 0.0;
+        for command in &self.commands {
+            if let RenderCommand::AddFillsD3D9(ref fills) = *command {
+                for fill in fills {
+                    if fill.link == alpha_tile_id.0 {
+                        value -= area_below_fill(fill.line_segment, local_pixel);
+                    }
+                }
+            }
+        }
+        value
+    }
+
+    /// Sends the commands of this frame to the given listener, in order, without retiling.
+    ///
+    /// Replay stops at the first command that the listener refuses, and the error is returned.
+    #[inline]
+    pub fn replay(&self, listener: &RenderCommandListener) -> Result<(), RenderCommandError> {
+        listener.replay(&self.commands)
+    }
+}
+
+// Returns the area of the given pixel that lies below the given fill, in tile-local coordinates,
+// signed by the horizontal direction of the fill. This is what the D3D9 fill shader accumulates.
+pub(crate) fn area_below_fill(line_segment: LineSegmentU16, pixel: Vector2I) -> f32 {
+    let from = vec2f(line_segment.from_x as f32, line_segment.from_y as f32) / 256.0;
+    let to = vec2f(line_segment.to_x as f32, line_segment.to_y as f32) / 256.0;
+    let (left, right) = if from.x() <= to.x() { (from, to) } else { (to, from) };
+    let (min_x, max_x) = (left.x().max(pixel.x() as f32), right.x().min(pixel.x() as f32 + 1.0));
+    if min_x >= max_x {
+        return 0.0;
+    }
+
+    // The covered height is piecewise linear in X, with corners where the fill crosses the top
+    // and bottom of the pixel, so the trapezoid rule is exact between those.
+    let slope = (right.y() - left.y()) / (right.x() - left.x());
+    let covered_height = |x: f32| {
+        (pixel.y() as f32 + 1.0 - (left.y() + slope * (x - left.x()))).clamp(0.0, 1.0)
+    };
+    let mut xs = vec![min_x, max_x];
+    if slope != 0.0 {
+        for &edge_y in &[pixel.y() as f32, pixel.y() as f32 + 1.0] {
+            let x = left.x() + (edge_y - left.y()) / slope;
+            if x > min_x && x < max_x {
+                xs.push(x);
+            }
+        }
+    }
+    xs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let area: f32 = xs.windows(2).map(|span| {
+        (covered_height(span[0]) + covered_height(span[1])) * 0.5 * (span[1] - span[0])
+    }).sum();
+    area * (to.x() - from.x()).signum()
+}
+
+fn render_transforms_equal(a: &RenderTransform, b: &RenderTransform) -> bool {
+    match (a, b) {
+        (RenderTransform::Transform2D(a), RenderTransform::Transform2D(b)) => a == b,
+        (RenderTransform::Perspective(a), RenderTransform::Perspective(b)) => {
+            a.transform == b.transform && a.window_size == b.window_size
+        }
+        _ => false,
+    }
+}
+
+// Returns the device-space bounds of the given draw path under a 2D global transform, including
+// the subpixel AA scale and dilation. Thinning is ignored, as it can move points at concave corners
+// outward, so the result is conservative.
+fn draw_path_bounds_2d(draw_path: &DrawPath, options: &PreparedBuildOptions) -> RectF {
+    let mut transform = match options.transform {
+        PreparedRenderTransform::Transform2D(transform) => transform,
+        _ => Transform2F::default(),
+    };
+    if options.subpixel_aa.is_enabled() {
+        transform *= Transform2F::from_scale(options.subpixel_aa.scale())
+    }
+    transform *= draw_path.transform();
+    let path_bounds = transform * draw_path.fill_outline().bounds();
+    path_bounds.dilate(draw_path.dilation(options).max(Vector2F::zero()))
+}
+
+// Returns the bounding rectangle of the given polygon, or `None` if it has no vertices.
+fn polygon_bounds(points: &[Vector2F]) -> Option<RectF> {
+    let (&first, rest) = points.split_first()?;
+    Some(rest.iter().fold(RectF::from_points(first, first), |bounds, &point| {
+        RectF::from_points(bounds.origin().min(point), bounds.lower_right().max(point))
+    }))
+}
+
 /// Receives render commands and delivers them to a `RenderCommandListener`.
 ///
 /// Scene sinks wrap render command listeners with cached information about the previous scene.
@@ -386,6 +1410,17 @@ pub struct SceneSink<'a> {
     pub(crate) renderer_level: RendererLevel,
     pub(crate) last_scene: Option<LastSceneInfo>,
     pub(crate) paint_texture_manager: PaintTextureManager,
+    pub(crate) draw_path_cache: Option<DrawPathCache>,
+    pub(crate) last_view_box: Option<RectF>,
+    // Storage for the occluder Z-buffer, kept between builds so that it needn't be reallocated.
+    pub(crate) spare_occluder_z_buffer: Option<DenseTileMap<i32>>,
+    // The paint ranks that the Z-buffer above was built with, or empty if the last build found no
+    // occluders.
+    pub(crate) occluder_paint_ranks: Vec<u32>,
+    // The bounds of the tiles drawn for each draw path in the last build, in tile coordinates.
+    pub(crate) draw_path_tile_bounds: Vec<Option<RectI>>,
+    // The number of tiles sent for each draw path in the last build, after culling.
+    pub(crate) draw_path_tile_counts: Vec<usize>,
 }
 
 pub(crate) struct LastSceneInfo {
@@ -432,7 +1467,68 @@ impl<'a> SceneSink<'a> {
             renderer_level,
             last_scene: None,
             paint_texture_manager: PaintTextureManager::new(),
+            draw_path_cache: None,
+            last_view_box: None,
+            spare_occluder_z_buffer: None,
+            occluder_paint_ranks: vec![],
+            draw_path_tile_bounds: vec![],
+            draw_path_tile_counts: vec![],
+        }
+    }
+
+    /// Returns the smallest rectangle, in tile coordinates, enclosing the tiles drawn for the
+    /// given draw path in the last build, or `None` if no tiles were drawn for it.
+    ///
+    /// Only tiles that received coverage count, so this can be much tighter than the bounds of the
+    /// path's outline, for example when the path is clipped. Tiles that were later culled because
+    /// paths above them hid them still count. Tiles are only known on the CPU at the D3D9 level,
+    /// so at the D3D11 level this always returns `None`.
+    #[inline]
+    pub fn draw_path_tile_bounds(&self, draw_path_id: DrawPathId) -> Option<RectI> {
+        self.draw_path_tile_bounds.get(draw_path_id.0 as usize).cloned().flatten()
+    }
+
+    /// Returns each draw path that had tiles drawn in the last build, along with the number of
+    /// tiles sent for it, in order of draw path ID.
+    ///
+    /// Draw paths that were hidden, culled, entirely outside the view, or completely hidden behind
+    /// other paths don't appear, which makes this useful for evicting paths that never render
+    /// from caches. Unlike with `draw_path_tile_bounds()`, tiles that were culled because paths
+    /// above them hid them aren't counted. Tiles are only known on the CPU at the D3D9 level, so
+    /// at the D3D11 level this yields nothing.
+    pub fn contributing_draw_paths(&self) -> impl Iterator<Item = (DrawPathId, usize)> + '_ {
+        self.draw_path_tile_counts.iter().enumerate().filter(|&(_, &tile_count)| {
+            tile_count > 0
+        }).map(|(draw_path_index, &tile_count)| (DrawPathId(draw_path_index as u32), tile_count))
+    }
+
+    /// Returns each tile that the last build found to be completely covered by an opaque
+    /// rectangle, along with the topmost draw path covering it.
+    ///
+    /// This is the map used to skip tiling paths that are entirely hidden, as it stood before any
+    /// tiles were built, so it can be used to visualize occlusion. Only unclipped, opaque,
+    /// axis-aligned rectangles are considered, and nothing is returned if the scene uses render
+    /// targets or a perspective transform. Occlusion of individual tiles found during tiling is
+    /// instead recorded in the `z_buffer_data` of each D3D9 tile batch.
+    pub fn occluding_draw_paths(&self) -> Vec<(Vector2I, DrawPathId)> {
+        let z_buffer = match self.spare_occluder_z_buffer {
+            Some(ref z_buffer) if !self.occluder_paint_ranks.is_empty() => z_buffer,
+            _ => return vec![],
+        };
+
+        let mut draw_path_ids_by_rank = vec![DrawPathId(0); self.occluder_paint_ranks.len()];
+        for (draw_path_index, &paint_rank) in self.occluder_paint_ranks.iter().enumerate() {
+            draw_path_ids_by_rank[paint_rank as usize] = DrawPathId(draw_path_index as u32);
+        }
+
+        let mut owners = vec![];
+        for (index, &z_value) in z_buffer.data.iter().enumerate() {
+            if z_value >= 0 {
+                owners.push((z_buffer.index_to_coords(index),
+                             draw_path_ids_by_rank[z_value as usize]));
+            }
         }
+        owners
     }
 }
 
@@ -449,6 +1545,59 @@ pub struct DrawPath {
     pub fill_rule: FillRule,
     /// How to blend this path with everything below it.
     pub blend_mode: BlendMode,
+    /// A transform applied to the outline of this path, before the global transform in the build
+    /// options.
+    ///
+    /// This lets paths be positioned without rewriting their outlines. It's composed with both 2D
+    /// and perspective global transforms. Paints (gradients and patterns) are positioned in scene
+    /// space and aren't moved by this transform; transform the paint itself to match.
+    pub transform: Transform2F,
+    /// If set, expands the outline of this path by the given number of device pixels instead of
+    /// the dilation in the build options.
+    ///
+    /// This is useful to embolden some text without affecting the rest of the scene. The outline
+    /// is dilated after all transforms are applied, so the amount doesn't depend on the scale of
+    /// this path or of the scene. As with the global dilation, this has no effect when paths are
+    /// transformed on GPU, which the D3D11 level does unless a perspective transform, clip
+    /// polygon, or scissor rectangle is in use.
+    pub dilation: Option<Vector2F>,
+    /// False if this path should be drawn with hard edges, for pixel art: each pixel, or subpixel
+    /// with subpixel antialiasing, is covered entirely or not at all, depending on its center.
+    pub antialias: bool,
+    /// True if this path is text (typically a glyph outline), whose edges may be snapped to the
+    /// pixel grid.
+    ///
+    /// This has no effect unless `grid_fit` is set in the build options.
+    pub text: bool,
+    /// If set, this path is stroked with the given style instead of being filled.
+    ///
+    /// The outline is expanded into a fill when the path is added to a scene. Strokes that would
+    /// be thinner than a hairline once the per-path and 2D global transforms are applied are
+    /// widened to a hairline when building, so that they don't disappear; the expanded outline
+    /// is cached and only recomputed when that width changes.
+    pub stroke: Option<StrokeStyle>,
+    /// Alternating lengths of dashes and gaps that a stroke is split into, in the units of the
+    /// outline. An empty array means the stroke is solid.
+    ///
+    /// As in the canvas API, an array with an odd number of entries is repeated to make it even,
+    /// and arrays with negative or non-finite entries, or with entries that sum to zero, are
+    /// treated as solid. The pattern runs continuously along each contour and wraps around the
+    /// start of closed contours. This has no effect on filled paths.
+    pub dash_array: Vec<f32>,
+    /// How far into the dash pattern strokes start, in the units of the outline.
+    pub dash_offset: f32,
+    // The expanded outline of the stroke, along with the style it was expanded with.
+    pub(crate) stroked_outline: Option<StrokedOutline>,
+    /// False if this path is hidden. Hidden paths are neither tiled nor used for occlusion.
+    ///
+    /// To toggle the visibility of a path that's already in a scene, use
+    /// `Scene::set_draw_path_visible()`.
+    pub visible: bool,
+    /// Where this path is painted relative to other paths drawn to the same render target.
+    ///
+    /// Paths with higher Z indices are painted on top of paths with lower ones. Paths with equal Z
+    /// indices are painted in the order they were added to the scene.
+    pub z_index: i32,
     /// The name of this path, for debugging.
     ///
     /// Pass the empty string (which does not allocate) if debugging is not needed.
@@ -472,8 +1621,32 @@ pub struct ClipPath {
     pub name: String,
 }
 
+#[derive(Clone, Debug)]
+pub(crate) struct StrokedOutline {
+    style: StrokeStyle,
+    outline: Outline,
+}
+
+impl StrokedOutline {
+    fn new(draw_path: &DrawPath, style: StrokeStyle) -> StrokedOutline {
+        let dashed_outline = draw_path.dash_pattern().map(|dash_pattern| {
+            let mut dash = OutlineDash::new(&draw_path.outline,
+                                            &dash_pattern,
+                                            draw_path.dash_offset);
+            dash.dash();
+            dash.into_outline()
+        });
+        let outline = dashed_outline.as_ref().unwrap_or(&draw_path.outline);
+
+        let mut stroke_to_fill = OutlineStrokeToFill::new(outline, style);
+        stroke_to_fill.offset();
+        StrokedOutline { style, outline: stroke_to_fill.into_outline() }
+    }
+}
+
 /// The ID of a draw path, unique to a single scene.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DrawPathId(pub u32);
 
 /// The ID of a clip path, unique to a single scene.
@@ -482,6 +1655,7 @@ pub struct ClipPathId(pub u32);
 
 /// Either a draw path ID or a clip path ID, depending on context.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PathId(pub u32);
 
 /// A raster image target that can be rendered to and later reused as a pattern.
@@ -511,8 +1685,10 @@ pub enum DisplayItem {
 impl DrawPath {
     /// Creates a new draw path with the given outline and paint.
     ///
-    /// Initially, there is no clip path, the fill rule is set to winding, the blend mode is set to
-    /// source-over, and the path has no name.
+    /// Initially, there is no clip path, the path is filled rather than stroked, the fill rule is
+    /// set to winding, the blend mode is set to source-over, the transform is the identity, the
+    /// global dilation applies, the path is visible, the Z index is zero, and the path has no
+    /// name.
     #[inline]
     pub fn new(outline: Outline, paint: PaintId) -> DrawPath {
         DrawPath {
@@ -521,6 +1697,16 @@ impl DrawPath {
             clip_path: None,
             fill_rule: FillRule::Winding,
             blend_mode: BlendMode::SrcOver,
+            transform: Transform2F::default(),
+            dilation: None,
+            antialias: true,
+            text: false,
+            stroke: None,
+            dash_array: vec![],
+            dash_offset: 0.0,
+            stroked_outline: None,
+            visible: true,
+            z_index: 0,
             name: String::new(),
         }
     }
@@ -531,6 +1717,42 @@ impl DrawPath {
         &self.outline
     }
 
+    // Returns the outline to be filled: the expanded stroke if this path is stroked, or the
+    // original outline otherwise.
+    #[inline]
+    pub(crate) fn fill_outline(&self) -> &Outline {
+        match self.stroked_outline {
+            Some(ref stroked_outline) => &stroked_outline.outline,
+            None => &self.outline,
+        }
+    }
+
+    /// Sets the style that this path is stroked with, or `None` to fill it.
+    #[inline]
+    pub fn set_stroke(&mut self, new_stroke: Option<StrokeStyle>) {
+        self.stroke = new_stroke
+    }
+
+    /// Sets the dash pattern that strokes are split into. Pass an empty array for solid strokes.
+    #[inline]
+    pub fn set_dash(&mut self, new_dash_array: Vec<f32>, new_dash_offset: f32) {
+        self.dash_array = new_dash_array;
+        self.dash_offset = new_dash_offset;
+    }
+
+    // Returns the dash pattern to split strokes into, or `None` if strokes are solid.
+    fn dash_pattern(&self) -> Option<Cow<'_, [f32]>> {
+        let valid = self.dash_array.iter().all(|&dash| dash.is_finite() && dash >= 0.0);
+        if !valid || self.dash_array.iter().sum::<f32>() <= 0.0 {
+            return None;
+        }
+        if self.dash_array.len() % 2 == 1 {
+            let doubled = self.dash_array.iter().chain(self.dash_array.iter()).cloned().collect();
+            return Some(Cow::Owned(doubled));
+        }
+        Some(Cow::Borrowed(&self.dash_array))
+    }
+
     #[inline]
     pub(crate) fn clip_path(&self) -> Option<ClipPathId> {
         self.clip_path
@@ -573,6 +1795,71 @@ impl DrawPath {
         self.blend_mode = new_blend_mode
     }
 
+    #[inline]
+    pub(crate) fn transform(&self) -> Transform2F {
+        self.transform
+    }
+
+    /// Sets the transform applied to the outline of this path before the global transform.
+    #[inline]
+    pub fn set_transform(&mut self, new_transform: Transform2F) {
+        self.transform = new_transform
+    }
+
+    // Returns the number of device pixels to expand this path by.
+    #[inline]
+    pub(crate) fn dilation(&self, options: &PreparedBuildOptions) -> Vector2F {
+        self.dilation.unwrap_or(options.dilation)
+    }
+
+    /// Sets the number of device pixels to expand this path by, or `None` to use the dilation in
+    /// the build options.
+    #[inline]
+    pub fn set_dilation(&mut self, new_dilation: Option<Vector2F>) {
+        self.dilation = new_dilation
+    }
+
+    #[inline]
+    pub(crate) fn antialias(&self) -> bool {
+        self.antialias
+    }
+
+    /// Sets whether this path is antialiased. Turn antialiasing off for hard-edged pixel art.
+    #[inline]
+    pub fn set_antialias(&mut self, antialias: bool) {
+        self.antialias = antialias
+    }
+
+    #[inline]
+    pub(crate) fn is_text(&self) -> bool {
+        self.text
+    }
+
+    /// Flags this path as text, so that its edges are snapped to the pixel grid when `grid_fit`
+    /// is set in the build options.
+    #[inline]
+    pub fn set_text(&mut self, text: bool) {
+        self.text = text
+    }
+
+    /// Sets whether this path is drawn.
+    #[inline]
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible
+    }
+
+    #[inline]
+    pub(crate) fn z_index(&self) -> i32 {
+        self.z_index
+    }
+
+    /// Sets the Z index, which determines where this path is painted relative to other paths
+    /// drawn to the same render target, independently of the order in which paths were added.
+    #[inline]
+    pub fn set_z_index(&mut self, new_z_index: i32) {
+        self.z_index = new_z_index
+    }
+
     /// Assigns a name to this path, for debugging.
     #[inline]
     pub fn set_name(&mut self, new_name: String) {
@@ -670,3 +1957,475 @@ impl PathId {
         DrawPathId(self.0)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::concurrent::executor::SequentialExecutor;
+    use crate::gpu::options::RendererLevel;
+    use crate::gpu::renderer::unpremultiply_color;
+    use crate::gpu_data::RenderCommand;
+    use crate::options::{BuildOptions, RenderCommandListener};
+    use crate::paint::{Paint, PaintId};
+    use instant::Instant;
+    use pathfinder_color::{ColorF, ColorU};
+    use pathfinder_content::fill::FillRule;
+    use pathfinder_content::outline::{Contour, Outline};
+    use pathfinder_geometry::rect::RectF;
+    use pathfinder_geometry::transform2d::Transform2F;
+    use pathfinder_geometry::vector::{Vector2F, vec2f, vec2i};
+    use std::sync::{Arc, Mutex};
+
+    use super::{BuildOutcome, BuildProgress, ClipPath, DisplayItem, DrawPath, DrawPathId};
+    use super::{RenderTarget, Scene};
+    use super::SceneSink;
+
+    // Builds a scene of overlapping rounded rectangles in different colors, some of them clipped.
+    fn fixture_scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(128.0, 128.0)));
+        let clip_outline = Outline::from_rect(RectF::new(vec2f(16.5, 12.0), vec2f(80.0, 90.0)));
+        let clip_path = scene.push_clip_path(ClipPath::new(clip_outline));
+        for index in 0..16 {
+            let color = ColorU::new((index * 16) as u8, 0, 255 - (index * 16) as u8, 160);
+            let paint = scene.push_paint(&Paint::from_color(color));
+            let origin = vec2f((index % 4) as f32 * 27.3, (index / 4) as f32 * 29.7);
+            let outline = Outline::from_rect_rounded(RectF::new(origin, vec2f(48.0, 40.0)),
+                                                     vec2f(9.0, 7.0));
+            let mut draw_path = DrawPath::new(outline, paint);
+            if index % 5 == 0 {
+                draw_path.set_clip_path(Some(clip_path));
+            }
+            scene.push_draw_path(draw_path);
+        }
+        scene
+    }
+
+    // The `Debug` output of commands only summarizes their buffers, so fills and tiles are
+    // described in full. Build times are left out, as they vary between builds, and so is
+    // `ViewBoxChanged`, which is only sent by the first build into a sink.
+    fn describe_commands(commands: &[RenderCommand]) -> Vec<String> {
+        commands.iter().filter_map(|command| {
+            match *command {
+                RenderCommand::AddFillsD3D9(ref fills) => {
+                    Some(format!("AddFillsD3D9({:?})", fills))
+                }
+                RenderCommand::DrawTilesD3D9(ref batch) => {
+                    Some(format!("DrawTilesD3D9({:?}, {:?})", batch.tiles, batch.clips))
+                }
+                RenderCommand::ViewBoxChanged(_) => None,
+                RenderCommand::Finish { .. } => Some("Finish".to_owned()),
+                ref command => Some(format!("{:?}", command)),
+            }
+        }).collect()
+    }
+
+    fn describe_full_build(scene: &mut Scene, options: BuildOptions) -> Vec<String> {
+        describe_commands(&scene.build_to_vec(options, RendererLevel::D3D9, &SequentialExecutor))
+    }
+
+    fn deterministic_options() -> BuildOptions {
+        BuildOptions { deterministic: true, ..BuildOptions::default() }
+    }
+
+    #[test]
+    fn test_incremental_build_of_one_dirty_path_matches_full_build() {
+        let mut scene = fixture_scene();
+        let commands = Arc::new(Mutex::new(vec![]));
+        let commands_for_listener = commands.clone();
+        let listener = RenderCommandListener::new(Box::new(move |command| {
+            commands_for_listener.lock().unwrap().push(command)
+        }));
+        let mut sink = SceneSink::new(listener, RendererLevel::D3D9);
+
+        let draw_path_count = scene.draw_path_count() as usize;
+        scene.build_incremental(deterministic_options(),
+                                &mut sink,
+                                &SequentialExecutor,
+                                &vec![true; draw_path_count]);
+        commands.lock().unwrap().clear();
+
+        scene.set_draw_path_visible(DrawPathId(6), false);
+        let mut dirty_draw_paths = vec![false; draw_path_count];
+        dirty_draw_paths[6] = true;
+        scene.build_incremental(deterministic_options(),
+                                &mut sink,
+                                &SequentialExecutor,
+                                &dirty_draw_paths);
+        let incremental_commands = describe_commands(&commands.lock().unwrap());
+
+        let full_commands = describe_full_build(&mut scene, deterministic_options());
+        assert!(full_commands.iter().any(|command| command.starts_with("DrawTilesD3D9")));
+        assert_eq!(incremental_commands, full_commands);
+    }
+
+    #[test]
+    fn test_changing_cached_option_invalidates_incremental_build() {
+        let mut scene = fixture_scene();
+        let commands = Arc::new(Mutex::new(vec![]));
+        let commands_for_listener = commands.clone();
+        let listener = RenderCommandListener::new(Box::new(move |command| {
+            commands_for_listener.lock().unwrap().push(command)
+        }));
+        let mut sink = SceneSink::new(listener, RendererLevel::D3D9);
+
+        let draw_path_count = scene.draw_path_count() as usize;
+        scene.build_incremental(deterministic_options(),
+                                &mut sink,
+                                &SequentialExecutor,
+                                &vec![true; draw_path_count]);
+        commands.lock().unwrap().clear();
+
+        // No path is marked dirty, so only the cache key can tell that the paths must be rebuilt.
+        let dilated_options = BuildOptions {
+            dilation: Vector2F::splat(2.0),
+            ..deterministic_options()
+        };
+        scene.build_incremental(dilated_options.clone(),
+                                &mut sink,
+                                &SequentialExecutor,
+                                &vec![false; draw_path_count]);
+        let incremental_commands = describe_commands(&commands.lock().unwrap());
+
+        let full_commands = describe_full_build(&mut scene, dilated_options.clone());
+        assert_ne!(full_commands, describe_full_build(&mut scene, deterministic_options()));
+        assert_eq!(incremental_commands, full_commands);
+    }
+
+    #[test]
+    fn test_global_alpha_only_fades_main_output() {
+        // The same paint is drawn into a render target and to the main output.
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(64.0, 64.0)));
+        let paint = scene.push_paint(&Paint::from_color(ColorU::new(255, 0, 0, 255)));
+        let outline = Outline::from_rect(RectF::new(vec2f(0.0, 0.0), vec2f(32.0, 32.0)));
+        scene.push_render_target(RenderTarget::new(vec2i(64, 64), String::new()));
+        scene.push_draw_path(DrawPath::new(outline.clone(), paint));
+        scene.pop_render_target();
+        scene.push_draw_path(DrawPath::new(outline, paint));
+
+        let options = BuildOptions { global_alpha: Some(0.5), ..BuildOptions::default() };
+        let commands = scene.build_to_vec(options, RendererLevel::D3D9, &SequentialExecutor);
+        let mut metadata = None;
+        let mut in_render_target = false;
+        let (mut render_target_alphas, mut main_alphas) = (vec![], vec![]);
+        for command in &commands {
+            match *command {
+                RenderCommand::UploadTextureMetadata(ref entries) => metadata = Some(entries),
+                RenderCommand::PushRenderTarget(_) => in_render_target = true,
+                RenderCommand::PopRenderTarget => in_render_target = false,
+                RenderCommand::DrawTilesD3D9(ref batch) => {
+                    for tile in &batch.tiles {
+                        let alpha = metadata.unwrap()[tile.color as usize].base_color.to_u8().a;
+                        if in_render_target {
+                            render_target_alphas.push(alpha);
+                        } else {
+                            main_alphas.push(alpha);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        assert!(!render_target_alphas.is_empty() && !main_alphas.is_empty());
+        assert!(render_target_alphas.iter().all(|&alpha| alpha == 255));
+        assert!(main_alphas.iter().all(|&alpha| alpha == 128));
+    }
+
+    #[test]
+    fn test_dash_pattern_doubles_odd_lengths_and_rejects_empty_patterns() {
+        let mut draw_path = DrawPath::new(Outline::new(), PaintId(0));
+        assert!(draw_path.dash_pattern().is_none());
+
+        draw_path.set_dash(vec![3.0, 1.0], 0.0);
+        assert_eq!(draw_path.dash_pattern().as_deref(), Some(&[3.0, 1.0][..]));
+
+        // Like canvas, odd-length patterns repeat to make an even number of entries.
+        draw_path.set_dash(vec![3.0, 1.0, 2.0], 0.0);
+        assert_eq!(draw_path.dash_pattern().as_deref(), Some(&[3.0, 1.0, 2.0, 3.0, 1.0, 2.0][..]));
+
+        // Patterns that sum to zero would never advance, and invalid ones are ignored; either way,
+        // the stroke is solid.
+        for dashes in &[vec![0.0, 0.0], vec![3.0, -1.0], vec![3.0, f32::NAN]] {
+            draw_path.set_dash(dashes.clone(), 0.0);
+            assert!(draw_path.dash_pattern().is_none());
+        }
+    }
+
+    #[test]
+    fn test_premultiplied_base_colors_round_trip_exactly() {
+        // With only 3/255 alpha, premultiplying in 8 bits would leave too little to recover the
+        // color channels from.
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(64.0, 64.0)));
+        for &color in &[ColorU::new(200, 100, 50, 3), ColorU::new(13, 255, 77, 128)] {
+            let paint = scene.push_paint(&Paint::from_color(color));
+            let outline = Outline::from_rect(RectF::new(vec2f(0.0, 0.0), vec2f(32.0, 32.0)));
+            scene.push_draw_path(DrawPath::new(outline, paint));
+        }
+
+        let straight_colors = uploaded_base_colors(&mut scene, false);
+        let premultiplied_colors = uploaded_base_colors(&mut scene, true);
+        assert_eq!(straight_colors.len(), 2);
+        assert_eq!(premultiplied_colors.len(), 2);
+        for (&straight, &premultiplied) in straight_colors.iter().zip(&premultiplied_colors) {
+            assert!(premultiplied.r() < straight.r());
+            let unpremultiplied = unpremultiply_color(premultiplied);
+            for &(actual, expected) in &[(unpremultiplied.r(), straight.r()),
+                                         (unpremultiplied.g(), straight.g()),
+                                         (unpremultiplied.b(), straight.b()),
+                                         (unpremultiplied.a(), straight.a())] {
+                assert!((actual - expected).abs() < 0.0001);
+            }
+        }
+    }
+
+    fn uploaded_base_colors(scene: &mut Scene, premultiplied_alpha: bool) -> Vec<ColorF> {
+        let options = BuildOptions { premultiplied_alpha, ..BuildOptions::default() };
+        let commands = scene.build_to_vec(options, RendererLevel::D3D9, &SequentialExecutor);
+        commands.iter().filter_map(|command| {
+            match *command {
+                RenderCommand::UploadTextureMetadata(ref entries) => Some(entries),
+                _ => None,
+            }
+        }).flat_map(|entries| entries.iter().map(|entry| entry.base_color)).collect()
+    }
+
+    // Returns the paint order of paths with the given colors and bounds, grouped by paint.
+    fn grouped_paint_order(paths: &[(ColorU, RectF)]) -> Vec<DrawPathId> {
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(256.0, 256.0)));
+        for &(color, rect) in paths {
+            let paint = scene.push_paint(&Paint::from_color(color));
+            scene.push_draw_path(DrawPath::new(Outline::from_rect(rect), paint));
+        }
+        let options = BuildOptions { group_by_paint: true, ..BuildOptions::default() };
+        let options = scene.prepare_build_options(options);
+        scene.paint_order(DrawPathId(0)..DrawPathId(paths.len() as u32), &options)
+    }
+
+    #[test]
+    fn test_group_by_paint_keeps_overlapping_paths_in_order() {
+        let (red, blue) = (ColorU::new(255, 0, 0, 255), ColorU::new(0, 0, 255, 255));
+        let paint_order = grouped_paint_order(&[
+            (red, RectF::new(vec2f(0.0, 0.0), vec2f(32.0, 32.0))),
+            (blue, RectF::new(vec2f(16.0, 16.0), vec2f(32.0, 32.0))),
+            (red, RectF::new(vec2f(32.0, 32.0), vec2f(32.0, 32.0))),
+        ]);
+        assert_eq!(paint_order, vec![DrawPathId(0), DrawPathId(1), DrawPathId(2)]);
+    }
+
+    #[test]
+    fn test_group_by_paint_merges_disjoint_paths() {
+        let (red, blue) = (ColorU::new(255, 0, 0, 255), ColorU::new(0, 0, 255, 255));
+        let paint_order = grouped_paint_order(&[
+            (red, RectF::new(vec2f(0.0, 0.0), vec2f(16.0, 16.0))),
+            (blue, RectF::new(vec2f(64.0, 64.0), vec2f(16.0, 16.0))),
+            (red, RectF::new(vec2f(128.0, 128.0), vec2f(16.0, 16.0))),
+            (blue, RectF::new(vec2f(192.0, 192.0), vec2f(16.0, 16.0))),
+        ]);
+        assert_eq!(paint_order, vec![DrawPathId(0), DrawPathId(2), DrawPathId(1), DrawPathId(3)]);
+    }
+
+    // Builds a scene with more paths than are tiled in one chunk of a sliced build.
+    fn many_path_scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(256.0, 256.0)));
+        for index in 0..600 {
+            let color = ColorU::new((index % 256) as u8, 0, 128, 200);
+            let paint = scene.push_paint(&Paint::from_color(color));
+            let origin = vec2f((index % 25) as f32 * 10.3, (index / 25) as f32 * 10.7);
+            let outline = Outline::from_rect(RectF::new(origin, vec2f(14.5, 12.5)));
+            scene.push_draw_path(DrawPath::new(outline, paint));
+        }
+        scene
+    }
+
+    fn recording_sink<'a>() -> (SceneSink<'a>, Arc<Mutex<Vec<RenderCommand>>>) {
+        let commands = Arc::new(Mutex::new(vec![]));
+        let commands_for_listener = commands.clone();
+        let listener = RenderCommandListener::new(Box::new(move |command| {
+            commands_for_listener.lock().unwrap().push(command)
+        }));
+        (SceneSink::new(listener, RendererLevel::D3D9), commands)
+    }
+
+    #[test]
+    fn test_sliced_build_matches_full_build() {
+        let mut scene = many_path_scene();
+        let (mut sink, commands) = recording_sink();
+
+        // Every deadline has passed by the time it's checked, so each slice tiles one chunk.
+        let mut slice_count = 1;
+        let mut progress = scene.build_until(deterministic_options(),
+                                             &mut sink,
+                                             &SequentialExecutor,
+                                             Instant::now());
+        while let BuildProgress::Paused(cursor) = progress {
+            slice_count += 1;
+            progress = scene.resume_build(cursor, &mut sink, &SequentialExecutor, Instant::now());
+        }
+        match progress {
+            BuildProgress::Finished(BuildOutcome::Finished) => {}
+            _ => panic!("The sliced build didn't finish!"),
+        }
+        assert!(slice_count > 1);
+
+        let sliced_commands = describe_commands(&commands.lock().unwrap());
+        assert_eq!(sliced_commands, describe_full_build(&mut scene, deterministic_options()));
+    }
+
+    #[test]
+    fn test_changing_scene_between_slices_cancels_build() {
+        let mut scene = many_path_scene();
+        let (mut sink, _) = recording_sink();
+
+        let cursor = match scene.build_until(deterministic_options(),
+                                             &mut sink,
+                                             &SequentialExecutor,
+                                             Instant::now()) {
+            BuildProgress::Paused(cursor) => cursor,
+            BuildProgress::Finished(_) => panic!("The build should have been paused!"),
+        };
+        scene.set_draw_path_visible(DrawPathId(0), false);
+        match scene.resume_build(cursor, &mut sink, &SequentialExecutor, Instant::now()) {
+            BuildProgress::Finished(BuildOutcome::Cancelled) => {}
+            _ => panic!("The build should have been cancelled!"),
+        }
+    }
+
+    fn hit_test_frame(scene: &mut Scene) -> super::BuiltFrame {
+        scene.build_frame(BuildOptions::default(), RendererLevel::D3D9, &SequentialExecutor)
+    }
+
+    #[test]
+    fn test_draw_path_at_hits_frontmost_covering_path() {
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(64.0, 64.0)));
+        let paint = scene.push_paint(&Paint::from_color(ColorU::black()));
+        let back_rect = RectF::new(vec2f(8.0, 8.0), vec2f(40.0, 40.0));
+        let back = scene.push_draw_path(DrawPath::new(Outline::from_rect(back_rect), paint));
+        // The left edge of the front path cuts the pixels in column 24 in half.
+        let front_rect = RectF::new(vec2f(24.5, 24.0), vec2f(32.0, 32.0));
+        let front = scene.push_draw_path(DrawPath::new(Outline::from_rect(front_rect), paint));
+        let frame = hit_test_frame(&mut scene);
+
+        assert_eq!(frame.draw_path_at(vec2f(16.5, 16.5), 0.5), Some(back));
+        assert_eq!(frame.draw_path_at(vec2f(30.5, 30.5), 0.5), Some(front));
+        assert_eq!(frame.draw_path_at(vec2f(52.5, 52.5), 0.5), Some(front));
+        assert_eq!(frame.draw_path_at(vec2f(4.5, 4.5), 0.5), None);
+        assert_eq!(frame.draw_path_at(vec2f(60.5, 12.5), 0.5), None);
+        assert_eq!(frame.draw_path_at(vec2f(24.5, 40.5), 0.4), Some(front));
+        assert_eq!(frame.draw_path_at(vec2f(24.5, 40.5), 0.6), Some(back));
+        assert_eq!(frame.draw_path_at(vec2f(56.5, 40.5), 0.0), Some(front));
+    }
+
+    #[test]
+    fn test_draw_path_at_applies_clip_paths() {
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(64.0, 64.0)));
+        let paint = scene.push_paint(&Paint::from_color(ColorU::black()));
+        // The right edge of the clip cuts the pixels in column 32 in half.
+        let clip_rect = RectF::new(vec2f(0.0, 0.0), vec2f(32.5, 64.0));
+        let clip_path = scene.push_clip_path(ClipPath::new(Outline::from_rect(clip_rect)));
+        let rect = RectF::new(vec2f(8.0, 8.0), vec2f(48.0, 48.0));
+        let mut draw_path = DrawPath::new(Outline::from_rect(rect), paint);
+        draw_path.set_clip_path(Some(clip_path));
+        let draw_path_id = scene.push_draw_path(draw_path);
+        let frame = hit_test_frame(&mut scene);
+
+        assert_eq!(frame.draw_path_at(vec2f(16.5, 16.5), 0.5), Some(draw_path_id));
+        assert_eq!(frame.draw_path_at(vec2f(28.5, 40.5), 0.5), Some(draw_path_id));
+        assert_eq!(frame.draw_path_at(vec2f(40.5, 40.5), 0.5), None);
+        assert_eq!(frame.draw_path_at(vec2f(48.5, 16.5), 0.5), None);
+        assert_eq!(frame.draw_path_at(vec2f(32.5, 24.5), 0.4), Some(draw_path_id));
+        assert_eq!(frame.draw_path_at(vec2f(32.5, 24.5), 0.6), None);
+    }
+
+    #[test]
+    fn test_draw_path_at_applies_even_odd_fill_rule() {
+        // The inner contour winds the same way as the outer one, so it's only a hole under the
+        // even-odd fill rule.
+        let hit = |fill_rule: FillRule, point: Vector2F| {
+            let mut scene = Scene::new();
+            scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(64.0, 64.0)));
+            let paint = scene.push_paint(&Paint::from_color(ColorU::black()));
+            let mut outline = Outline::from_rect(RectF::new(vec2f(8.0, 8.0), vec2f(48.0, 48.0)));
+            outline.push_contour(Contour::from_rect(RectF::new(vec2f(20.0, 20.0),
+                                                               vec2f(24.0, 24.0))));
+            let mut draw_path = DrawPath::new(outline, paint);
+            draw_path.set_fill_rule(fill_rule);
+            let draw_path_id = scene.push_draw_path(draw_path);
+            hit_test_frame(&mut scene).draw_path_at(point, 0.5) == Some(draw_path_id)
+        };
+
+        assert!(hit(FillRule::EvenOdd, vec2f(12.5, 12.5)));
+        assert!(hit(FillRule::EvenOdd, vec2f(50.5, 30.5)));
+        assert!(!hit(FillRule::EvenOdd, vec2f(32.5, 32.5)));
+        assert!(!hit(FillRule::EvenOdd, vec2f(22.5, 40.5)));
+        assert!(hit(FillRule::Winding, vec2f(32.5, 32.5)));
+        assert!(hit(FillRule::Winding, vec2f(22.5, 40.5)));
+    }
+
+    #[test]
+    fn test_draw_path_at_ignores_paths_drawn_to_render_targets() {
+        let mut scene = Scene::new();
+        scene.set_view_box(RectF::new(vec2f(0.0, 0.0), vec2f(64.0, 64.0)));
+        let paint = scene.push_paint(&Paint::from_color(ColorU::black()));
+        let render_target_rect = RectF::new(vec2f(0.0, 0.0), vec2f(64.0, 64.0));
+        scene.push_render_target(RenderTarget::new(vec2i(64, 64), String::new()));
+        scene.push_draw_path(DrawPath::new(Outline::from_rect(render_target_rect), paint));
+        scene.pop_render_target();
+        let rect = RectF::new(vec2f(40.0, 40.0), vec2f(16.0, 16.0));
+        let draw_path_id = scene.push_draw_path(DrawPath::new(Outline::from_rect(rect), paint));
+        let frame = hit_test_frame(&mut scene);
+
+        assert_eq!(frame.draw_path_at(vec2f(16.5, 16.5), 0.0), None);
+        assert_eq!(frame.draw_path_at(vec2f(36.5, 36.5), 0.5), None);
+        assert_eq!(frame.draw_path_at(vec2f(48.5, 48.5), 0.5), Some(draw_path_id));
+    }
+
+    #[test]
+    fn test_retain_intersecting_renumbers_paths_and_keeps_render_target_paths() {
+        let mut scene = Scene::new();
+        let paint = scene.push_paint(&Paint::from_color(ColorU::black()));
+        let inside = RectF::new(vec2f(10.0, 10.0), vec2f(20.0, 20.0));
+        let outside = RectF::new(vec2f(200.0, 200.0), vec2f(20.0, 20.0));
+        let push_named_path = |scene: &mut Scene, rect: RectF, transform: Transform2F, name| {
+            let mut draw_path = DrawPath::new(Outline::from_rect(rect), paint);
+            draw_path.set_transform(transform);
+            draw_path.set_name(String::from(name));
+            scene.push_draw_path(draw_path);
+        };
+        let identity = Transform2F::default();
+        push_named_path(&mut scene, inside, identity, "a");
+        push_named_path(&mut scene, outside, identity, "b");
+        scene.push_render_target(RenderTarget::new(vec2i(64, 64), String::new()));
+        push_named_path(&mut scene, outside, identity, "c");
+        scene.pop_render_target();
+        push_named_path(&mut scene, outside, identity, "d");
+        // This path's outline is outside, but its transform moves it inside.
+        let moved_inside = Transform2F::from_translation(vec2f(-190.0, -190.0));
+        push_named_path(&mut scene, outside, moved_inside, "e");
+        push_named_path(&mut scene, outside, identity, "f");
+        push_named_path(&mut scene, inside, identity, "g");
+
+        let mapping = scene.retain_intersecting(RectF::new(vec2f(0.0, 0.0), vec2f(50.0, 50.0)));
+
+        let new_ids: Vec<_> = mapping.iter().map(|id| id.map(|id| id.0)).collect();
+        assert_eq!(new_ids, vec![Some(0), None, Some(1), None, Some(2), None, Some(3)]);
+        let names: Vec<_> = (0..scene.draw_path_count()).map(|index| {
+            scene.get_draw_path(DrawPathId(index)).name.clone()
+        }).collect();
+        assert_eq!(names, vec!["a", "c", "e", "g"]);
+
+        let display_list: Vec<_> = scene.display_list.iter().map(|display_item| {
+            match *display_item {
+                DisplayItem::DrawPaths(ref range) => format!("{}..{}", range.start.0, range.end.0),
+                DisplayItem::PushRenderTarget(_) => "Push".to_owned(),
+                DisplayItem::PopRenderTarget => "Pop".to_owned(),
+            }
+        }).collect();
+        assert_eq!(display_list, vec!["0..1", "Push", "1..2", "Pop", "2..4"]);
+    }
+}
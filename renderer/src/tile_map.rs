@@ -12,6 +12,7 @@ use pathfinder_geometry::rect::RectI;
 use pathfinder_geometry::vector::{Vector2I, vec2i};
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "pf-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DenseTileMap<T> where T: Clone + Copy {
     pub data: Vec<T>,
     pub rect: RectI,
@@ -30,6 +31,15 @@ impl<T> DenseTileMap<T> where T: Clone + Copy {
         DenseTileMap { data, rect }
     }
 
+    /// Sets every entry to `value` and resizes the map to cover `rect`, reusing the existing
+    /// allocation if it's large enough.
+    #[inline]
+    pub fn reset(&mut self, value: T, rect: RectI) {
+        self.data.clear();
+        self.data.resize(rect.size().x() as usize * rect.size().y() as usize, value);
+        self.rect = rect;
+    }
+
     #[inline]
     pub fn get(&self, coords: Vector2I) -> Option<&T> {
         self.coords_to_index(coords).and_then(|index| self.data.get(index))
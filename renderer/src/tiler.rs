@@ -23,17 +23,60 @@ use pathfinder_content::outline::{ContourIterFlags, Outline};
 use pathfinder_content::segment::Segment;
 use pathfinder_geometry::line_segment::LineSegment2F;
 use pathfinder_geometry::rect::RectF;
+use pathfinder_geometry::util;
 use pathfinder_geometry::vector::{Vector2F, Vector2I, vec2f, vec2i};
 use pathfinder_simd::default::{F32x2, U32x2};
+use std::cell::RefCell;
 use std::f32::NEG_INFINITY;
 
-const FLATTENING_TOLERANCE: f32 = 0.25;
+// Grid fitting only snaps edges that are at least this long, in device pixels, so that short
+// edges from flattened curves don't flatten the curves.
+const GRID_FIT_MIN_EDGE_LENGTH: f32 = 1.0;
+// Grid fitting only snaps edges whose slope relative to the nearer axis is at most this (about 6
+// degrees).
+const GRID_FIT_MAX_SLOPE: f32 = 0.1;
+// Scratch buffers that grew past this many elements while tiling a path are freed afterward, so
+// that one huge path doesn't pin its memory to a thread for the rest of the program.
+const MAX_RETAINED_SCRATCH_LEN: usize = 65536;
+
+thread_local! {
+    static TILER_SCRATCH: RefCell<TilerScratch> = RefCell::new(TilerScratch::default());
+}
+
+// Temporary buffers for tiling one path on CPU, reused from path to path so that tiling thousands
+// of small paths doesn't keep going back to the allocator. Each thread has its own, so threads of
+// a work-stealing executor never contend for them.
+#[derive(Default)]
+struct TilerScratch {
+    // The flattened line segments of the contour being tiled.
+    line_segments: Vec<LineSegment2F>,
+    grid_fit: GridFitScratch,
+}
+
+#[derive(Default)]
+struct GridFitScratch {
+    points: Vec<Vector2F>,
+    snapped_x: Vec<Option<(f32, f32)>>,
+    snapped_y: Vec<Option<(f32, f32)>>,
+}
+
+impl TilerScratch {
+    fn trim(&mut self) {
+        // The grid fitting buffers are no larger than the line segments.
+        if self.line_segments.capacity() > MAX_RETAINED_SCRATCH_LEN {
+            *self = TilerScratch::default();
+        }
+    }
+}
 
 pub(crate) struct Tiler<'a, 'b, 'c, 'd> {
     scene_builder: &'a SceneBuilder<'b, 'a, 'c, 'd>,
     pub(crate) object_builder: ObjectBuilder,
     outline: &'a Outline,
     clip_path: Option<&'a BuiltPath>,
+    antialias: bool,
+    grid_fit: bool,
+    conservative: bool,
 }
 
 impl<'a, 'b, 'c, 'd> Tiler<'a, 'b, 'c, 'd> {
@@ -62,7 +105,15 @@ impl<'a, 'b, 'c, 'd> Tiler<'a, 'b, 'c, 'd> {
                                                 clip_path_id,
                                                 &path_info);
 
-        Tiler { scene_builder, object_builder, outline, clip_path }
+        Tiler {
+            scene_builder,
+            object_builder,
+            outline,
+            clip_path,
+            antialias: path_info.antialias(),
+            grid_fit: path_info.grid_fit(),
+            conservative: path_info.conservative(),
+        }
     }
 
     pub(crate) fn generate_tiles(&mut self) {
@@ -70,6 +121,11 @@ impl<'a, 'b, 'c, 'd> Tiler<'a, 'b, 'c, 'd> {
             BuiltPathData::CPU(_) => {
                 self.generate_fills();
                 self.prepare_tiles();
+
+                // Conservatively rasterized paths have no masks left to fill.
+                if self.conservative {
+                    self.object_builder.fills.clear();
+                }
             }
             BuiltPathData::TransformCPUBinGPU(ref mut data) => {
                 data.outline = (*self.outline).clone();
@@ -83,15 +139,68 @@ impl<'a, 'b, 'c, 'd> Tiler<'a, 'b, 'c, 'd> {
     fn generate_fills(&mut self) {
         debug_assert_eq!(self.scene_builder.sink.renderer_level, RendererLevel::D3D9);
 
+        if !self.antialias || self.grid_fit {
+            TILER_SCRATCH.with(|scratch| self.generate_flattened_fills(&mut scratch.borrow_mut()));
+            return;
+        }
+
         for contour in self.outline.contours() {
+            // Stop early if this path would take the build over its fill limit.
+            if self.scene_builder.fill_limit_exceeded(self.object_builder.fills.len()) {
+                return;
+            }
+
             for segment in contour.iter(ContourIterFlags::empty()) {
                 process_segment(&segment, self.scene_builder, &mut self.object_builder);
             }
         }
     }
 
+    // Generates fills from the flattened contours, for paths that are grid fitted or drawn without
+    // antialiasing.
+    fn generate_flattened_fills(&mut self, scratch: &mut TilerScratch) {
+        let (scene_builder, object_builder) = (self.scene_builder, &mut self.object_builder);
+        let tolerance = scene_builder.built_options.aa_quality.flattening_tolerance();
+
+        for contour in self.outline.contours() {
+            // Stop early if this path would take the build over its fill limit.
+            if scene_builder.fill_limit_exceeded(object_builder.fills.len()) {
+                break;
+            }
+
+            let line_segments = &mut scratch.line_segments;
+            line_segments.clear();
+            for segment in contour.iter(ContourIterFlags::empty()) {
+                flatten_segment(&segment, tolerance, &mut |line_segment| {
+                    line_segments.push(line_segment)
+                });
+            }
+            if self.grid_fit {
+                grid_fit_contour(line_segments, &mut scratch.grid_fit);
+            }
+
+            if self.antialias {
+                for &line_segment in line_segments.iter() {
+                    process_line_segment(line_segment, scene_builder, object_builder);
+                }
+                continue;
+            }
+
+            let mut process_step = |step| process_line_segment(step, scene_builder, object_builder);
+            let mut stepper = PixelStepper::new();
+            for &line_segment in line_segments.iter() {
+                stepper.add_line_segment(line_segment, &mut process_step);
+            }
+            stepper.close(&mut process_step);
+        }
+
+        scratch.trim();
+    }
+
     fn prepare_tiles(&mut self) {
         // Don't do this here if the GPU will do it.
+        let fill_rule = self.object_builder.built_path.fill_rule;
+        let conservative = self.conservative;
         let (backdrops, tiles, clips) = match self.object_builder.built_path.data {
             BuiltPathData::CPU(ref mut tiled_data) => {
                 (&mut tiled_data.backdrops, &mut tiled_data.tiles, &mut tiled_data.clip_tiles)
@@ -111,6 +220,23 @@ impl<'a, 'b, 'c, 'd> Tiler<'a, 'b, 'c, 'd> {
             let mut draw_alpha_tile_id = draw_tile.alpha_tile_id;
             let mut draw_tile_backdrop = backdrops[column] as i8;
 
+            // Under conservative rasterization, a tile that an edge passes through is fully
+            // covered, so swap its mask for a backdrop that's inside under either fill rule.
+            if conservative && draw_alpha_tile_id != AlphaTileId(!0) {
+                draw_alpha_tile_id = AlphaTileId(!0);
+                draw_tile_backdrop = 1;
+            }
+
+            // A tile without a mask is either entirely inside or entirely outside the path,
+            // depending on its winding number and the fill rule. Normalize the backdrops of tiles
+            // that are outside to zero so that they're treated as empty and don't occlude anything
+            // or let clip masks through. (Under the even-odd rule, a nonzero backdrop can still be
+            // outside.)
+            if draw_alpha_tile_id == AlphaTileId(!0) &&
+                    !fill_rule.is_inside(draw_tile_backdrop as i32) {
+                draw_tile_backdrop = 0;
+            }
+
             if let Some(built_clip_path) = self.clip_path {
                 let clip_tiles = match built_clip_path.data {
                     BuiltPathData::CPU(BuiltPathBinCPUData { ref tiles, .. }) => tiles,
@@ -166,21 +292,158 @@ impl<'a, 'b, 'c, 'd> Tiler<'a, 'b, 'c, 'd> {
 fn process_segment(segment: &Segment,
                    scene_builder: &SceneBuilder,
                    object_builder: &mut ObjectBuilder) {
+    let tolerance = scene_builder.built_options.aa_quality.flattening_tolerance();
+    flatten_segment(segment, tolerance, &mut |line_segment| {
+        process_line_segment(line_segment, scene_builder, object_builder)
+    });
+}
+
+// Flattens the given segment into lines within the given tolerance, passing each line in order
+// to `process_line_segment`.
+pub(crate) fn flatten_segment<F>(segment: &Segment, tolerance: f32, process_line_segment: &mut F)
+                                 where F: FnMut(LineSegment2F) {
     // TODO(pcwalton): Stop degree elevating.
     if segment.is_quadratic() {
         let cubic = segment.to_cubic();
-        return process_segment(&cubic, scene_builder, object_builder);
+        return flatten_segment(&cubic, tolerance, process_line_segment);
     }
 
     if segment.is_line() ||
-            (segment.is_cubic() && segment.as_cubic_segment().is_flat(FLATTENING_TOLERANCE)) {
-        return process_line_segment(segment.baseline, scene_builder, object_builder);
+            (segment.is_cubic() && segment.as_cubic_segment().is_flat(tolerance)) {
+        return process_line_segment(segment.baseline);
     }
 
     // TODO(pcwalton): Use a smarter flattening algorithm.
     let (prev, next) = segment.split(0.5);
-    process_segment(&prev, scene_builder, object_builder);
-    process_segment(&next, scene_builder, object_builder);
+    flatten_segment(&prev, tolerance, process_line_segment);
+    flatten_segment(&next, tolerance, process_line_segment);
+}
+
+// Snaps the near-axis-aligned edges of a flattened contour to pixel boundaries, for text drawn
+// with grid fitting.
+//
+// An edge snaps if it's at least `GRID_FIT_MIN_EDGE_LENGTH` long and its slope relative to the
+// nearer axis is at most `GRID_FIT_MAX_SLOPE`. Both of its endpoints move to the pixel boundary
+// nearest its midpoint, which straightens it exactly onto that boundary. Where two snapping edges
+// along the same axis share an endpoint, the longer edge wins. Points are moved rather than edges,
+// so the contour stays closed, and the remaining edges simply follow their endpoints.
+fn grid_fit_contour(line_segments: &mut Vec<LineSegment2F>, scratch: &mut GridFitScratch) {
+    if line_segments.is_empty() {
+        return;
+    }
+
+    // Edge `i` runs from point `i` to the next point, wrapping around if the contour is closed.
+    let GridFitScratch { ref mut points, ref mut snapped_x, ref mut snapped_y } = *scratch;
+    points.clear();
+    points.extend(line_segments.iter().map(|line| line.from()));
+    let last_point = line_segments[line_segments.len() - 1].to();
+    if last_point != points[0] {
+        points.push(last_point);
+    }
+    let point_count = points.len();
+
+    // For each point, the snapped X and Y coordinates, along with the lengths of the edges that
+    // they came from.
+    snapped_x.clear();
+    snapped_x.resize(point_count, None);
+    snapped_y.clear();
+    snapped_y.resize(point_count, None);
+    for (index, line_segment) in line_segments.iter().enumerate() {
+        let endpoints = [index, (index + 1) % point_count];
+        let vector = line_segment.vector();
+        let (width, height) = (vector.x().abs(), vector.y().abs());
+        let midpoint = line_segment.midpoint();
+        if height >= GRID_FIT_MIN_EDGE_LENGTH && width <= height * GRID_FIT_MAX_SLOPE {
+            snap_points(snapped_x, &endpoints, midpoint.x().round(), height);
+        } else if width >= GRID_FIT_MIN_EDGE_LENGTH && height <= width * GRID_FIT_MAX_SLOPE {
+            snap_points(snapped_y, &endpoints, midpoint.y().round(), width);
+        }
+    }
+
+    for (index, point) in points.iter_mut().enumerate() {
+        if let Some((x, _)) = snapped_x[index] {
+            point.set_x(x);
+        }
+        if let Some((y, _)) = snapped_y[index] {
+            point.set_y(y);
+        }
+    }
+
+    let line_segment_count = line_segments.len();
+    line_segments.clear();
+    line_segments.extend((0..line_segment_count).filter_map(|index| {
+        let (from, to) = (points[index], points[(index + 1) % point_count]);
+        if from == to { None } else { Some(LineSegment2F::new(from, to)) }
+    }));
+}
+
+// Snaps one coordinate of the given points to `coord`, except for points that a longer edge has
+// already snapped.
+fn snap_points(snapped: &mut [Option<(f32, f32)>], indices: &[usize], coord: f32, length: f32) {
+    for &index in indices {
+        match snapped[index] {
+            Some((_, other_length)) if other_length >= length => {}
+            _ => snapped[index] = Some((coord, length)),
+        }
+    }
+}
+
+// Replaces the edges of a contour with pixel-aligned steps, for paths drawn without antialiasing.
+//
+// Coverage is accumulated over the horizontal extent of each edge, so every edge is replaced with
+// a horizontal step across each pixel column whose center it spans, lying on the pixel boundary
+// nearest to the edge at that center. Vertical risers join the steps; they don't change coverage
+// but keep the contour closed. Every pixel is then either entirely inside or entirely outside of
+// the path, depending on whether its center is.
+struct PixelStepper {
+    first_point: Option<Vector2F>,
+    last_point: Option<Vector2F>,
+}
+
+impl PixelStepper {
+    fn new() -> PixelStepper {
+        PixelStepper { first_point: None, last_point: None }
+    }
+
+    fn add_line_segment<F>(&mut self, line_segment: LineSegment2F, process_step: &mut F)
+                           where F: FnMut(LineSegment2F) {
+        let (from, to) = (line_segment.from(), line_segment.to());
+        let (from_x, to_x) = (snap_to_pixel_boundary(from.x()), snap_to_pixel_boundary(to.x()));
+        let step = if to_x > from_x { 1 } else { -1 };
+        let mut x = from_x;
+        while x != to_x {
+            let center_x = x.min(x + step) as f32 + 0.5;
+            let t = (center_x - from.x()) / (to.x() - from.x());
+            let y = snap_to_pixel_boundary(util::lerp(from.y(), to.y(), t)) as f32;
+            self.push_point(vec2f(x as f32, y), process_step);
+            self.push_point(vec2f((x + step) as f32, y), process_step);
+            x += step;
+        }
+    }
+
+    fn close<F>(&mut self, process_step: &mut F) where F: FnMut(LineSegment2F) {
+        if let Some(first_point) = self.first_point {
+            self.push_point(first_point, process_step);
+        }
+    }
+
+    fn push_point<F>(&mut self, point: Vector2F, process_step: &mut F)
+                     where F: FnMut(LineSegment2F) {
+        match self.last_point {
+            None => self.first_point = Some(point),
+            Some(last_point) if last_point != point => {
+                process_step(LineSegment2F::new(last_point, point))
+            }
+            Some(_) => {}
+        }
+        self.last_point = Some(point);
+    }
+}
+
+// Rounds a coordinate to the nearest pixel boundary, rounding halves down, so that a pixel whose
+// center lies exactly on an edge falls on a consistent side of it.
+fn snap_to_pixel_boundary(coord: f32) -> i32 {
+    (coord - 0.5).ceil() as i32
 }
 
 // This is the meat of the technique. It implements the fast lattice-clipping algorithm from
@@ -312,3 +575,123 @@ enum StepDirection {
     X,
     Y,
 }
+
+#[cfg(test)]
+mod test {
+    use crate::concurrent::executor::SequentialExecutor;
+    use crate::gpu::options::RendererLevel;
+    use crate::gpu_data::RenderCommand;
+    use crate::options::{BuildOptions, ViewBoxEdges};
+    use crate::paint::Paint;
+    use crate::scene::{self, DrawPath, Scene};
+    use crate::tiles::{TILE_HEIGHT, TILE_WIDTH};
+    use pathfinder_color::ColorU;
+    use pathfinder_content::outline::Outline;
+    use pathfinder_geometry::line_segment::LineSegment2F;
+    use pathfinder_geometry::rect::RectF;
+    use pathfinder_geometry::vector::{Vector2I, vec2f, vec2i};
+    use std::collections::HashMap;
+
+    use super::{GridFitScratch, grid_fit_contour};
+
+    // Builds a scene containing a single rectangle at the D3D9 level and returns the coverage of
+    // each of the given pixels, in scene coordinates, computed from the tiles and fills.
+    fn rect_coverage(rect: RectF, view_box: RectF, antialias: bool, pixels: &[Vector2I])
+                     -> Vec<f32> {
+        let mut scene = Scene::new();
+        scene.set_view_box(view_box);
+        let paint = scene.push_paint(&Paint::from_color(ColorU::black()));
+        let mut draw_path = DrawPath::new(Outline::from_rect(rect), paint);
+        draw_path.set_antialias(antialias);
+        scene.push_draw_path(draw_path);
+
+        let options = BuildOptions {
+            view_box_edges: ViewBoxEdges::HalfOpen,
+            ..BuildOptions::default()
+        };
+        let frame = scene.build_frame(options, RendererLevel::D3D9, &SequentialExecutor);
+
+        let mut tiles = HashMap::new();
+        for command in frame.commands() {
+            if let RenderCommand::DrawTilesD3D9(ref batch) = *command {
+                for &tile in &batch.tiles {
+                    tiles.insert(vec2i(tile.tile_x as i32, tile.tile_y as i32), tile);
+                }
+            }
+        }
+
+        pixels.iter().map(|&pixel| {
+            let tile_coords = vec2i(pixel.x() / TILE_WIDTH as i32, pixel.y() / TILE_HEIGHT as i32);
+            let tile = match tiles.get(&tile_coords) {
+                None => return 0.0,
+                Some(tile) => tile,
+            };
+            let local_pixel = vec2i(pixel.x() % TILE_WIDTH as i32, pixel.y() % TILE_HEIGHT as i32);
+            let mut winding = tile.backdrop as f32;
+            for fill in frame.fills_for_tile(tile_coords) {
+                winding -= scene::area_below_fill(fill.line_segment, local_pixel);
+            }
+            winding.abs().min(1.0)
+        }).collect()
+    }
+
+    #[test]
+    fn test_half_open_view_boxes_split_shared_edge() {
+        // The view boxes meet at X = 100, which is in the middle of a tile column.
+        let left_view_box = RectF::new(vec2f(0.0, 0.0), vec2f(100.0, 100.0));
+        let right_view_box = RectF::new(vec2f(100.0, 0.0), vec2f(100.0, 100.0));
+        let rect = RectF::new(vec2f(50.0, 8.0), vec2f(100.0, 74.5));
+
+        let pixels: Vec<_> = (90..110).flat_map(|x| {
+            vec![vec2i(x, 8), vec2i(x, 40), vec2i(x, 82)]
+        }).collect();
+        let left_coverage = rect_coverage(rect, left_view_box, true, &pixels);
+        let right_coverage = rect_coverage(rect, right_view_box, true, &pixels);
+
+        for (index, &pixel) in pixels.iter().enumerate() {
+            // The bottom edge of the rectangle cuts the last row of pixels in half.
+            let expected = if pixel.y() == 82 { 0.5 } else { 1.0 };
+            let (left, right) = (left_coverage[index], right_coverage[index]);
+            assert!((left + right - expected).abs() < 0.01,
+                    "{:?} covered {} + {} instead of {}", pixel, left, right, expected);
+            let outside = if pixel.x() < 100 { right } else { left };
+            assert!(outside < 0.01, "{:?} covered {} outside its view box", pixel, outside);
+        }
+    }
+
+    #[test]
+    fn test_aliased_coverage_is_all_or_nothing() {
+        let view_box = RectF::new(vec2f(0.0, 0.0), vec2f(64.0, 64.0));
+        let rect = RectF::new(vec2f(10.3, 20.7), vec2f(30.4, 12.6));
+        let pixels: Vec<_> = (0..64).flat_map(|y| (0..64).map(move |x| vec2i(x, y))).collect();
+        let coverage = rect_coverage(rect, view_box, false, &pixels);
+
+        for (&pixel, &coverage) in pixels.iter().zip(&coverage) {
+            let center = pixel.to_f32() + vec2f(0.5, 0.5);
+            let expected = if rect.contains_point(center) { 1.0 } else { 0.0 };
+            assert_eq!(coverage, expected, "{:?} covered {}", pixel, coverage);
+        }
+    }
+
+    #[test]
+    fn test_grid_fitting_snaps_near_axis_edges_only() {
+        let corners = [
+            vec2f(10.3, 0.2),
+            vec2f(10.4, 20.3),
+            vec2f(30.2, 40.1),
+            vec2f(30.3, 0.4),
+        ];
+        let mut line_segments: Vec<_> = (0..corners.len()).map(|index| {
+            LineSegment2F::new(corners[index], corners[(index + 1) % corners.len()])
+        }).collect();
+        grid_fit_contour(&mut line_segments, &mut GridFitScratch::default());
+
+        // The left and right edges snap to X = 10 and X = 30 and the top edge to Y = 0, but the
+        // diagonal edge keeps the Y coordinates of its endpoints.
+        let points: Vec<_> = line_segments.iter().map(|line_segment| line_segment.from()).collect();
+        assert_eq!(points, vec![vec2f(10.0, 0.0),
+                                vec2f(10.0, 20.3),
+                                vec2f(30.0, 40.1),
+                                vec2f(30.0, 0.0)]);
+    }
+}
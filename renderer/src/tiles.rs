@@ -14,9 +14,18 @@ use crate::paint::PaintId;
 use pathfinder_content::effects::BlendMode;
 use pathfinder_content::fill::FillRule;
 use pathfinder_geometry::rect::{RectF, RectI};
-use pathfinder_geometry::vector::vec2f;
+use pathfinder_geometry::vector::{Vector2I, vec2f};
 
+/// The width of a tile, in device pixels.
+///
+/// This isn't configurable: besides the builder, the tile size is baked into the mask framebuffer
+/// layout, the D3D9 clip shaders, and the D3D11 compute shaders, so changing it requires
+/// regenerating the shaders. Tile coordinates are stored as `i16`, which limits scenes to
+/// 32,767 tiles (524,272 pixels at 16 pixels per tile) along each axis. Fill line segments are
+/// stored in 1/256-pixel units relative to their tile in `u16`s, which limits tiles to 256 pixels
+/// on a side.
 pub const TILE_WIDTH: u32 = 16;
+/// The height of a tile, in device pixels. See `TILE_WIDTH`.
 pub const TILE_HEIGHT: u32 = 16;
 
 #[derive(Clone, Copy)]
@@ -30,6 +39,9 @@ pub(crate) struct DrawTilingPathInfo {
     pub(crate) paint_id: PaintId,
     pub(crate) blend_mode: BlendMode,
     pub(crate) fill_rule: FillRule,
+    pub(crate) antialias: bool,
+    pub(crate) grid_fit: bool,
+    pub(crate) conservative: bool,
 }
 
 impl TilingPathInfo {
@@ -42,6 +54,27 @@ impl TilingPathInfo {
         }
     }
 
+    pub(crate) fn antialias(&self) -> bool {
+        match *self {
+            TilingPathInfo::Draw(ref draw_tiling_path_info) => draw_tiling_path_info.antialias,
+            TilingPathInfo::Clip => true,
+        }
+    }
+
+    pub(crate) fn grid_fit(&self) -> bool {
+        match *self {
+            TilingPathInfo::Draw(ref draw_tiling_path_info) => draw_tiling_path_info.grid_fit,
+            TilingPathInfo::Clip => false,
+        }
+    }
+
+    pub(crate) fn conservative(&self) -> bool {
+        match *self {
+            TilingPathInfo::Draw(ref draw_tiling_path_info) => draw_tiling_path_info.conservative,
+            TilingPathInfo::Clip => false,
+        }
+    }
+
     pub(crate) fn to_ctrl(&self) -> u8 {
         let mut ctrl = 0;
         match *self {
@@ -65,7 +98,36 @@ pub fn round_rect_out_to_tile_bounds(rect: RectF) -> RectI {
     (rect * vec2f(1.0 / TILE_WIDTH as f32, 1.0 / TILE_HEIGHT as f32)).round_out().to_i32()
 }
 
+/// Returns the Morton (Z-order) code of the given tile coordinates, which interleaves the bits of
+/// the X and Y coordinates, X in the low bit.
+///
+/// Sorting tiles by this code visits them in nested 2×2 squares, so tiles that are close together
+/// on screen stay close together in the sort. Coordinates are biased so that the code orders
+/// negative coordinates before positive ones, the same as comparing them as numbers would.
+/// Coordinates outside the range of `i16`, which tiles can't have, are clamped.
+pub fn morton_code(tile_coords: Vector2I) -> u32 {
+    return spread_bits(bias(tile_coords.x())) | (spread_bits(bias(tile_coords.y())) << 1);
+
+    fn bias(coord: i32) -> u32 {
+        (coord.clamp(i16::MIN as i32, i16::MAX as i32) - i16::MIN as i32) as u32
+    }
+
+    // Moves bit `i` of a 16-bit value to bit `2i`.
+    fn spread_bits(mut value: u32) -> u32 {
+        value = (value | (value << 8)) & 0x00ff_00ff;
+        value = (value | (value << 4)) & 0x0f0f_0f0f;
+        value = (value | (value << 2)) & 0x3333_3333;
+        (value | (value << 1)) & 0x5555_5555
+    }
+}
+
 impl TileObjectPrimitive {
     #[inline]
     pub fn is_solid(&self) -> bool { !self.alpha_tile_id.is_valid() }
+
+    /// Returns the Morton code of this tile's coordinates. See `morton_code()`.
+    #[inline]
+    pub fn morton_code(&self) -> u32 {
+        morton_code(Vector2I::new(self.tile_x as i32, self.tile_y as i32))
+    }
 }
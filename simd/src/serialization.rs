@@ -0,0 +1,77 @@
+// pathfinder/simd/src/serialization.rs
+//
+// Copyright © 2020 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `serde` support for the vector types of whichever backend is in use.
+//!
+//! Vectors are serialized as fixed-size arrays of their lanes, so the representation is the same
+//! on every backend and carries no length prefix.
+
+use crate::default::{F32x2, F32x4, I32x2, I32x4};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Two 32-bit floats
+
+impl Serialize for F32x2 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        [self.x(), self.y()].serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for F32x2 {
+    fn deserialize<D>(deserializer: D) -> Result<F32x2, D::Error> where D: Deserializer<'de> {
+        let [x, y] = <[f32; 2]>::deserialize(deserializer)?;
+        Ok(F32x2::new(x, y))
+    }
+}
+
+// Four 32-bit floats
+
+impl Serialize for F32x4 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        [self.x(), self.y(), self.z(), self.w()].serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for F32x4 {
+    fn deserialize<D>(deserializer: D) -> Result<F32x4, D::Error> where D: Deserializer<'de> {
+        let [x, y, z, w] = <[f32; 4]>::deserialize(deserializer)?;
+        Ok(F32x4::new(x, y, z, w))
+    }
+}
+
+// Two 32-bit signed integers
+
+impl Serialize for I32x2 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        [self.x(), self.y()].serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for I32x2 {
+    fn deserialize<D>(deserializer: D) -> Result<I32x2, D::Error> where D: Deserializer<'de> {
+        let [x, y] = <[i32; 2]>::deserialize(deserializer)?;
+        Ok(I32x2::new(x, y))
+    }
+}
+
+// Four 32-bit signed integers
+
+impl Serialize for I32x4 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        [self.x(), self.y(), self.z(), self.w()].serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for I32x4 {
+    fn deserialize<D>(deserializer: D) -> Result<I32x4, D::Error> where D: Deserializer<'de> {
+        let [x, y, z, w] = <[i32; 4]>::deserialize(deserializer)?;
+        Ok(I32x4::new(x, y, z, w))
+    }
+}